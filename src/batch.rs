@@ -0,0 +1,347 @@
+use crate::message_sender::{
+    Operation, Request, MAX_PDU_DATA, MAX_READ_QUANTITY,
+};
+use crate::modbus::{CrcConfig, RequestFrame};
+use crate::response::Response;
+use crate::transport::PortConfig;
+
+/// Function code and register address of a register read
+fn read_target(op: &Operation) -> Option<(u8, u16)> {
+    match op.req {
+        Request::ReadSingle(addr) => Some((0x03, addr)),
+        Request::ReadSingleRO(addr) => Some((0x04, addr)),
+        Request::WriteSingle(_, _, _)
+        | Request::CommEventCounter
+        | Request::ReadExceptionStatus
+        | Request::Loopback(_)
+        | Request::WriteCoil(_, _)
+        | Request::ReadFileRecord(_, _, _)
+        | Request::ReadDeviceId(_)
+        | Request::WriteMultiple(_, _)
+        | Request::Custom(_, _, _) => None,
+    }
+}
+
+/// Put every operation in a group of its own
+pub fn no_batching(ops: Vec<Operation>) -> Vec<Vec<Operation>> {
+    ops.into_iter().map(|op| vec![op]).collect()
+}
+
+/// First register and register count a group reads, the group must only
+/// hold reads. An operation may start anywhere in the registers before it,
+/// so the span is not simply the sum of the quantities
+fn group_span(group: &[Operation]) -> (u16, usize) {
+    // unwrap because only register reads are grouped
+    let start = read_target(&group[0]).unwrap().1;
+    let end = group
+        .iter()
+        .map(|op| {
+            read_target(op).unwrap().1 as usize + op.read_quantity() as usize
+        })
+        .max()
+        .unwrap_or(start as usize);
+
+    (start, end - start as usize)
+}
+
+/// Length of a successful reply to a group, for timeouts derived from it.
+/// Replies that only tell their length once they arrive are taken as the
+/// longest RTU frame
+pub fn group_reply_len(group: &[Operation]) -> usize {
+    match group {
+        [op] => match op.req {
+            Request::ReadDeviceId(_) | Request::Custom(_, _, _) => {
+                // address, function code and CRC around the data
+                MAX_PDU_DATA + 4
+            }
+            _ => Response::new(op.clone(), vec![]).expected_len(),
+        },
+        // address, function code, byte count and CRC around the registers
+        _ => 5 + 2 * group_span(group).1,
+    }
+}
+
+/// Group runs of reads that use the same function code on contiguous or
+/// overlapping addresses, in list order, so each group can be read with one
+/// request. A read joins a group if it starts at or after the first register
+/// of the group and at most right after its last one, multi-register reads
+/// included.
+/// Reads are never grouped across a gap in addresses, or with reads that are
+/// polled a different number of times
+pub fn coalesce_reads(ops: Vec<Operation>) -> Vec<Vec<Operation>> {
+    let mut groups: Vec<Vec<Operation>> = vec![];
+
+    for op in ops {
+        let joins_last = groups.last().map_or(false, |group| {
+            let first = read_target(&group[0]);
+            match (first, read_target(&op)) {
+                (Some((first_func, _)), Some((func, addr))) => {
+                    let (start, len) = group_span(group);
+                    let end = start as usize + len;
+                    let op_end = addr as usize + op.read_quantity() as usize;
+
+                    group[0].read_once == op.read_once
+                        && func == first_func
+                        && addr >= start
+                        && addr as usize <= end
+                        && op_end.max(end) - start as usize
+                            <= MAX_READ_QUANTITY as usize
+                }
+                _ => false,
+            }
+        });
+
+        if joins_last {
+            // unwrap because joins_last is only true if a group exists
+            groups.last_mut().unwrap().push(op);
+        } else {
+            groups.push(vec![op]);
+        }
+    }
+
+    groups
+}
+
+/// Repeat every group once per device address, interleaving the devices so
+/// each group is read from all of them before moving to the next group
+pub fn rotate_devices(
+    groups: Vec<Vec<Operation>>,
+    device_addrs: &[u8],
+) -> Vec<Vec<Operation>> {
+    groups
+        .iter()
+        .flat_map(|group| {
+            device_addrs.iter().map(move |&addr| {
+                group
+                    .iter()
+                    .cloned()
+                    .map(|mut op| {
+                        op.device_addr = Some(addr);
+                        op
+                    })
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// Request frame for a group, a group of one is sent as its own operation
+pub fn group_frame(group: &[Operation], port_conf: &PortConfig) -> Vec<u8> {
+    match group {
+        [op] => op.to_modbus_bytes(port_conf),
+        // unwrap because only register reads are grouped
+        _ => port_conf.crc.reencode(
+            RequestFrame {
+                value: group_span(group).1 as u16,
+                ..group[0].req.to_frame(group[0].target_addr(port_conf)).unwrap()
+            }
+            .to_bytes()
+            .to_vec(),
+        ),
+    }
+}
+
+/// Split the response to a group into one response per operation, holding
+/// the registers at the operation's address, each with its own `crc`.
+/// Operations are matched to registers by address, so overlapping operations
+/// get the same registers. If the group response is malformed every operation
+/// gets the raw bytes so the failure shows up on all of them, a bad CRC is only
+/// ignored with `skip_crc`
+pub fn split_response(
+    group: &[Operation],
+    bytes: Vec<u8>,
+    skip_crc: bool,
+    crc: CrcConfig,
+) -> Vec<Response> {
+    if let [op] = group {
+        return vec![Response::new(op.clone(), bytes)];
+    }
+
+    let (start, quantity) = group_span(group);
+    let data_len = quantity * 2;
+    let valid = bytes.len() == data_len + 3 + crc.trailer_len()
+        && bytes[2] as usize == data_len
+        && (skip_crc || crc.check(&bytes) != Some(false));
+
+    if !valid {
+        return group
+            .iter()
+            .map(|op| Response::new(op.clone(), bytes.clone()))
+            .collect();
+    }
+
+    group
+        .iter()
+        .map(|op| {
+            // unwrap because only register reads are grouped
+            let offset = 3 + 2 * (read_target(op).unwrap().1 - start) as usize;
+            let len = 2 * op.read_quantity() as usize;
+
+            let mut frame = vec![bytes[0], bytes[1], len as u8];
+            frame.extend_from_slice(&bytes[offset..offset + len]);
+            crc.append(&mut frame);
+
+            Response::new(op.clone(), frame)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_sender::DataType;
+
+    fn read(addr: u16) -> Operation {
+        Operation::new(format!("r{}", addr), Request::ReadSingle(addr))
+    }
+
+    /// Reply of device 1 to a holding register read of `registers`
+    fn reply(registers: &[u16]) -> Vec<u8> {
+        let mut bytes = vec![1, 0x03, 2 * registers.len() as u8];
+        for register in registers {
+            bytes.extend(register.to_be_bytes());
+        }
+        CrcConfig::default().append(&mut bytes);
+        bytes
+    }
+
+    /// Data bytes of a split response
+    fn data(response: &Response) -> &[u8] {
+        let bytes = response.bytes();
+        &bytes[3..bytes.len() - 2]
+    }
+
+    /// Register address of every op, one list per group
+    fn addrs(groups: &[Vec<Operation>]) -> Vec<Vec<u16>> {
+        groups
+            .iter()
+            .map(|group| {
+                group.iter().map(|op| read_target(op).unwrap().1).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn adjacent_reads_are_grouped() {
+        let groups = coalesce_reads(vec![read(10), read(11), read(12)]);
+        assert_eq!(addrs(&groups), [vec![10, 11, 12]]);
+        assert_eq!(group_span(&groups[0]), (10, 3));
+    }
+
+    #[test]
+    fn overlapping_reads_are_grouped() {
+        let wide = Operation { data_type: DataType::U64, ..read(10) };
+        let groups = coalesce_reads(vec![wide, read(12), read(14)]);
+        assert_eq!(addrs(&groups), [vec![10, 12, 14]]);
+        assert_eq!(group_span(&groups[0]), (10, 5));
+    }
+
+    #[test]
+    fn gaps_split_groups() {
+        let groups = coalesce_reads(vec![read(10), read(12), read(9)]);
+        assert_eq!(addrs(&groups), [vec![10], vec![12], vec![9]]);
+    }
+
+    #[test]
+    fn function_codes_are_not_mixed() {
+        let input = Operation::new("i".to_string(), Request::ReadSingleRO(11));
+        let groups = coalesce_reads(vec![read(10), input, read(12)]);
+        assert_eq!(addrs(&groups), [vec![10], vec![11], vec![12]]);
+    }
+
+    #[test]
+    fn read_once_is_not_mixed() {
+        let once = Operation { read_once: true, ..read(11) };
+        let groups = coalesce_reads(vec![read(10), once, read(12)]);
+        assert_eq!(addrs(&groups), [vec![10], vec![11], vec![12]]);
+    }
+
+    #[test]
+    fn groups_are_capped_at_the_read_quantity() {
+        let ops = (0..MAX_READ_QUANTITY + 1).map(read).collect();
+        let groups = coalesce_reads(ops);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(group_span(&groups[0]), (0, MAX_READ_QUANTITY as usize));
+        assert_eq!(group_span(&groups[1]), (MAX_READ_QUANTITY, 1));
+    }
+
+    #[test]
+    fn writes_are_never_grouped() {
+        let write =
+            Operation::new("w".to_string(), Request::WriteSingle(11, 0.0, 0));
+        let groups = coalesce_reads(vec![read(10), write, read(11)]);
+        assert_eq!(groups.len(), 3);
+    }
+
+    #[test]
+    fn multi_register_reads_are_split() {
+        let wide = Operation { data_type: DataType::U64, ..read(10) };
+        let group = vec![read(9), wide, read(14)];
+        let responses = split_response(
+            &group,
+            reply(&[9, 10, 11, 12, 13, 14]),
+            false,
+            CrcConfig::default(),
+        );
+
+        assert_eq!(data(&responses[0]), [0, 9]);
+        assert_eq!(data(&responses[1]), [0, 10, 0, 11, 0, 12, 0, 13]);
+        assert_eq!(data(&responses[2]), [0, 14]);
+        assert!(responses.iter().all(|r| r.frame_error().is_none()));
+    }
+
+    #[test]
+    fn ops_inside_a_group_get_their_own_registers() {
+        let wide = Operation { data_type: DataType::U64, ..read(10) };
+        let group = vec![wide, read(11), read(13)];
+        let responses = split_response(
+            &group,
+            reply(&[10, 11, 12, 13]),
+            false,
+            CrcConfig::default(),
+        );
+
+        assert_eq!(data(&responses[1]), [0, 11]);
+        assert_eq!(data(&responses[2]), [0, 13]);
+    }
+
+    #[test]
+    fn malformed_replies_go_to_every_op() {
+        let group = vec![read(10), read(11)];
+        let exception = {
+            let mut bytes = vec![1, 0x83, 0x02];
+            CrcConfig::default().append(&mut bytes);
+            bytes
+        };
+        let mut short = reply(&[10, 11]);
+        short.remove(4);
+
+        for bytes in [exception, short] {
+            let responses = split_response(
+                &group,
+                bytes.clone(),
+                false,
+                CrcConfig::default(),
+            );
+            assert_eq!(responses.len(), 2);
+            for response in responses {
+                assert_eq!(response.bytes(), bytes);
+                assert!(response.value().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn bad_crc_is_only_split_with_skip_crc() {
+        let group = vec![read(10), read(11)];
+        let mut bytes = reply(&[10, 11]);
+        *bytes.last_mut().unwrap() ^= 0xFF;
+
+        let checked =
+            split_response(&group, bytes.clone(), false, CrcConfig::default());
+        assert!(checked.iter().all(|r| r.bytes() == bytes));
+
+        let skipped = split_response(&group, bytes, true, CrcConfig::default());
+        assert_eq!(data(&skipped[1]), [0, 11]);
+    }
+}
@@ -4,18 +4,57 @@ use std::fmt::{Display, Formatter};
 pub enum ErrKind {
     MathOperationParseError,
     RequestParseError,
+    /// Continuous responses are keyed by name, so names must be unique
+    DuplicateOperationName,
     InvalidPortOption,
     MathOperationResultInOutOfRangeValue,
 
     FailedToOpenTargetPort,
     PortWriteFailed,
+    PortReadFailed,
 
     PortOpThreadNotPresent,
     PortOpDroppedChannelTxWithoutResponse,
+    /// The user stopped waiting for a one-shot response
+    RequestCanceled,
+    /// A register read back after a write doesn't hold the written value
+    WriteVerifyFailed,
 
     PortTypeUnequal,
 
+    InvalidFrame,
+    /// Nothing arrived before the read timed out
+    NoResponse,
+    /// Some bytes arrived, but fewer than a complete response
+    TruncatedResponse,
+    CrcCheckFailed,
+    /// The response came from another device than the one asked, e.g. a late
+    /// reply on a shared bus
+    DeviceAddressMismatch,
+
     AttemptToStartMultipleContinuousQuarry,
+    PortBusyScanning,
+    PortBusyMonitoring,
+
+    FailedToStartSimulator,
+
+    RegisterMapParseError,
+}
+
+impl ErrKind {
+    /// Whether the error comes from the user's input or configuration rather
+    /// than from the port or a device
+    pub fn is_input_error(self) -> bool {
+        matches!(
+            self,
+            ErrKind::MathOperationParseError
+                | ErrKind::RequestParseError
+                | ErrKind::DuplicateOperationName
+                | ErrKind::InvalidPortOption
+                | ErrKind::MathOperationResultInOutOfRangeValue
+                | ErrKind::RegisterMapParseError
+        )
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -34,6 +73,15 @@ impl Error {
     pub fn with_message(kind: ErrKind, message: String) -> Self {
         Self { kind, message }
     }
+
+    pub fn kind(&self) -> ErrKind {
+        self.kind
+    }
+
+    /// The custom message, without the error kind
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Display for Error {
@@ -0,0 +1,93 @@
+use iced::{
+    widget::{Checkbox, Row, TextInput},
+    Alignment, Command, Element, Length,
+};
+
+use serde::{Deserialize, Serialize};
+
+use string_to_num::ParseNum;
+
+use crate::error::{ErrKind, Error};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GatewayMessage {
+    SetEnabled(bool),
+    SetDeviceAddresses(String),
+}
+
+/// Poll several slaves behind one port, the continuous quarry reads every
+/// operation from each listed device address in turn
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GatewayView {
+    enabled: bool,
+    /// Device addresses separated by `,`
+    device_addrs: String,
+}
+
+impl GatewayView {
+    /// Device addresses to poll, `None` if gateway mode is off
+    pub fn device_addrs(&self) -> Result<Option<Vec<u8>>, Error> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let addrs = self
+            .device_addrs
+            .split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+            .map(|addr| {
+                addr.parse_num::<u8>().map_err(|_| {
+                    Error::with_message(
+                        ErrKind::InvalidPortOption,
+                        format!("\"{}\" is not a valid device address", addr),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if addrs.is_empty() {
+            return Err(Error::with_message(
+                ErrKind::InvalidPortOption,
+                "Gateway mode needs at least one device address".to_string(),
+            ));
+        }
+
+        Ok(Some(addrs))
+    }
+
+    pub fn update(
+        &mut self,
+        message: GatewayMessage,
+    ) -> Command<GatewayMessage> {
+        match message {
+            GatewayMessage::SetEnabled(enabled) => self.enabled = enabled,
+            GatewayMessage::SetDeviceAddresses(addrs) => {
+                self.device_addrs = addrs
+            }
+        }
+
+        Command::none()
+    }
+
+    pub fn view(&self) -> Element<GatewayMessage> {
+        Row::new()
+            .padding(5)
+            .align_items(Alignment::Center)
+            .push(Checkbox::new(
+                self.enabled,
+                "Gateway Mode",
+                GatewayMessage::SetEnabled,
+            ))
+            .push(
+                TextInput::new(
+                    "Device Addresses, e.g. 1, 2, 5",
+                    &self.device_addrs,
+                    GatewayMessage::SetDeviceAddresses,
+                )
+                .width(Length::Fill)
+                .padding([0, 2]),
+            )
+            .into()
+    }
+}
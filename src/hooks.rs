@@ -0,0 +1,70 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::error::Error;
+use crate::response::Response;
+
+/// Where a response came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResponseSource {
+    OneShot,
+    Continuous,
+}
+
+/// A response or error as it arrived, before any view shows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseEvent {
+    pub source: ResponseSource,
+    pub result: Result<Response, Error>,
+}
+
+/// Stream of every response to whoever subscribed, so readings can be acted
+/// on, e.g. forwarded elsewhere, without going through the views
+#[derive(Debug, Default)]
+pub struct ResponseHooks {
+    listeners: Vec<Sender<ResponseEvent>>,
+}
+
+impl ResponseHooks {
+    /// Receive every event emitted from now on, dropping the receiver
+    /// unsubscribes
+    pub fn subscribe(&mut self) -> Receiver<ResponseEvent> {
+        let (tx, rx) = channel();
+        self.listeners.push(tx);
+        rx
+    }
+
+    /// Send `event` to every subscriber, forgetting the ones that are gone
+    pub fn emit(&mut self, event: ResponseEvent) {
+        self.listeners.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    pub fn has_listeners(&self) -> bool {
+        !self.listeners.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrKind;
+
+    #[test]
+    fn emit_to_subscribers() {
+        let mut hooks = ResponseHooks::default();
+        let first = hooks.subscribe();
+        let second = hooks.subscribe();
+
+        let event = ResponseEvent {
+            source: ResponseSource::OneShot,
+            result: Err(Error::new(ErrKind::RequestCanceled)),
+        };
+        hooks.emit(event.clone());
+        assert_eq!(first.recv(), Ok(event.clone()));
+        assert_eq!(second.recv(), Ok(event.clone()));
+
+        drop(first);
+        hooks.emit(event.clone());
+        assert_eq!(second.recv(), Ok(event));
+        assert_eq!(hooks.listeners.len(), 1);
+    }
+}
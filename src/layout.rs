@@ -0,0 +1,243 @@
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serialization of the saved layout
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LayoutFormat {
+    #[default]
+    Ron,
+    Json,
+}
+
+impl LayoutFormat {
+    fn parse(format: &str) -> Result<Self, String> {
+        match format.to_ascii_lowercase().as_str() {
+            "ron" => Ok(LayoutFormat::Ron),
+            "json" => Ok(LayoutFormat::Json),
+            _ => Err(format!("Unknown layout format \"{}\"", format)),
+        }
+    }
+
+    /// Format of a file by its extension, RON unless it ends in `.json`
+    fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => LayoutFormat::Json,
+            _ => LayoutFormat::Ron,
+        }
+    }
+}
+
+/// The file the layout is loaded from on launch and saved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutFile {
+    pub path: String,
+    pub format: LayoutFormat,
+}
+
+impl Default for LayoutFile {
+    fn default() -> Self {
+        Self { path: "layout.ron".to_string(), format: LayoutFormat::Ron }
+    }
+}
+
+pub const USAGE: &str =
+    "Usage: modbus-tester [--layout PATH] [--format ron|json]
+
+  --layout PATH   layout file to load and save, layout.ron by default
+  --format FMT    ron or json, inferred from the layout file extension if not
+                  given";
+
+impl LayoutFile {
+    /// Parse the command line arguments, without the program name. Without
+    /// `--layout` the file is `layout.ron` or `layout.json` by format
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Self, String> {
+        let mut path = None;
+        let mut format = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value =
+                || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--layout" => path = Some(value()?),
+                "--format" => format = Some(LayoutFormat::parse(&value()?)?),
+                _ => return Err(format!("Unknown argument \"{}\"", arg)),
+            }
+        }
+
+        Ok(match (path, format) {
+            (Some(path), Some(format)) => Self { path, format },
+            (Some(path), None) => {
+                let format = LayoutFormat::from_path(&path);
+                Self { path, format }
+            }
+            (None, Some(LayoutFormat::Json)) => Self {
+                path: "layout.json".to_string(),
+                format: LayoutFormat::Json,
+            },
+            (None, _) => Self::default(),
+        })
+    }
+
+    /// Read the layout, `None` if there is no layout file yet. A file that
+    /// can't be read or doesn't parse is an error
+    pub fn load<T: DeserializeOwned>(&self) -> Result<Option<T>, String> {
+        let string = match std::fs::read_to_string(&self.path) {
+            Ok(string) => string,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+
+        match self.format {
+            LayoutFormat::Ron => {
+                ron::from_str(&string).map_err(|e| e.to_string())
+            }
+            LayoutFormat::Json => {
+                serde_json::from_str(&string).map_err(|e| e.to_string())
+            }
+        }
+        .map(Some)
+    }
+
+    /// Copy the layout file next to itself with a `.bak` extension, so saving
+    /// over a layout that failed to load doesn't lose it. Returns the path of
+    /// the copy
+    pub fn backup(&self) -> Result<String, String> {
+        let backup = format!("{}.bak", self.path);
+        std::fs::copy(&self.path, &backup).map_err(|e| e.to_string())?;
+        Ok(backup)
+    }
+
+    pub fn save<T: Serialize>(&self, layout: &T) -> Result<(), String> {
+        let string = match self.format {
+            LayoutFormat::Ron => {
+                ron::to_string(layout).map_err(|e| e.to_string())?
+            }
+            LayoutFormat::Json => serde_json::to_string_pretty(layout)
+                .map_err(|e| e.to_string())?,
+        };
+
+        std::fs::write(&self.path, string).map_err(|e| e.to_string())
+    }
+
+    /// File the user's op templates are kept in, next to the layout and in
+    /// its format
+    pub fn templates_file(&self) -> LayoutFile {
+        let name = match self.format {
+            LayoutFormat::Ron => "templates.ron",
+            LayoutFormat::Json => "templates.json",
+        };
+
+        LayoutFile {
+            path: self.folder().join(name).display().to_string(),
+            format: self.format,
+        }
+    }
+
+    /// Absolute directory holding the layout file
+    pub fn folder(&self) -> PathBuf {
+        let path = Path::new(&self.path);
+        let folder = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        folder.canonicalize().unwrap_or_else(|_| folder.to_path_buf())
+    }
+}
+
+/// Open `folder` in the file manager of the platform
+pub fn open_folder(folder: &Path) -> Result<(), String> {
+    let opener = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    // explorer exits with 1 even when it opened the folder, so only failing
+    // to launch the opener is an error
+    std::process::Command::new(opener)
+        .arg(folder)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("{} failed: {}", opener, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<LayoutFile, String> {
+        LayoutFile::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn format_from_flag_or_extension() {
+        assert_eq!(args(&[]), Ok(LayoutFile::default()));
+        assert_eq!(
+            args(&["--format", "json"]),
+            Ok(LayoutFile {
+                path: "layout.json".to_string(),
+                format: LayoutFormat::Json
+            })
+        );
+        assert_eq!(
+            args(&["--layout", "bench.JSON"]).map(|file| file.format),
+            Ok(LayoutFormat::Json)
+        );
+        // an explicit format wins over the extension
+        assert_eq!(
+            args(&["--layout", "bench.json", "--format", "ron"])
+                .map(|file| file.format),
+            Ok(LayoutFormat::Ron)
+        );
+    }
+
+    #[test]
+    fn malformed_layout_is_an_error() {
+        let dir = std::env::temp_dir();
+        let file = LayoutFile {
+            path: dir.join("modbus-tester-bad.ron").display().to_string(),
+            format: LayoutFormat::Ron,
+        };
+
+        std::fs::write(&file.path, "(ops: [").unwrap();
+        assert!(file.load::<Vec<u16>>().is_err());
+        let backup = file.backup().unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "(ops: [");
+
+        std::fs::remove_file(&file.path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+        assert_eq!(file.load::<Vec<u16>>(), Ok(None));
+    }
+
+    #[test]
+    fn templates_next_to_layout() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let file = LayoutFile {
+            path: dir.join("bench.json").display().to_string(),
+            format: LayoutFormat::Json,
+        };
+
+        let templates = file.templates_file();
+        assert_eq!(
+            templates.path,
+            dir.join("templates.json").display().to_string()
+        );
+        assert_eq!(templates.format, LayoutFormat::Json);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert!(args(&["--format", "yaml"]).is_err());
+        assert!(args(&["--layout"]).is_err());
+        assert!(args(&["layout.ron"]).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+//! Modbus RTU encoding and decoding used by the tester, usable without the GUI
+//!
+//! Build an [`Operation`](message_sender::Operation), encode it with
+//! [`Operation::to_modbus_bytes`](message_sender::Operation::to_modbus_bytes)
+//! and decode what the device sent back with
+//! [`decode_frame`](response::decode_frame). Every response the tester
+//! receives can be followed through [`ResponseHooks`](hooks::ResponseHooks)
+
+pub mod batch;
+pub mod error;
+pub mod hooks;
+pub mod message_sender;
+pub mod modbus;
+pub mod response;
+pub mod transport;
@@ -1,37 +1,113 @@
 extern crate core;
 
-mod error;
+use modbus_tester::{
+    batch, error, hooks, message_sender, modbus, response, transport,
+};
+
+mod gateway;
+mod layout;
 mod r#macro;
-mod message_sender;
+mod metrics;
+mod monitor;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod one_shot_queue;
 mod ops;
+mod palette;
 mod port_op;
+mod register_map;
 mod response_display;
+mod rules;
+mod scan;
+mod simulator;
+mod snapshot;
+mod style;
+mod templates;
+mod wide_csv;
 
 use std::sync::{
-    mpsc::{channel, Receiver, Sender},
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{channel, Receiver, RecvError, Sender},
     Arc, Mutex,
 };
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use iced::{
     alignment::Vertical,
+    event::{self, Event},
+    keyboard::{self, KeyCode, Modifiers},
     widget::{
-        scrollable, Button, Column, Container, PickList, Row, Space, TextInput,
+        scrollable, Button, Checkbox, Column, Container, PickList, Row, Space,
+        Text, TextInput,
     },
-    Application, Command, Element, Length, Settings,
+    window, Alignment, Application, Color, Command, Element, Length, Settings,
+    Subscription,
 };
 
 use serde::{Deserialize, Serialize};
 
+use string_to_num::ParseNum;
+
 use crate::error::*;
+use crate::gateway::*;
+use crate::hooks::{ResponseEvent, ResponseHooks, ResponseSource};
+use crate::layout::{open_folder, LayoutFile, USAGE};
+use crate::message_sender::{mask_input, InputMask, Request};
+use crate::metrics::Metrics;
+use crate::modbus::{
+    CrcAlgorithm, CrcByteOrder, CrcConfig, CRC_ALGORITHMS, CRC_BYTE_ORDERS,
+};
+use crate::monitor::*;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{MqttMessage, MqttView};
+use crate::one_shot_queue::OneShotQueue;
 use crate::ops::*;
+use crate::palette::CommandPalette;
 use crate::port_op::*;
+use crate::register_map::*;
+use crate::response::Response;
 use crate::response_display::*;
+use crate::rules::*;
+use crate::scan::*;
+use crate::simulator::*;
+use crate::snapshot::Snapshot;
+use crate::style::{TextSize, UiScale, UI_SCALES};
+use crate::templates::TemplateLibrary;
+use crate::transport::PortConfig;
+use crate::wide_csv::WideCsv;
+
+/// Where the wide CSV of the continuous responses is written
+const WIDE_CSV_PATH: &str = "continuous_wide.csv";
+
+/// Recently used ports remembered in the layout
+const MAX_RECENT_PORTS: usize = 5;
+
+/// How long closing the window waits for the port thread to release the port
+const EXIT_JOIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Schema version of the saved layout, bumped when a layout saved before
+/// would load with a different meaning, see `App::migrate`
+const LAYOUT_VERSION: u32 = 2;
+
+/// Layouts saved before the version was saved are version 1
+fn first_layout_version() -> u32 {
+    1
+}
 
 /**
 Entry point
 */
 fn main() -> iced::Result {
-    let mut setting = Settings::with_flags(());
+    let layout_file = match LayoutFile::from_args(std::env::args().skip(1)) {
+        Ok(layout_file) => layout_file,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut setting = Settings::with_flags(layout_file);
     setting.window = iced::window::Settings {
         size: (1280, 720),
         position: Default::default(),
@@ -46,6 +122,8 @@ fn main() -> iced::Result {
     };
 
     setting.default_font = Some(include_bytes!("../JetBrainsMono-Regular.ttf"));
+    // the port thread is shut down before the window closes, see `Message::Exit`
+    setting.exit_on_close_request = false;
 
     App::run(setting)
 }
@@ -57,108 +135,491 @@ enum Message {
     OneShotViewList(OpViewListMessage),
     ContinuousViewList(OpViewListMessage),
     OneShotDisplay(ResponseViewMessage),
+    ContinuousDisplay(KeyedResponseViewMessage),
+    Simulator(SimulatorMessage),
+    Gateway(GatewayMessage),
+    #[cfg(feature = "mqtt")]
+    Mqtt(MqttMessage),
 
     SaveLayout,
+    OpenLayoutFolder,
+    ExportSnapshot,
     RefreshAvailablePorts,
     SetComPort(String),
     SetParity(Parity),
     SetStopBits(StopBits),
+    SetDataBits(DataBits),
+    SetFraming(Framing),
     SetBaud(String),
     SetDeviceAddress(String),
+    SetReadDelay(String),
+    SetBatchReads(bool),
+    SetResumePolling(bool),
+    SetPauseOnBlur(bool),
+    SetErrorPolicy(ErrorPolicy),
+    SetSkipCrc(bool),
+    SetCrcAlgorithm(CrcAlgorithm),
+    SetCrcByteOrder(CrcByteOrder),
+    SetNineBit(bool),
+    SetT35Framing(bool),
+    SetAutoTimeout(bool),
+    SetCompactView(bool),
+    SetWideCsv(bool),
+    SetUiScale(UiScale),
+    SetPlcAddressing(bool),
+    ClearInputErrors,
 
     OneShotQuarry(OpView),
     OneShotResponse(Result<Response, Error>),
+    CancelOneShot,
+    VerifyWriteResult(Result<WriteVerification, Error>),
 
     ContinuousQuarryToggle(OpViewList),
+    /// Stop the continuous quarry, then start it over with cleared responses
+    /// and stats
+    ContinuousQuarryRestart(OpViewList),
     ContinuousQuarryStartResult(Result<(), Error>),
     ContinuousQuarryResult(Result<Vec<Result<Response, Error>>, Error>),
+    MetricsTick(Instant),
+    TestConnection,
+    TestConnectionResult(Result<String, Error>),
+    SetTestEcho(bool),
+    /// The window gained (`true`) or lost focus
+    WindowFocus(bool),
+    Disconnect,
+    /// The window is closing, shut the port thread down first
+    Exit,
+
+    ScanDisplay(ScanViewMessage),
+    ScanToggle,
+    DetectBaud,
+    ScanStartResult(Result<(), Error>),
+    ScanResult(Result<Vec<Result<ScanEvent, Error>>, Error>),
+
+    MonitorDisplay(MonitorViewMessage),
+    MonitorToggle,
+    MonitorStartResult(Result<(), Error>),
+    MonitorResult(Result<Vec<Result<MonitorFrame, Error>>, Error>),
+
+    /// A key press no widget handled
+    KeyPressed(KeyCode, Modifiers),
+
+    OpenPalette,
+    ClosePalette,
+    SetPaletteQuery(String),
+    /// Close the palette and handle the chosen action
+    RunPaletteEntry(Box<Message>),
 }
 
 #[derive(Serialize, Deserialize, Default)]
 struct App {
+    /// Schema version the layout was saved with
+    #[serde(default = "first_layout_version")]
+    version: u32,
+
     one_shot_ops: OpViewList,
     continuous_ops: OpViewList,
 
+    /// Coalesce contiguous continuous reads into block reads
+    #[serde(default)]
+    batch_reads: bool,
+
+    #[serde(default)]
+    rules: RuleList,
+
+    #[serde(default)]
+    simulator: SimulatorView,
+
+    #[serde(default)]
+    gateway: GatewayView,
+
+    /// Publishes continuous values to an MQTT broker
+    #[cfg(feature = "mqtt")]
+    #[serde(default)]
+    mqtt: MqttView,
+
+    /// Where the layout was loaded from and is saved to
     #[serde(skip)]
-    available_ports: Vec<String>,
+    layout_file: LayoutFile,
+    /// Built-in op templates and the user's, saved next to the layout
+    #[serde(skip)]
+    templates: TemplateLibrary,
 
     #[serde(skip)]
+    available_ports: Vec<String>,
+    /// Ports picked before, most recent first, kept while they're unplugged
+    #[serde(default)]
+    recent_ports: Vec<String>,
+
+    /// Saved so polling can resume on the same port
+    #[serde(default)]
     port_option: PortOption,
+    /// Whether bursts, scans and polling stop at their first error
+    #[serde(default)]
+    error_policy: ErrorPolicy,
+    /// Test Connection sends a loopback test, not only opens the port
+    #[serde(default)]
+    test_echo: bool,
+    /// Outcome of the last Test Connection, shown under the top bar rather
+    /// than in the responses
+    #[serde(skip)]
+    connection_status: Option<Result<String, Error>>,
+    #[serde(skip)]
+    testing_connection: bool,
+
+    /// Op addresses are entered PLC-style, e.g. 40001 for holding register 0
+    #[serde(default)]
+    plc_addressing: bool,
+
+    /// Start the continuous quarry on launch if it was running when the
+    /// layout was saved
+    #[serde(default)]
+    resume_polling: bool,
+    /// Whether the continuous quarry was running when the layout was saved
+    #[serde(default)]
+    polling_active: bool,
+    /// Hold the continuous quarry while the window is unfocused, for shared
+    /// buses that shouldn't be polled unattended
+    #[serde(default)]
+    pause_on_blur: bool,
+    /// The continuous quarry is held because the window lost focus
+    #[serde(skip)]
+    blur_paused: bool,
+    /// Show responses as op names and values only
+    #[serde(default)]
+    compact_view: bool,
+    /// Log the continuous responses as a CSV with a column per op
+    #[serde(default)]
+    wide_csv: bool,
+    #[serde(skip)]
+    wide_csv_log: Option<WideCsv>,
+    /// Scale of the whole UI, for high-DPI and distant screens
+    #[serde(default)]
+    ui_scale: UiScale,
+
+    /// The continuous op list was edited last, keyboard shortcuts apply to
+    /// the list edited last
+    #[serde(skip)]
+    continuous_ops_active: bool,
+
+    #[serde(skip)]
+    palette: CommandPalette,
 
     #[serde(skip)]
     responses: ResponseView,
+    /// Input and configuration errors, kept apart from device responses
+    #[serde(skip)]
+    input_errors: Vec<Error>,
     #[serde(skip)]
     continuous_responses: KeyedResponseView,
+    #[serde(skip)]
+    metrics: Metrics,
+
+    /// Every response is sent to its subscribers before the views get it
+    #[serde(skip)]
+    hooks: ResponseHooks,
 
     #[serde(skip)]
     port_thread_sender: Option<Sender<OpMessage>>,
+    #[serde(skip)]
+    port_thread: Option<JoinHandle<Result<(), Box<RecvError>>>>,
+    #[serde(skip)]
+    exiting: bool,
+
+    /// Bumped to cancel the one-shot request in progress
+    #[serde(skip)]
+    one_shot_cancel: Arc<AtomicUsize>,
+    #[serde(skip)]
+    one_shot_queue: OneShotQueue,
 
+    /// Port config the continuous quarry was started with
+    #[serde(skip)]
+    continuous_port_conf: Option<PortConfig>,
+    /// Requests of the continuous quarry that succeeded and failed
+    #[serde(skip)]
+    continuous_tally: (usize, usize),
     #[serde(skip)]
     #[allow(clippy::type_complexity)]
     continuous_quarry_channel:
         Option<Arc<Mutex<Receiver<Result<Response, Error>>>>>,
+
+    #[serde(skip)]
+    scan_view: ScanView,
+    #[serde(skip)]
+    #[allow(clippy::type_complexity)]
+    scan_channel: Option<Arc<Mutex<Receiver<Result<ScanEvent, Error>>>>>,
+
+    #[serde(skip)]
+    monitor_view: MonitorView,
+    #[serde(skip)]
+    #[allow(clippy::type_complexity)]
+    monitor_channel: Option<Arc<Mutex<Receiver<Result<MonitorFrame, Error>>>>>,
 }
 
 impl Application for App {
     type Executor = iced::executor::Default;
     type Message = Message;
     type Theme = iced::Theme;
-    type Flags = ();
+    type Flags = LayoutFile;
 
-    fn new(_flags: Self::Flags) -> (Self, Command<Self::Message>) {
-        let mut app = match std::fs::read_to_string("layout.ron") {
-            Ok(string) => {
-                ron::from_str::<App>(&string).unwrap_or_else(|_| App::default())
+    fn new(layout_file: Self::Flags) -> (Self, Command<Self::Message>) {
+        // a layout that doesn't load is backed up before a save overwrites it
+        let (mut app, load_error) = match layout_file.load::<App>() {
+            Ok(app) => (app.unwrap_or_default(), None),
+            Err(e) => {
+                let log = match layout_file.backup() {
+                    Ok(backup) => format!(
+                        "Failed to load layout from {}: {}. Started with the \
+                        default layout, the file was backed up to {}",
+                        layout_file.path, e, backup
+                    ),
+                    Err(backup_err) => format!(
+                        "Failed to load layout from {}: {}. Started with the \
+                        default layout, backing the file up failed too: {}",
+                        layout_file.path, e, backup_err
+                    ),
+                };
+                (App::default(), Some(log))
             }
-            Err(_) => App::default(),
         };
+        app.layout_file = layout_file;
+        if let Some(log) = load_error {
+            app.responses.update(ResponseViewMessage::AddLog(log));
+        }
+        let (templates, load_error) =
+            TemplateLibrary::load(app.layout_file.templates_file());
+        app.templates = templates;
+        if let Some(log) = load_error {
+            app.responses.update(ResponseViewMessage::AddLog(log));
+        }
+        app.set_template_names();
+        if app.version > LAYOUT_VERSION {
+            app.responses.update(ResponseViewMessage::AddLog(format!(
+                "Layout {} was saved by a newer version, some settings may \
+                not have loaded",
+                app.layout_file.path
+            )));
+        }
+        app.migrate();
+        #[cfg(feature = "mqtt")]
+        app.mqtt.resume(&mut app.hooks);
 
-        app.available_ports = serialport::available_ports()
-            .unwrap()
-            .into_iter()
-            .map(|port| port.port_name)
-            .collect::<Vec<_>>();
+        // validates every op as well
+        app.one_shot_ops.set_plc_addressing(app.plc_addressing);
+        app.continuous_ops.set_plc_addressing(app.plc_addressing);
+
+        app.responses.set_compact(app.compact_view);
+        app.continuous_responses.set_compact(app.compact_view);
+
+        app.set_preview_port();
+        app.one_shot_ops.set_input_id("one-shot");
+        app.continuous_ops.set_input_id("continuous");
+
+        app.available_ports = app.list_ports();
 
         let (tx, rx) = channel();
 
-        std::thread::spawn(move || port_op_thread(rx));
+        app.port_thread = Some(std::thread::spawn(move || port_op_thread(rx)));
 
         app.port_thread_sender = Some(tx);
 
-        (app, Command::none())
+        if !app.resume_polling || !app.polling_active {
+            return (app, Command::none());
+        }
+
+        // never poll a port other than the saved one
+        let port_available =
+            app.port_option.port_name.as_ref().map_or(false, |port_name| {
+                app.available_ports.iter().any(|name| name == port_name)
+            });
+        let command = if port_available {
+            let op_list = app.continuous_ops.clone();
+            app.update(Message::ContinuousQuarryToggle(op_list))
+        } else {
+            app.responses.update(ResponseViewMessage::AddLog(format!(
+                "Not resuming polling, port {} is unavailable",
+                app.port_option.port_name.as_deref().unwrap_or("<none>")
+            )));
+            Command::none()
+        };
+
+        (app, command)
     }
 
     fn title(&self) -> String {
-        "Counter App".to_string()
+        // keep the active port in view, it's easy to lose track of when
+        // switching between devices
+        match PortConfig::try_from(self.port_option.clone()) {
+            Ok(port_conf) => format!("Counter App - {}", port_conf),
+            Err(_) => "Counter App - no port configured".to_string(),
+        }
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
         match message {
             Message::None => Command::none(),
+            // sent by op list commands, e.g. the write value stepper
+            Message::OneShotViewList(OpViewListMessage::SendRequest(op)) => {
+                self.update(Message::OneShotQuarry(op))
+            }
+            Message::ContinuousViewList(OpViewListMessage::SendRequest(op)) => {
+                self.update(Message::OneShotQuarry(op))
+            }
+            Message::OneShotViewList(OpViewListMessage::VerifyWrite(op)) => {
+                self.start_verify_write(op)
+            }
+            Message::ContinuousViewList(OpViewListMessage::VerifyWrite(op)) => {
+                self.start_verify_write(op)
+            }
+            Message::VerifyWriteResult(Err(e)) => self.report_error(e),
+            Message::VerifyWriteResult(Ok(verification)) => {
+                let value = |resp: &Response| {
+                    resp.value().map_or("?".to_string(), |val| val.to_string())
+                };
+                let name = verification.write.op.name.clone();
+                let summary = format!(
+                    "\"{}\" was {}, wrote {}, read back {}",
+                    name,
+                    value(&verification.before),
+                    verification.written,
+                    value(&verification.after)
+                );
+                let passed = verification.passed();
+
+                for resp in
+                    [verification.before, verification.write, verification.after]
+                {
+                    self.responses
+                        .update(ResponseViewMessage::AddResponse(Ok(resp)));
+                }
+                if passed {
+                    self.responses.update(ResponseViewMessage::AddLog(format!(
+                        "Write verified: {}",
+                        summary
+                    )));
+                    scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+                } else {
+                    self.report_error(Error::with_message(
+                        ErrKind::WriteVerifyFailed,
+                        format!("Write not accepted: {}", summary),
+                    ))
+                }
+            }
+            Message::OneShotViewList(OpViewListMessage::ImportCsv(path)) => {
+                let ops = self.import_register_map(&path);
+                self.one_shot_ops
+                    .update(OpViewListMessage::AddOperations(ops))
+                    .map(Message::OneShotViewList)
+            }
+            Message::ContinuousViewList(OpViewListMessage::ImportCsv(path)) => {
+                let ops = self.import_register_map(&path);
+                self.continuous_ops
+                    .update(OpViewListMessage::AddOperations(ops))
+                    .map(Message::ContinuousViewList)
+            }
+            Message::OneShotViewList(OpViewListMessage::InsertTemplate(
+                name,
+            )) => {
+                let ops = self.templates.ops(&name);
+                self.one_shot_ops
+                    .update(OpViewListMessage::AddOperations(ops))
+                    .map(Message::OneShotViewList)
+            }
+            Message::ContinuousViewList(OpViewListMessage::InsertTemplate(
+                name,
+            )) => {
+                let ops = self.templates.ops(&name);
+                self.continuous_ops
+                    .update(OpViewListMessage::AddOperations(ops))
+                    .map(Message::ContinuousViewList)
+            }
+            Message::OneShotViewList(OpViewListMessage::SaveTemplate(name)) => {
+                let ops = self.one_shot_ops.to_vec();
+                self.save_template(&name, &ops)
+            }
+            Message::ContinuousViewList(OpViewListMessage::SaveTemplate(
+                name,
+            )) => {
+                let ops = self.continuous_ops.to_vec();
+                self.save_template(&name, &ops)
+            }
             Message::OneShotViewList(msg) => {
+                self.continuous_ops_active = false;
                 self.one_shot_ops.update(msg).map(Message::OneShotViewList)
             }
             Message::ContinuousViewList(msg) => {
+                self.continuous_ops_active = true;
                 self.continuous_ops.update(msg).map(Message::ContinuousViewList)
             }
             Message::OneShotDisplay(msg) => {
                 self.responses.update(msg).map(Message::OneShotDisplay)
             }
+            Message::ContinuousDisplay(msg) => self
+                .continuous_responses
+                .update(msg)
+                .map(Message::ContinuousDisplay),
+            Message::Simulator(msg) => match self.simulator.update(msg) {
+                Ok(command) => {
+                    self.available_ports = self.list_ports();
+                    command.map(Message::Simulator)
+                }
+                Err(e) => self.report_error(e),
+            },
+            Message::Gateway(msg) => {
+                self.gateway.update(msg).map(Message::Gateway)
+            }
+            #[cfg(feature = "mqtt")]
+            Message::Mqtt(msg) => {
+                self.mqtt.update(msg, &mut self.hooks).map(Message::Mqtt)
+            }
 
             Message::SaveLayout => {
-                if let Ok(string) = ron::to_string(self) {
-                    // don't care if write failed
-                    let _ = std::fs::write("layout.ron", string);
+                self.version = LAYOUT_VERSION;
+                self.polling_active = self.continuous_quarry_channel.is_some();
+                match self.layout_file.save(self) {
+                    Ok(()) => Command::none(),
+                    Err(e) => {
+                        self.responses.update(ResponseViewMessage::AddLog(
+                            format!(
+                                "Failed to save layout to {}: {}",
+                                self.layout_file.path, e
+                            ),
+                        ));
+                        scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+                    }
                 }
+            }
+            Message::OpenLayoutFolder => {
+                let folder = self.layout_file.folder();
+                match open_folder(&folder) {
+                    Ok(()) => Command::none(),
+                    Err(e) => {
+                        self.responses.update(ResponseViewMessage::AddLog(
+                            format!(
+                                "Failed to open {}: {}",
+                                folder.display(),
+                                e
+                            ),
+                        ));
+                        scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+                    }
+                }
+            }
+            Message::ExportSnapshot => {
+                let snapshot = Snapshot::new(
+                    &self.continuous_responses,
+                    self.continuous_port_conf.as_ref(),
+                );
+                let log = match snapshot.write("snapshot.json") {
+                    Ok(()) => "Exported snapshot to snapshot.json".to_string(),
+                    Err(e) => format!("Failed to export snapshot: {}", e),
+                };
 
-                Command::none()
+                self.responses.update(ResponseViewMessage::AddLog(log));
+                scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
             }
             Message::RefreshAvailablePorts => {
-                self.available_ports = serialport::available_ports()
-                    .unwrap()
-                    .into_iter()
-                    .map(|port| port.port_name)
-                    .collect::<Vec<_>>();
+                self.available_ports = self.list_ports();
                 if let Some(port_name) = &self.port_option.port_name {
                     if !self.available_ports.iter().any(|name| name == port_name)
                     {
@@ -168,12 +629,11 @@ impl Application for App {
                 Command::none()
             }
             Message::SetComPort(port_name) => {
-                self.available_ports = serialport::available_ports()
-                    .unwrap()
-                    .into_iter()
-                    .map(|port| port.port_name)
-                    .collect::<Vec<_>>();
+                self.available_ports = self.list_ports();
                 if self.available_ports.iter().any(|s| *s == port_name) {
+                    self.recent_ports.retain(|name| *name != port_name);
+                    self.recent_ports.insert(0, port_name.clone());
+                    self.recent_ports.truncate(MAX_RECENT_PORTS);
                     self.port_option.port_name = Some(port_name)
                 } else {
                     self.port_option.port_name = None
@@ -185,6 +645,9 @@ impl Application for App {
                 Command::none()
             }
             Message::SetBaud(baud) => {
+                // a mistyped letter is dropped, the field error explains the
+                // rest
+                let (baud, _) = mask_input(&baud, InputMask::Integer);
                 self.port_option.baud = baud;
                 Command::none()
             }
@@ -192,24 +655,190 @@ impl Application for App {
                 self.port_option.stop_bits = Some(stop_bits);
                 Command::none()
             }
+            Message::SetReadDelay(read_delay_ms) => {
+                self.port_option.read_delay_ms = read_delay_ms;
+                Command::none()
+            }
+            Message::SetDataBits(data_bits) => {
+                self.port_option.data_bits = data_bits;
+                Command::none()
+            }
+            Message::SetFraming(framing) => {
+                self.port_option.set_framing(framing);
+                Command::none()
+            }
             Message::SetDeviceAddress(addr) => {
+                let (addr, _) = mask_input(&addr, InputMask::Integer);
                 self.port_option.device_addr = addr;
+                self.set_preview_port();
+                Command::none()
+            }
+            Message::SetBatchReads(batch_reads) => {
+                self.batch_reads = batch_reads;
                 Command::none()
             }
 
-            Message::OneShotQuarry(op_view) => Command::perform(
-                one_shot_quarry(
-                    op_view,
-                    self.port_option.clone(),
-                    self.port_thread_sender.clone().unwrap(),
-                ),
-                Message::OneShotResponse,
-            ),
+            Message::SetPlcAddressing(plc_addressing) => {
+                self.plc_addressing = plc_addressing;
+                self.one_shot_ops.set_plc_addressing(plc_addressing);
+                self.continuous_ops.set_plc_addressing(plc_addressing);
+                Command::none()
+            }
+            Message::SetUiScale(ui_scale) => {
+                self.ui_scale = ui_scale;
+                Command::none()
+            }
+            Message::SetWideCsv(wide_csv) => {
+                self.wide_csv = wide_csv;
+                Command::none()
+            }
+            Message::SetCompactView(compact_view) => {
+                self.compact_view = compact_view;
+                self.responses.set_compact(compact_view);
+                self.continuous_responses.set_compact(compact_view);
+                Command::none()
+            }
+            Message::ClearInputErrors => {
+                self.input_errors.clear();
+                Command::none()
+            }
+            Message::OpenPalette => self.palette.open(),
+            Message::ClosePalette => {
+                self.palette.close();
+                Command::none()
+            }
+            Message::SetPaletteQuery(query) => {
+                self.palette.set_query(query);
+                Command::none()
+            }
+            Message::RunPaletteEntry(msg) => {
+                self.palette.close();
+                self.update(*msg)
+            }
+            Message::SetNineBit(nine_bit) => {
+                self.port_option.nine_bit = nine_bit;
+                Command::none()
+            }
+            Message::SetT35Framing(t35_framing) => {
+                self.port_option.t35_framing = t35_framing;
+                Command::none()
+            }
+            Message::SetAutoTimeout(auto_timeout) => {
+                self.port_option.auto_timeout = auto_timeout;
+                Command::none()
+            }
+            Message::SetCrcAlgorithm(crc) => {
+                self.port_option.crc = crc;
+                self.set_preview_port();
+                Command::none()
+            }
+            Message::SetCrcByteOrder(byte_order) => {
+                self.port_option.crc_byte_order = byte_order;
+                self.set_preview_port();
+                Command::none()
+            }
+            Message::SetSkipCrc(skip_crc) => {
+                self.port_option.skip_crc = skip_crc;
+                Command::none()
+            }
+            Message::SetResumePolling(resume_polling) => {
+                self.resume_polling = resume_polling;
+                Command::none()
+            }
+            Message::SetPauseOnBlur(pause_on_blur) => {
+                self.pause_on_blur = pause_on_blur;
+                // turning it off while held must not leave polling stuck
+                if !pause_on_blur && self.blur_paused {
+                    self.set_blur_paused(false)
+                } else {
+                    Command::none()
+                }
+            }
+            Message::SetErrorPolicy(error_policy) => {
+                self.error_policy = error_policy;
+                Command::none()
+            }
+
+            Message::OneShotQuarry(op_view) => {
+                // the port thread would refuse it, say why before sending
+                if let Some(conflict) = self.port_conflict() {
+                    return self.report_error(Error::with_message(
+                        ErrKind::PortTypeUnequal,
+                        format!("{} not sent: {}", op_view.name, conflict),
+                    ));
+                }
+
+                let count = op_view.repeat_count();
+                let command =
+                    match self.one_shot_queue.push_burst(op_view, count) {
+                        Some(op_view) => self.send_one_shot(op_view),
+                        None => Command::none(),
+                    };
+                self.mark_in_flight();
+                command
+            }
+            Message::CancelOneShot => {
+                self.one_shot_queue.cancel_pending();
+                self.mark_in_flight();
+                self.one_shot_cancel.fetch_add(1, Ordering::SeqCst);
+                Command::none()
+            }
             Message::OneShotResponse(response) => {
-                self.responses
-                    .update(ResponseViewMessage::AddResponse(response))
-                    .map(Message::OneShotDisplay);
-                scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+                if self.hooks.has_listeners() {
+                    self.hooks.emit(ResponseEvent {
+                        source: ResponseSource::OneShot,
+                        result: response.clone(),
+                    });
+                }
+
+                let burst = self.one_shot_queue.in_progress_burst();
+                let next = self
+                    .one_shot_queue
+                    .finish(&response, self.error_policy)
+                    .map(|op_view| self.send_one_shot(op_view));
+                self.mark_in_flight();
+
+                if let Ok(resp) = &response {
+                    if let (Request::WriteCoil(addr, _), Some(val)) =
+                        (resp.op.req, resp.value())
+                    {
+                        self.one_shot_ops.confirm_coil(
+                            &resp.op.name,
+                            addr,
+                            val != 0f64,
+                        );
+                        self.continuous_ops.confirm_coil(
+                            &resp.op.name,
+                            addr,
+                            val != 0f64,
+                        );
+                    }
+                }
+
+                let snap = match (response, burst) {
+                    (Err(e), None) => self.report_error(e),
+                    (Err(e), Some(_)) if e.kind().is_input_error() => {
+                        self.report_error(e)
+                    }
+                    (response, burst) => {
+                        self.responses.update(match burst {
+                            Some(burst) => {
+                                ResponseViewMessage::AddBurstResponse(
+                                    burst, response,
+                                )
+                            }
+                            None => ResponseViewMessage::AddResponse(response),
+                        });
+                        scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+                    }
+                };
+                if let Some(summary) = self.one_shot_queue.take_burst_summary() {
+                    self.responses.update(ResponseViewMessage::AddLog(summary));
+                }
+                match next {
+                    Some(next) => Command::batch([snap, next]),
+                    None => snap,
+                }
             }
 
             Message::ContinuousQuarryToggle(op_list) => {
@@ -220,11 +849,32 @@ impl Application for App {
                             .replace(Arc::new(Mutex::new(rx)));
                         self.continuous_responses
                             .update(KeyedResponseViewMessage::ClearResponses);
+                        self.rules.reset();
+                        self.metrics.reset();
+                        self.continuous_tally = (0, 0);
+                        self.continuous_port_conf =
+                            PortConfig::try_from(self.port_option.clone()).ok();
+                        if self.wide_csv {
+                            match WideCsv::create(WIDE_CSV_PATH) {
+                                Ok(log) => self.wide_csv_log = Some(log),
+                                Err(e) => {
+                                    self.responses.update(
+                                        ResponseViewMessage::AddLog(format!(
+                                            "Failed to create {}: {}",
+                                            WIDE_CSV_PATH, e
+                                        )),
+                                    );
+                                }
+                            }
+                        }
 
                         Command::perform(
                             continuous_quarry_start(
                                 op_list,
                                 self.port_option.clone(),
+                                self.error_policy,
+                                self.batch_reads,
+                                self.gateway.clone(),
                                 self.port_thread_sender.clone().unwrap(),
                                 tx,
                             ),
@@ -232,7 +882,8 @@ impl Application for App {
                         )
                     }
                     Some(_) => {
-                        let _ = self.continuous_quarry_channel.take();
+                        self.blur_paused = false;
+                        self.finish_continuous("stopped");
 
                         Command::perform(
                             continuous_quarry_stop(
@@ -243,18 +894,38 @@ impl Application for App {
                     }
                 }
             }
+            Message::ContinuousQuarryRestart(op_list) => {
+                if self.continuous_quarry_channel.is_none() {
+                    return self
+                        .update(Message::ContinuousQuarryToggle(op_list));
+                }
+                self.blur_paused = false;
+                self.finish_continuous("stopped");
+
+                // starting clears the responses and stats, it is only sent
+                // once the stop request is queued on the port thread
+                Command::perform(
+                    continuous_quarry_stop(
+                        self.port_thread_sender.clone().unwrap(),
+                    ),
+                    move |()| Message::ContinuousQuarryToggle(op_list),
+                )
+            }
             Message::ContinuousQuarryStartResult(start_result) => {
-                if let Ok(()) = start_result {
-                    if let Some(rx) = &self.continuous_quarry_channel {
-                        Command::perform(
+                match start_result {
+                    Ok(()) => match &self.continuous_quarry_channel {
+                        Some(rx) => Command::perform(
                             continuous_quarry_get_results(rx.clone()),
                             Message::ContinuousQuarryResult,
-                        )
-                    } else {
-                        Command::none()
+                        ),
+                        None => Command::none(),
+                    },
+                    // nothing is polled, so show the ops again
+                    Err(e) => {
+                        let _ = self.continuous_quarry_channel.take();
+                        self.finish_wide_csv();
+                        self.report_error(e)
                     }
-                } else {
-                    Command::none()
                 }
             }
             Message::ContinuousQuarryResult(results) => match &self
@@ -264,9 +935,84 @@ impl Application for App {
 
                 Some(rx) => match results {
                     Ok(results) => {
-                        for (key, val) in results.into_iter().filter_map(|r| {
-                            r.map_or(None, |r| Some((r.op.name.clone(), r)))
-                        }) {
+                        let rx = rx.clone();
+                        let mut commands = vec![];
+
+                        for result in results {
+                            // the previous sample is bound to `prev` in the
+                            // read expression
+                            let result = result.map(|resp| {
+                                let key =
+                                    (resp.op.device_addr, resp.op.name.clone());
+                                let prev = self.continuous_responses.prev(&key);
+                                resp.with_prev(prev)
+                            });
+                            if self.hooks.has_listeners() {
+                                self.hooks.emit(ResponseEvent {
+                                    source: ResponseSource::Continuous,
+                                    result: result.clone(),
+                                });
+                            }
+
+                            // a failing op doesn't stop the others being
+                            // polled, its error is only reported
+                            let val = match result {
+                                Ok(val) => val,
+                                Err(e) => {
+                                    self.continuous_tally.1 += 1;
+                                    commands.push(self.report_error(e));
+                                    continue;
+                                }
+                            };
+                            let key = (val.op.device_addr, val.op.name.clone());
+                            if val.frame_error().is_some() {
+                                self.continuous_tally.1 += 1;
+                            } else {
+                                self.continuous_tally.0 += 1;
+                            }
+
+                            self.metrics.record(&val);
+                            if let Some(Err(e)) = self
+                                .wide_csv_log
+                                .as_mut()
+                                .map(|log| log.record(&val))
+                            {
+                                self.wide_csv_log = None;
+                                self.responses.update(
+                                    ResponseViewMessage::AddLog(format!(
+                                        "Stopped logging to {}: {}",
+                                        WIDE_CSV_PATH, e
+                                    )),
+                                );
+                            }
+                            if let Some(value) = val.value() {
+                                for rule in self.rules.evaluate(&key, value) {
+                                    commands.push(self.trigger_rule(rule));
+                                }
+                            }
+
+                            // only log the alarm when the value crosses the
+                            // limit, not on every cycle it stays past it
+                            let alarm = val.value().and_then(|value| {
+                                Some((value, val.op.alarm(value)?))
+                            });
+                            if let Some((value, limit)) = alarm {
+                                if !self.continuous_responses.in_alarm(&key) {
+                                    self.responses.update(
+                                        ResponseViewMessage::AddLog(format!(
+                                            "Alarm: {} is {}, {}",
+                                            key.1,
+                                            val.op.format_display(value),
+                                            limit
+                                        )),
+                                    );
+                                    commands.push(scrollable::snap_to(
+                                        scrollable::Id::new("RespView"),
+                                        1.0,
+                                    ));
+                                }
+                            }
+
                             self.continuous_responses.update(
                                 KeyedResponseViewMessage::AddResponse(
                                     key,
@@ -274,58 +1020,425 @@ impl Application for App {
                                 ),
                             );
                         }
-                        Command::perform(
-                            continuous_quarry_get_results(rx.clone()),
+                        commands.push(Command::perform(
+                            continuous_quarry_get_results(rx),
                             Message::ContinuousQuarryResult,
-                        )
+                        ));
+
+                        Command::batch(commands)
+                    }
+                    // the port thread ended the poll, at the first error with
+                    // `ErrorPolicy::Stop` or because the port is gone
+                    Err(_) => {
+                        self.finish_continuous("stopped by an error");
+                        scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
                     }
-                    Err(_) => Command::perform(
-                        continuous_quarry_get_results(rx.clone()),
-                        Message::ContinuousQuarryResult,
-                    ),
                 },
             },
-        }
-    }
 
-    fn view(&self) -> Element<'_, Self::Message> {
-        Column::new()
-            .push(
-                // top bar options
-                Row::new()
-                    .height(Length::Units(40))
-                    .padding([5, 10])
-                    .push(
-                        // Save layout button
-                        Container::new(
-                            Button::new("Save Layout")
-                                .on_press(Message::SaveLayout),
-                        )
-                        .padding([0, 2]),
-                    )
-                    .push(
-                        // refresh port button
-                        Container::new(
-                            Button::new("Refresh")
-                                .on_press(Message::RefreshAvailablePorts),
-                        )
-                        .padding([0, 4, 0, 32]),
-                    )
-                    .push(
-                        // Com port picker
-                        Container::new(
-                            PickList::new(
-                                &self.available_ports,
-                                self.port_option.port_name.clone(),
-                                Message::SetComPort,
-                            )
-                            .placeholder("Port"),
-                        )
-                        .padding([0, 16, 0, 4]),
+            Message::MetricsTick(now) => {
+                self.metrics.tick(now, &self.port_option);
+                Command::none()
+            }
+            Message::TestConnection => {
+                self.testing_connection = true;
+                self.connection_status = None;
+                Command::perform(
+                    test_connection(
+                        self.port_option.clone(),
+                        self.test_echo,
+                        self.port_thread_sender.clone().unwrap(),
+                        self.one_shot_cancel.clone(),
+                    ),
+                    Message::TestConnectionResult,
+                )
+            }
+            Message::TestConnectionResult(result) => {
+                self.testing_connection = false;
+                self.connection_status = Some(result);
+                Command::none()
+            }
+            Message::SetTestEcho(test_echo) => {
+                self.test_echo = test_echo;
+                Command::none()
+            }
+            Message::WindowFocus(focused) => {
+                if self.pause_on_blur
+                    && self.continuous_quarry_channel.is_some()
+                    && self.blur_paused == focused
+                {
+                    self.set_blur_paused(!focused)
+                } else {
+                    Command::none()
+                }
+            }
+
+            Message::Disconnect => {
+                // a running scan or monitor still reports when it ended
+                self.blur_paused = false;
+                self.finish_continuous("stopped");
+
+                Command::perform(
+                    port_disconnect(self.port_thread_sender.clone().unwrap()),
+                    |()| Message::None,
+                )
+            }
+            Message::Exit => {
+                self.finish_continuous("stopped");
+
+                if let Some(tx) = self.port_thread_sender.take() {
+                    port_shutdown(&tx);
+                }
+                // the thread only sees the shutdown between requests, which
+                // can wait out a long read delay and timeout, so it is left
+                // behind if it doesn't end in time. A panic on it doesn't
+                // matter anymore
+                if let Some(port_thread) = self.port_thread.take() {
+                    let deadline = Instant::now() + EXIT_JOIN_TIMEOUT;
+                    while !port_thread.is_finished() && Instant::now() < deadline
+                    {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    if port_thread.is_finished() {
+                        let _ = port_thread.join();
+                    }
+                }
+                self.exiting = true;
+
+                Command::none()
+            }
+            Message::ScanDisplay(msg) => {
+                self.scan_view.update(msg).map(Message::ScanDisplay)
+            }
+            Message::ScanToggle => match self.scan_channel {
+                None => {
+                    let (tx, rx) = channel();
+                    self.scan_channel.replace(Arc::new(Mutex::new(rx)));
+                    self.scan_view.update(ScanViewMessage::Clear);
+
+                    Command::perform(
+                        scan_start(
+                            self.port_option.clone(),
+                            self.error_policy,
+                            self.port_thread_sender.clone().unwrap(),
+                            tx,
+                        ),
+                        Message::ScanStartResult,
                     )
-                    .push(
-                        // Parity picker
-                        Container::new(
+                }
+                Some(_) => Command::perform(
+                    scan_stop(self.port_thread_sender.clone().unwrap()),
+                    |()| Message::None,
+                ),
+            },
+            Message::DetectBaud => {
+                let (tx, rx) = channel();
+                self.scan_channel.replace(Arc::new(Mutex::new(rx)));
+                self.scan_view.update(ScanViewMessage::Clear);
+
+                Command::perform(
+                    detect_baud_start(
+                        self.port_option.clone(),
+                        self.error_policy,
+                        self.port_thread_sender.clone().unwrap(),
+                        tx,
+                    ),
+                    Message::ScanStartResult,
+                )
+            }
+            Message::ScanStartResult(start_result) => match start_result {
+                Ok(()) => match &self.scan_channel {
+                    Some(rx) => Command::perform(
+                        scan_get_results(rx.clone()),
+                        Message::ScanResult,
+                    ),
+                    None => Command::none(),
+                },
+                Err(e) => {
+                    self.scan_channel = None;
+                    self.scan_view.update(ScanViewMessage::Close);
+                    self.report_error(e)
+                }
+            },
+            Message::ScanResult(results) => {
+                let rx = match &self.scan_channel {
+                    Some(rx) => rx.clone(),
+                    None => return Command::none(),
+                };
+
+                let mut finished = results.is_err();
+                for event in results.into_iter().flatten() {
+                    match event {
+                        Ok(event) => {
+                            finished |= event == ScanEvent::Finished;
+                            if let ScanEvent::BaudFound(baud) = event {
+                                self.port_option.baud = baud.to_string();
+                            }
+                            self.scan_view
+                                .update(ScanViewMessage::AddEvent(event));
+                        }
+                        Err(e) => {
+                            // the scan thread stops after reporting an error
+                            finished = true;
+                            let _ = self.report_error(e);
+                        }
+                    }
+                }
+
+                if finished {
+                    self.scan_channel = None;
+                    self.scan_view
+                        .update(ScanViewMessage::AddEvent(ScanEvent::Finished));
+                    Command::none()
+                } else {
+                    Command::perform(scan_get_results(rx), Message::ScanResult)
+                }
+            }
+
+            Message::MonitorDisplay(msg) => {
+                self.monitor_view.update(msg).map(Message::MonitorDisplay)
+            }
+            Message::MonitorToggle => match self.monitor_channel {
+                None => {
+                    let (tx, rx) = channel();
+                    self.monitor_channel.replace(Arc::new(Mutex::new(rx)));
+                    self.monitor_view.open();
+
+                    Command::perform(
+                        monitor_start(
+                            self.port_option.clone(),
+                            self.monitor_view.silence_gap(),
+                            self.port_thread_sender.clone().unwrap(),
+                            tx,
+                        ),
+                        Message::MonitorStartResult,
+                    )
+                }
+                Some(_) => Command::perform(
+                    monitor_stop(self.port_thread_sender.clone().unwrap()),
+                    |()| Message::None,
+                ),
+            },
+            Message::MonitorStartResult(start_result) => match start_result {
+                Ok(()) => match &self.monitor_channel {
+                    Some(rx) => Command::perform(
+                        monitor_get_results(rx.clone()),
+                        Message::MonitorResult,
+                    ),
+                    None => Command::none(),
+                },
+                Err(e) => {
+                    self.monitor_channel = None;
+                    self.report_error(e)
+                }
+            },
+            Message::MonitorResult(results) => {
+                let rx = match &self.monitor_channel {
+                    Some(rx) => rx.clone(),
+                    None => return Command::none(),
+                };
+
+                // the monitor thread is gone once its channel is closed
+                let mut finished = results.is_err();
+                let mut commands = vec![];
+                for frame in results.into_iter().flatten() {
+                    match frame {
+                        Ok(frame) => {
+                            self.monitor_view
+                                .update(MonitorViewMessage::AddFrame(frame));
+                        }
+                        Err(e) => {
+                            // the monitor thread stops after reporting an
+                            // error
+                            finished = true;
+                            commands.push(self.report_error(e));
+                        }
+                    }
+                }
+
+                if finished {
+                    self.monitor_channel = None;
+                } else {
+                    commands.push(Command::perform(
+                        monitor_get_results(rx),
+                        Message::MonitorResult,
+                    ));
+                }
+                Command::batch(commands)
+            }
+
+            Message::KeyPressed(key_code, modifiers) => {
+                let msg = match key_code {
+                    KeyCode::Tab if modifiers.shift() => {
+                        return iced::widget::focus_previous();
+                    }
+                    KeyCode::Tab => return iced::widget::focus_next(),
+                    KeyCode::Up => OpViewListMessage::FocusPreviousRow,
+                    KeyCode::Down => OpViewListMessage::FocusNextRow,
+                    KeyCode::N if modifiers.command() => {
+                        OpViewListMessage::AddOperation
+                    }
+                    KeyCode::K if modifiers.command() => {
+                        return self.update(Message::OpenPalette);
+                    }
+                    KeyCode::Escape if self.palette.is_visible() => {
+                        return self.update(Message::ClosePalette);
+                    }
+                    KeyCode::Escape
+                        if self.continuous_responses.is_popped_out() =>
+                    {
+                        return self.update(Message::ContinuousDisplay(
+                            KeyedResponseViewMessage::ClosePopOut,
+                        ));
+                    }
+                    _ => return Command::none(),
+                };
+
+                // the continuous ops are hidden while they are polled
+                if self.continuous_ops_active
+                    && self.continuous_quarry_channel.is_none()
+                {
+                    self.update(Message::ContinuousViewList(msg))
+                } else {
+                    self.update(Message::OneShotViewList(msg))
+                }
+            }
+        }
+    }
+
+    fn should_exit(&self) -> bool {
+        self.exiting
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.ui_scale.factor()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let keys = iced::subscription::events_with(|event, status| {
+            match (event, status) {
+                (
+                    Event::Keyboard(keyboard::Event::KeyPressed {
+                        key_code,
+                        modifiers,
+                    }),
+                    event::Status::Ignored,
+                ) => Some(Message::KeyPressed(key_code, modifiers)),
+                (Event::Window(window::Event::Focused), _) => {
+                    Some(Message::WindowFocus(true))
+                }
+                (Event::Window(window::Event::Unfocused), _) => {
+                    Some(Message::WindowFocus(false))
+                }
+                (Event::Window(window::Event::CloseRequested), _) => {
+                    Some(Message::Exit)
+                }
+                _ => None,
+            }
+        });
+
+        // metrics are only meaningful while the continuous quarry runs
+        if self.continuous_quarry_channel.is_some() {
+            Subscription::batch([
+                keys,
+                iced::time::every(Duration::from_secs(1))
+                    .map(Message::MetricsTick),
+            ])
+        } else {
+            keys
+        }
+    }
+
+    fn view(&self) -> Element<'_, Self::Message> {
+        // a popped out value takes the whole window
+        if let Some(pop_out) = self.continuous_responses.pop_out_view() {
+            return pop_out.map(Message::ContinuousDisplay);
+        }
+
+        // baud detection shares the port thread with a running scan
+        let mut auto_baud = Button::new("Auto Baud");
+        if self.scan_channel.is_none() {
+            auto_baud = auto_baud.on_press(Message::DetectBaud);
+        }
+        let mut test_port = Button::new(if self.testing_connection {
+            "Testing..."
+        } else {
+            "Test Connection"
+        });
+        if !self.testing_connection {
+            test_port = test_port.on_press(Message::TestConnection);
+        }
+        let mut cancel_one_shot = Button::new("Cancel Request");
+        if self.one_shot_queue.is_busy() {
+            cancel_one_shot = cancel_one_shot.on_press(Message::CancelOneShot);
+        }
+
+        Column::new()
+            .push(
+                // top bar options
+                Row::new()
+                    .height(Length::Units(40))
+                    .padding([5, 10])
+                    .push(
+                        // Save layout button
+                        Container::new(
+                            Button::new("Save Layout")
+                                .on_press(Message::SaveLayout),
+                        )
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        // open the folder of the layout file
+                        Container::new(
+                            Button::new("Open Folder")
+                                .on_press(Message::OpenLayoutFolder),
+                        )
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        // refresh port button
+                        Container::new(
+                            Button::new("Refresh")
+                                .on_press(Message::RefreshAvailablePorts),
+                        )
+                        .padding([0, 4, 0, 32]),
+                    )
+                    .push(
+                        // Com port picker
+                        Container::new(
+                            PickList::new(
+                                self.port_choices(),
+                                self.port_option.port_name.clone(),
+                                Message::SetComPort,
+                            )
+                            .placeholder("Port"),
+                        )
+                        .padding([0, 16, 0, 4]),
+                    )
+                    .push(
+                        // Framing preset picker, sets the three pickers after
+                        Container::new(
+                            PickList::new(
+                                FRAMINGS,
+                                self.port_option.framing(),
+                                Message::SetFraming,
+                            )
+                            .placeholder("Framing"),
+                        )
+                        .padding([0, 16]),
+                    )
+                    .push(
+                        // Data bits picker
+                        Container::new(PickList::new(
+                            DATA_BITS,
+                            Some(self.port_option.data_bits),
+                            Message::SetDataBits,
+                        ))
+                        .padding([0, 16]),
+                    )
+                    .push(
+                        // Parity picker
+                        Container::new(
                             PickList::new(
                                 PARITIES,
                                 self.port_option.parity,
@@ -371,7 +1484,214 @@ impl Application for App {
                         .width(Length::Units(96))
                         .align_y(Vertical::Center),
                     )
+                    .push(
+                        // Delay before reading responses setting
+                        Container::new(TextInput::new(
+                            "Read Delay ms",
+                            &self.port_option.read_delay_ms,
+                            Message::SetReadDelay,
+                        ))
+                        .padding([0, 16])
+                        .height(Length::Fill)
+                        .width(Length::Units(128))
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // Batch reads toggle
+                        Container::new(Checkbox::new(
+                            self.batch_reads,
+                            "Batch Reads",
+                            Message::SetBatchReads,
+                        ))
+                        .padding([0, 16])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // PLC-style address toggle
+                        Container::new(Checkbox::new(
+                            self.plc_addressing,
+                            "PLC Addresses",
+                            Message::SetPlcAddressing,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // 9-bit addressing toggle
+                        Container::new(Checkbox::new(
+                            self.port_option.nine_bit,
+                            "9-bit Address",
+                            Message::SetNineBit,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // frame by inter-character gap toggle
+                        Container::new(Checkbox::new(
+                            self.port_option.t35_framing,
+                            "t3.5 Framing",
+                            Message::SetT35Framing,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // timeout scaled with the baud toggle
+                        Container::new(Checkbox::new(
+                            self.port_option.auto_timeout,
+                            "Auto Timeout",
+                            Message::SetAutoTimeout,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // checksum picker, for non-compliant devices
+                        Container::new(PickList::new(
+                            CRC_ALGORITHMS,
+                            Some(self.port_option.crc),
+                            Message::SetCrcAlgorithm,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // CRC byte order picker, for devices that swap them
+                        Container::new(PickList::new(
+                            CRC_BYTE_ORDERS,
+                            Some(self.port_option.crc_byte_order),
+                            Message::SetCrcByteOrder,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // debug only CRC check toggle
+                        Container::new(Checkbox::new(
+                            self.port_option.skip_crc,
+                            "Skip CRC (debug)",
+                            Message::SetSkipCrc,
+                        ))
+                        .padding([0, 16, 0, 0])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // resume polling on launch toggle
+                        Container::new(Checkbox::new(
+                            self.resume_polling,
+                            "Resume Polling",
+                            Message::SetResumePolling,
+                        ))
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // hold polling while the window is unfocused toggle
+                        Container::new(Checkbox::new(
+                            self.pause_on_blur,
+                            "Pause Unfocused",
+                            Message::SetPauseOnBlur,
+                        ))
+                        .padding([0, 0, 0, 16])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // stop or go on at the first error of bursts, scans
+                        // and polling
+                        Container::new(PickList::new(
+                            ERROR_POLICIES,
+                            Some(self.error_policy),
+                            Message::SetErrorPolicy,
+                        ))
+                        .padding([0, 0, 0, 16])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
                     .push(Space::new(Length::Units(16), Length::Fill))
+                    .push(
+                        // open the port, and echo a loopback test if checked
+                        Container::new(test_port).padding([0, 4]),
+                    )
+                    .push(
+                        Container::new(Checkbox::new(
+                            self.test_echo,
+                            "Echo",
+                            Message::SetTestEcho,
+                        ))
+                        .padding([0, 4])
+                        .height(Length::Fill)
+                        .align_y(Vertical::Center),
+                    )
+                    .push(
+                        // detect baud button
+                        Container::new(auto_baud).padding([0, 4]),
+                    )
+                    .push(
+                        // toggle bus scan button
+                        Container::new(
+                            Button::new(
+                                if self.scan_channel.is_some() {
+                                    "Stop Scan"
+                                } else {
+                                    "Scan Bus"
+                                },
+                            )
+                            .on_press(Message::ScanToggle),
+                        )
+                        .padding([0, 4]),
+                    )
+                    .push(
+                        // toggle bus monitor button
+                        Container::new(
+                            Button::new(
+                                if self.monitor_channel.is_some() {
+                                    "Stop Monitor"
+                                } else {
+                                    "Monitor Bus"
+                                },
+                            )
+                            .on_press(Message::MonitorToggle),
+                        )
+                        .padding([0, 4]),
+                    )
+                    .push(
+                        // toggle simulator button
+                        Container::new(
+                            Button::new(
+                                if self.simulator.is_running() {
+                                    "Stop Simulator"
+                                } else {
+                                    "Start Simulator"
+                                },
+                            )
+                            .on_press(
+                                Message::Simulator(SimulatorMessage::Toggle),
+                            ),
+                        )
+                        .padding([0, 4]),
+                    )
+                    .push(
+                        // cancel pending one-shot requests button
+                        Container::new(cancel_one_shot).padding([0, 4]),
+                    )
+                    .push(
+                        // release port button
+                        Container::new(
+                            Button::new("Disconnect")
+                                .on_press(Message::Disconnect),
+                        )
+                        .padding([0, 4]),
+                    )
                     .push(
                         // toggle quarry button
                         Container::new(
@@ -382,8 +1702,22 @@ impl Application for App {
                             ),
                         )
                         .padding([0, 4, 0, 32]),
+                    )
+                    .push(
+                        Container::new(
+                            Button::new("Clear and Restart").on_press(
+                                Message::ContinuousQuarryRestart(
+                                    self.continuous_ops.clone(),
+                                ),
+                            ),
+                        )
+                        .padding([0, 4]),
                     ),
             )
+            .push(self.palette_view())
+            .push(self.port_option_errors())
+            .push(self.connection_status_view())
+            .push(self.input_error_panel())
             .push(
                 Row::new()
                     .padding([5, 10])
@@ -406,6 +1740,7 @@ impl Application for App {
                                 ))
                                 .height(Length::FillPortion(70)),
                             )
+                            .push(self.one_shot_queue.view())
                             .push(
                                 scrollable(
                                     self.responses
@@ -418,11 +1753,60 @@ impl Application for App {
                             .width(Length::FillPortion(50)),
                     )
                     .push(
-                        // Continuous view or continuous response view
-                        Container::new(
-                            // if channel not present, show cv
-                            if self.continuous_quarry_channel.is_none() {
-                                self.continuous_ops.view().map(|msg| {
+                        Column::new()
+                            .padding([4, 0])
+                            .push(
+                                Row::new()
+                                    .align_items(Alignment::Center)
+                                    .push(
+                                        Container::new(
+                                            self.gateway
+                                                .view()
+                                                .map(Message::Gateway),
+                                        )
+                                        .width(Length::Fill),
+                                    )
+                                    .push(
+                                        // compact response display toggle
+                                        Container::new(Checkbox::new(
+                                            self.compact_view,
+                                            "Values Only",
+                                            Message::SetCompactView,
+                                        ))
+                                        .padding([0, 16]),
+                                    )
+                                    .push(
+                                        // one column per op CSV log toggle,
+                                        // applies from the next start
+                                        Container::new(Checkbox::new(
+                                            self.wide_csv,
+                                            "Wide CSV",
+                                            Message::SetWideCsv,
+                                        ))
+                                        .padding([0, 16, 0, 0]),
+                                    )
+                                    .push(
+                                        // UI scale picker
+                                        Container::new(PickList::new(
+                                            UI_SCALES,
+                                            Some(self.ui_scale),
+                                            Message::SetUiScale,
+                                        ))
+                                        .padding([0, 16, 0, 0]),
+                                    )
+                                    .push(
+                                        // continuous dashboard export button
+                                        Button::new("Export Snapshot")
+                                            .on_press(Message::ExportSnapshot),
+                                    ),
+                            )
+                            .push(self.mqtt_view())
+                            .push(
+                                // Continuous view or continuous response view
+                                Container::new(
+                                    // if channel not present, show cv
+                                    if self.continuous_quarry_channel.is_none() {
+                                        self.continuous_ops.view().map(|msg| {
                                     if let OpViewListMessage::SendRequest(
                                         op_view,
                                     ) = msg
@@ -432,20 +1816,530 @@ impl Application for App {
                                         Message::ContinuousViewList(msg)
                                     }
                                 })
-                            } else {
-                                // else show responses
-                                scrollable::Scrollable::new(
-                                    self.continuous_responses
-                                        .view()
-                                        .map(|_msg| Message::None),
+                                    } else {
+                                        // else show metrics and responses
+                                        self.continuous_results_view()
+                                    },
                                 )
-                                .into()
-                            },
-                        )
-                        .padding([4, 0])
-                        .width(Length::FillPortion(50)),
+                                .height(Length::FillPortion(70)),
+                            )
+                            .push(
+                                // Bus scan results, bus monitor or simulator
+                                // register table
+                                Container::new(
+                                    if self.scan_view.is_visible() {
+                                        self.scan_view
+                                            .view()
+                                            .map(Message::ScanDisplay)
+                                    } else if self.monitor_view.is_visible() {
+                                        self.monitor_view
+                                            .view()
+                                            .map(Message::MonitorDisplay)
+                                    } else {
+                                        self.simulator
+                                            .view()
+                                            .map(Message::Simulator)
+                                    },
+                                )
+                                .height(Length::FillPortion(30)),
+                            )
+                            .width(Length::FillPortion(50)),
                     ),
             )
             .into()
     }
 }
+
+impl App {
+    /// Upgrade a layout loaded from an older layout version to the current
+    /// one, newer layouts are left as they are
+    fn migrate(&mut self) {
+        if self.version >= LAYOUT_VERSION {
+            return;
+        }
+
+        self.one_shot_ops.migrate(self.version);
+        self.continuous_ops.migrate(self.version);
+        self.version = LAYOUT_VERSION;
+    }
+
+    /// Read and parse a register map CSV, every invalid row is logged
+    fn import_register_map(&mut self, path: &str) -> Vec<OpView> {
+        let csv = match std::fs::read_to_string(path) {
+            Ok(csv) => csv,
+            Err(e) => {
+                let _ = self.report_error(Error::with_message(
+                    ErrKind::RegisterMapParseError,
+                    format!("Failed to read \"{}\" due to: {}", path, e),
+                ));
+                return vec![];
+            }
+        };
+
+        let (ops, errors) = parse_register_map(&csv);
+        for error in errors {
+            let _ = self.report_error(error);
+        }
+        self.responses.update(ResponseViewMessage::AddLog(format!(
+            "Imported {} operations from \"{}\"",
+            ops.len(),
+            path
+        )));
+
+        ops
+    }
+
+    /// Save `ops` as the user template `name` and offer it in both lists
+    fn save_template(&mut self, name: &str, ops: &[OpView]) -> Command<Message> {
+        let log = match self.templates.save(name, ops) {
+            Ok(path) => format!(
+                "Saved template \"{}\" with {} operations to {}",
+                name.trim(),
+                ops.len(),
+                path
+            ),
+            Err(e) => format!("Failed to save template: {}", e),
+        };
+        self.set_template_names();
+
+        self.responses.update(ResponseViewMessage::AddLog(log));
+        scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+    }
+
+    /// Pass the names of the templates to the op lists
+    fn set_template_names(&mut self) {
+        let names = self.templates.names();
+        self.one_shot_ops.set_template_names(names.clone());
+        self.continuous_ops.set_template_names(names);
+    }
+
+    /// Pass the port's device address and checksum to the op lists for frame
+    /// previews
+    fn set_preview_port(&mut self) {
+        let device_addr = self.port_option.device_addr.parse_num::<u8>().ok();
+        let crc = CrcConfig {
+            algorithm: self.port_option.crc,
+            byte_order: self.port_option.crc_byte_order,
+        };
+        self.one_shot_ops.set_preview_port(device_addr, crc);
+        self.continuous_ops.set_preview_port(device_addr, crc);
+    }
+
+    /// Read, write and read back the register of `op` on the port
+    fn start_verify_write(&self, op: OpView) -> Command<Message> {
+        Command::perform(
+            verify_write(
+                op,
+                self.port_option.clone(),
+                self.port_thread_sender.clone().unwrap(),
+                self.one_shot_cancel.clone(),
+            ),
+            Message::VerifyWriteResult,
+        )
+    }
+
+    /// Show an error, input errors go to the input error panel and every
+    /// other error to the responses
+    fn report_error(&mut self, error: Error) -> Command<Message> {
+        if error.kind().is_input_error() {
+            self.input_errors.push(error);
+            Command::none()
+        } else {
+            self.responses.update(ResponseViewMessage::AddResponse(Err(error)));
+            scrollable::snap_to(scrollable::Id::new("RespView"), 1.0)
+        }
+    }
+
+    /// Input errors not dismissed yet, with a button clearing them
+    fn input_error_panel(&self) -> Element<'_, Message> {
+        if self.input_errors.is_empty() {
+            return Space::with_height(Length::Units(0)).into();
+        }
+
+        let mut column = Column::new().width(Length::Fill);
+        for error in self.input_errors.iter() {
+            column = column.push(
+                Text::new(format!("{:?}: {}", error.kind(), error.message()))
+                    .size(TextSize::SMALL)
+                    .style(Color::from_rgb(0.8, 0.2, 0.2)),
+            );
+        }
+
+        Container::new(
+            Row::new()
+                .align_items(Alignment::Center)
+                .push(column)
+                .push(Button::new("Clear").on_press(Message::ClearInputErrors)),
+        )
+        .padding([0, 10])
+        .into()
+    }
+
+    #[cfg(feature = "mqtt")]
+    fn mqtt_view(&self) -> Element<'_, Message> {
+        self.mqtt.view().map(Message::Mqtt)
+    }
+
+    /// Nothing to show when built without MQTT support
+    #[cfg(not(feature = "mqtt"))]
+    fn mqtt_view(&self) -> Element<'_, Message> {
+        Space::with_height(Length::Units(0)).into()
+    }
+
+    /// Poll metrics above the latest response of every continuous op
+    fn continuous_results_view(&self) -> Element<'_, Message> {
+        Column::new()
+            .push(self.metrics.view())
+            .push(scrollable::Scrollable::new(
+                self.continuous_responses.view().map(Message::ContinuousDisplay),
+            ))
+            .into()
+    }
+
+    /// End the continuous quarry if one runs, logging how many of its
+    /// requests succeeded and failed. `how` tells how it ended
+    fn finish_continuous(&mut self, how: &str) {
+        if self.continuous_quarry_channel.take().is_none() {
+            return;
+        }
+        self.finish_wide_csv();
+
+        let (succeeded, failed) = std::mem::take(&mut self.continuous_tally);
+        self.responses.update(ResponseViewMessage::AddLog(format!(
+            "Polling {}: {} succeeded, {} failed",
+            how, succeeded, failed
+        )));
+    }
+
+    /// Write the last cycle of the wide CSV and stop logging to it
+    fn finish_wide_csv(&mut self) {
+        if let Some(Err(e)) = self.wide_csv_log.take().map(WideCsv::finish) {
+            self.responses.update(ResponseViewMessage::AddLog(format!(
+                "Failed to write {}: {}",
+                WIDE_CSV_PATH, e
+            )));
+        }
+    }
+
+    /// Hold or carry on the continuous quarry for the window focus, logged so
+    /// a gap in the values is explained
+    fn set_blur_paused(&mut self, paused: bool) -> Command<Message> {
+        self.blur_paused = paused;
+        self.responses.update(ResponseViewMessage::AddLog(
+            if paused {
+                "Polling paused while the window is unfocused"
+            } else {
+                "Polling resumed"
+            }
+            .to_string(),
+        ));
+
+        Command::perform(
+            continuous_quarry_pause(
+                self.port_thread_sender.clone().unwrap(),
+                paused,
+            ),
+            |()| Message::None,
+        )
+    }
+
+    /// Actions the command palette lists, in the order they are shown
+    fn palette_entries(&self) -> Vec<(String, Message)> {
+        let mut entries = vec![
+            ("Save layout".to_string(), Message::SaveLayout),
+            ("Open layout folder".to_string(), Message::OpenLayoutFolder),
+            ("Refresh ports".to_string(), Message::RefreshAvailablePorts),
+            (
+                if self.continuous_quarry_channel.is_some() {
+                    "Stop continuous quarry"
+                } else {
+                    "Start continuous quarry"
+                }
+                .to_string(),
+                Message::ContinuousQuarryToggle(self.continuous_ops.clone()),
+            ),
+            (
+                "Clear and restart continuous quarry".to_string(),
+                Message::ContinuousQuarryRestart(self.continuous_ops.clone()),
+            ),
+            ("Export snapshot".to_string(), Message::ExportSnapshot),
+            (
+                if self.scan_channel.is_some() {
+                    "Stop scan"
+                } else {
+                    "Scan bus"
+                }
+                .to_string(),
+                Message::ScanToggle,
+            ),
+            (
+                if self.monitor_channel.is_some() {
+                    "Stop monitor"
+                } else {
+                    "Monitor bus"
+                }
+                .to_string(),
+                Message::MonitorToggle,
+            ),
+            (
+                if self.simulator.is_running() {
+                    "Stop simulator"
+                } else {
+                    "Start simulator"
+                }
+                .to_string(),
+                Message::Simulator(SimulatorMessage::Toggle),
+            ),
+            ("Disconnect".to_string(), Message::Disconnect),
+            (
+                "Add operation".to_string(),
+                Message::OneShotViewList(OpViewListMessage::AddOperation),
+            ),
+        ];
+        if self.scan_channel.is_none() {
+            entries.push(("Auto baud".to_string(), Message::DetectBaud));
+        }
+        if self.one_shot_queue.is_busy() {
+            entries.push(("Cancel request".to_string(), Message::CancelOneShot));
+        }
+        entries.extend(ERROR_POLICIES.iter().map(|&policy| {
+            (policy.to_string(), Message::SetErrorPolicy(policy))
+        }));
+        entries.extend(UI_SCALES.iter().map(|&scale| {
+            (format!("UI scale {}", scale), Message::SetUiScale(scale))
+        }));
+
+        entries.extend(self.one_shot_ops.iter().enumerate().map(|(idx, op)| {
+            (
+                format!("Go to {}", op.name),
+                Message::OneShotViewList(OpViewListMessage::FocusRow(idx)),
+            )
+        }));
+        // the continuous ops are hidden while they are polled
+        if self.continuous_quarry_channel.is_none() {
+            entries.extend(self.continuous_ops.iter().enumerate().map(
+                |(idx, op)| {
+                    (
+                        format!("Go to {} (continuous)", op.name),
+                        Message::ContinuousViewList(
+                            OpViewListMessage::FocusRow(idx),
+                        ),
+                    )
+                },
+            ));
+        }
+
+        entries
+            .into_iter()
+            .map(|(label, msg)| (label, Message::RunPaletteEntry(Box::new(msg))))
+            .collect()
+    }
+
+    /// The command palette if it's open, nothing otherwise
+    fn palette_view(&self) -> Element<'_, Message> {
+        if !self.palette.is_visible() {
+            return Space::with_height(Length::Units(0)).into();
+        }
+
+        self.palette.view(
+            self.palette_entries(),
+            Message::SetPaletteQuery,
+            Message::ClosePalette,
+        )
+    }
+
+    /// Why one-shot requests can't be sent while the continuous quarry is
+    /// running, `None` if the port options still match the ones it was
+    /// started with. Invalid options are reported on their own
+    fn port_conflict(&self) -> Option<String> {
+        self.continuous_quarry_channel.as_ref()?;
+        let active = self.continuous_port_conf.as_ref()?;
+        let port_conf = PortConfig::try_from(self.port_option.clone()).ok()?;
+
+        let differences = port_conf.differences(active);
+        if differences.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "the {} changed since the continuous quarry started on {}, stop it \
+            to send one-shot requests",
+            differences.join(", "),
+            active
+        ))
+    }
+
+    /// Red line naming every port option that can't be used yet, empty if
+    /// all of them are valid
+    fn port_option_errors(&self) -> Element<'_, Message> {
+        let mut errors = self
+            .port_option
+            .field_errors()
+            .into_iter()
+            .map(|(field, message)| format!("{}: {}", field, message))
+            .collect::<Vec<_>>();
+        errors.extend(self.port_conflict());
+
+        if errors.is_empty() {
+            return Space::with_height(Length::Units(0)).into();
+        }
+
+        Container::new(
+            Text::new(errors.join(", "))
+                .size(TextSize::SMALL)
+                .style(Color::from_rgb(0.8, 0.2, 0.2)),
+        )
+        .padding([0, 10])
+        .into()
+    }
+
+    /// Outcome of the last Test Connection, green if it passed
+    fn connection_status_view(&self) -> Element<'_, Message> {
+        let (status, color) = match &self.connection_status {
+            None => return Space::with_height(Length::Units(0)).into(),
+            Some(Ok(status)) => (status.clone(), Color::from_rgb(0.2, 0.7, 0.2)),
+            Some(Err(e)) => (
+                format!("Connection test failed: {}", e.message()),
+                Color::from_rgb(0.8, 0.2, 0.2),
+            ),
+        };
+
+        Container::new(Text::new(status).size(TextSize::SMALL).style(color))
+            .padding([0, 10])
+            .into()
+    }
+
+    /// Available serial ports, including the simulator port if it's running
+    fn list_ports(&self) -> Vec<String> {
+        let mut ports = serialport::available_ports()
+            .unwrap()
+            .into_iter()
+            .map(|port| port.port_name)
+            .collect::<Vec<_>>();
+
+        if let Some(port_name) = self.simulator.port_name() {
+            ports.push(port_name.clone());
+        }
+
+        ports
+    }
+
+    /// Ports for the port picker, recently used ones first. Recent ports that
+    /// are unplugged are listed last and can't be picked
+    fn port_choices(&self) -> Vec<String> {
+        let available =
+            |name: &String| self.available_ports.iter().any(|port| port == name);
+
+        self.recent_ports
+            .iter()
+            .filter(|name| available(name))
+            .chain(
+                self.available_ports
+                    .iter()
+                    .filter(|name| !self.recent_ports.contains(name)),
+            )
+            .cloned()
+            .chain(
+                self.recent_ports
+                    .iter()
+                    .filter(|name| !available(name))
+                    .map(|name| format!("{} (unplugged)", name)),
+            )
+            .collect()
+    }
+
+    /// Show which op rows have a one-shot request queued or in progress
+    fn mark_in_flight(&mut self) {
+        let names = self.one_shot_queue.active_names();
+        self.one_shot_ops.mark_in_flight(&names);
+        self.continuous_ops.mark_in_flight(&names);
+    }
+
+    /// Send the one-shot request the queue started
+    fn send_one_shot(&self, op_view: OpView) -> Command<Message> {
+        Command::perform(
+            one_shot_quarry(
+                op_view,
+                self.port_option.clone(),
+                self.port_thread_sender.clone().unwrap(),
+                self.one_shot_cancel.clone(),
+            ),
+            Message::OneShotResponse,
+        )
+    }
+
+    /// Log a fired rule and send its target operation as a one shot quarry
+    fn trigger_rule(&mut self, rule: Rule) -> Command<Message> {
+        let target = self
+            .one_shot_ops
+            .iter()
+            .chain(self.continuous_ops.iter())
+            .find(|op| op.name == rule.target_op)
+            .cloned();
+
+        match target {
+            Some(op_view) => {
+                self.responses.update(ResponseViewMessage::AddLog(format!(
+                    "Rule triggered: {}",
+                    rule
+                )));
+
+                self.update(Message::OneShotQuarry(op_view))
+            }
+            None => {
+                self.responses.update(ResponseViewMessage::AddLog(format!(
+                    "Rule triggered: {}, but no operation named \"{}\" exists",
+                    rule, rule.target_op
+                )));
+
+                Command::none()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_v1_layout() {
+        // as saved before the layout had a version
+        let v1 = r#"(
+            one_shot_ops: (ops: [
+                (
+                    name: "setpoint",
+                    op_type: WriteSingle,
+                    op_addr: "0x10",
+                    op_val: "21.5",
+                    eval_str: "val * 10",
+                ),
+            ]),
+            continuous_ops: (ops: [
+                (
+                    name: "temp",
+                    op_type: ReadSingle,
+                    op_addr: "0x20",
+                    op_val: "",
+                    eval_str: "val / 10",
+                ),
+            ]),
+        )"#;
+
+        let mut app = ron::from_str::<App>(v1).unwrap();
+        assert_eq!(app.version, 1);
+        app.migrate();
+        assert_eq!(app.version, LAYOUT_VERSION);
+
+        let setpoint = &app.one_shot_ops[0];
+        assert_eq!(setpoint.write_eval, "val * 10");
+        assert_eq!(setpoint.read_eval, "val");
+        let temp = &app.continuous_ops[0];
+        assert_eq!(temp.read_eval, "val / 10");
+        assert_eq!(temp.write_eval, "val");
+
+        // a current layout is left alone
+        app.one_shot_ops.migrate(LAYOUT_VERSION);
+        assert_eq!(app.one_shot_ops[0].write_eval, "val * 10");
+    }
+}
@@ -1,19 +1,278 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use meval::Expr;
+use meval::{Context, Expr};
+
+use serde::{Deserialize, Serialize};
 
 use string_to_num::ParseNum;
 
 use crate::error::*;
-use crate::ops::*;
-use crate::port_op::PortConfig;
+use crate::modbus::{
+    bare_frame, custom_frame, read_device_id_frame, read_file_record_frame,
+    write_multiple_frame, RequestFrame,
+};
+use crate::transport::PortConfig;
+
+/// Type of available operations without operation info
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum OpType {
+    ReadSingle,
+    WriteSingle,
+    ReadSingleRO,
+    /// Get Comm Event Counter diagnostic
+    CommEventCounter,
+    /// Read Exception Status diagnostic
+    ReadExceptionStatus,
+    /// Diagnostics echo test, the value is the pattern to echo
+    Loopback,
+    /// Write Single Coil, any non-zero value switches the coil on
+    WriteCoil,
+    /// Read File Record, reads one record of a device file
+    ReadFileRecord,
+    /// Read Device Identification, reads the vendor and product strings
+    ReadDeviceId,
+    /// Write Multiple Registers, writes a block of raw register values
+    WriteMultiple,
+    /// A function code the tester doesn't know, e.g. vendor specific, sent
+    /// with a raw payload
+    Custom,
+}
+
+impl OpType {
+    /// Whether the op addresses a register, diagnostics don't and have no
+    /// address, value or conversion
+    pub fn has_address(&self) -> bool {
+        matches!(
+            self,
+            OpType::ReadSingle
+                | OpType::WriteSingle
+                | OpType::ReadSingleRO
+                | OpType::WriteCoil
+                | OpType::WriteMultiple
+        )
+    }
+}
+
+impl Display for OpType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                OpType::ReadSingle => {
+                    "Read Single"
+                }
+                OpType::WriteSingle => {
+                    "Write Single"
+                }
+                OpType::ReadSingleRO => {
+                    "Read Single RO"
+                }
+                OpType::CommEventCounter => {
+                    "Comm Event Counter"
+                }
+                OpType::ReadExceptionStatus => {
+                    "Read Exception Status"
+                }
+                OpType::Loopback => {
+                    "Loopback Test"
+                }
+                OpType::WriteCoil => {
+                    "Write Coil"
+                }
+                OpType::ReadFileRecord => {
+                    "Read File Record"
+                }
+                OpType::ReadDeviceId => {
+                    "Read Device ID"
+                }
+                OpType::WriteMultiple => {
+                    "Write Multiple"
+                }
+                OpType::Custom => {
+                    "Custom Function"
+                }
+            }
+        )
+    }
+}
+
+/// Conformity level of a Read Device Identification request, which objects
+/// the device returns
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum DeviceIdLevel {
+    /// Vendor name, product code and revision
+    #[default]
+    Basic,
+    /// The basic objects plus vendor URL, product, model and application
+    /// names
+    Regular,
+}
+
+impl DeviceIdLevel {
+    /// Read device ID code of the request
+    pub fn read_code(&self) -> u8 {
+        match self {
+            DeviceIdLevel::Basic => 0x01,
+            DeviceIdLevel::Regular => 0x02,
+        }
+    }
+}
+
+impl Display for DeviceIdLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DeviceIdLevel::Basic => "Basic",
+                DeviceIdLevel::Regular => "Regular",
+            }
+        )
+    }
+}
+
+/// How the registers returned by a read are interpreted
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum DataType {
+    #[default]
+    U16,
+    Ascii,
+    Bcd,
+    /// Four registers combined into one 64-bit value
+    U64,
+    I64,
+    F64,
+    /// A date and time spread over registers, see `DateTimeLayout`
+    DateTime,
+}
+
+impl DataType {
+    /// Whether values of this type span four registers
+    pub fn is_64_bit(&self) -> bool {
+        matches!(self, DataType::U64 | DataType::I64 | DataType::F64)
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DataType::U16 => "U16",
+                DataType::Ascii => "ASCII",
+                DataType::Bcd => "BCD",
+                DataType::U64 => "U64",
+                DataType::I64 => "I64",
+                DataType::F64 => "F64",
+                DataType::DateTime => "Date/Time",
+            }
+        )
+    }
+}
+
+/// How read values are shown after the read conversion, on top of the op's
+/// precision
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum ValueFormat {
+    #[default]
+    Plain,
+    /// A fraction shown as a percentage, `0.25` as `25%`
+    Percent,
+    Scientific,
+    /// Digits grouped by thousands, `1234567` as `1,234,567`
+    Thousands,
+}
+
+impl Display for ValueFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ValueFormat::Plain => "Plain",
+                ValueFormat::Percent => "Percent",
+                ValueFormat::Scientific => "Scientific",
+                ValueFormat::Thousands => "Thousands",
+            }
+        )
+    }
+}
+
+/// How a `DateTime` read packs the date and time into its registers, as
+/// meters commonly expose their clock
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum DateTimeLayout {
+    /// Three registers of packed BCD, `YYMM DDhh mmss`
+    #[default]
+    BcdYearFirst,
+    /// Three registers of packed BCD, `ssmm hhDD MMYY`
+    BcdSecondFirst,
+    /// Three registers of one binary byte per field, `YY MM`, `DD hh`,
+    /// `mm ss`
+    Bytes,
+    /// Six registers of one binary field each, from the full year to the
+    /// second
+    Words,
+}
+
+impl DateTimeLayout {
+    /// Registers a read of this layout fetches
+    pub fn registers(&self) -> u16 {
+        match self {
+            DateTimeLayout::Words => 6,
+            DateTimeLayout::BcdYearFirst
+            | DateTimeLayout::BcdSecondFirst
+            | DateTimeLayout::Bytes => 3,
+        }
+    }
+}
+
+impl Display for DateTimeLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DateTimeLayout::BcdYearFirst => "BCD YYMM DDhh mmss",
+                DateTimeLayout::BcdSecondFirst => "BCD ssmm hhDD MMYY",
+                DateTimeLayout::Bytes => "Bytes YY MM DD hh mm ss",
+                DateTimeLayout::Words => "Words Y M D h m s",
+            }
+        )
+    }
+}
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Request {
     ReadSingle(u16),
     WriteSingle(u16, f64, u16),
     ReadSingleRO(u16),
+    /// Get Comm Event Counter, reads the status word and event count
+    CommEventCounter,
+    /// Read Exception Status, reads one byte of device defined status
+    ReadExceptionStatus,
+    /// Diagnostics Return Query Data, the device echoes the pattern back
+    Loopback(u16),
+    /// Write Single Coil, `true` switches the coil on
+    WriteCoil(u16, bool),
+    /// Read File Record of file number, record number and record length in
+    /// registers
+    ReadFileRecord(u16, u16, u16),
+    /// Read Device Identification of every object of a conformity level
+    ReadDeviceId(DeviceIdLevel),
+    /// Write Multiple Registers from an address, with the register count. The
+    /// values are kept by the operation, see `Operation::request_bytes`
+    WriteMultiple(u16, u16),
+    /// Function code with an optional register address and quantity, sent in
+    /// that order before the payload. The payload is kept by the operation,
+    /// see `Operation::request_bytes`
+    Custom(u8, Option<u16>, Option<u16>),
 }
 
 impl Request {
@@ -22,127 +281,1243 @@ impl Request {
             Request::ReadSingle(_) => "ReadSingle".to_string(),
             Request::WriteSingle(_, _, _) => "WriteSingle".to_string(),
             Request::ReadSingleRO(_) => "ReadSingleRO".to_string(),
+            Request::CommEventCounter => "CommEventCounter".to_string(),
+            Request::ReadExceptionStatus => "ReadExceptionStatus".to_string(),
+            Request::Loopback(_) => "Loopback".to_string(),
+            Request::WriteCoil(_, _) => "WriteCoil".to_string(),
+            Request::ReadFileRecord(_, _, _) => "ReadFileRecord".to_string(),
+            Request::ReadDeviceId(_) => "ReadDeviceId".to_string(),
+            Request::WriteMultiple(_, _) => "WriteMultiple".to_string(),
+            Request::Custom(_, _, _) => "Custom".to_string(),
+        }
+    }
+
+    /// Register address the request targets, `None` for diagnostics that
+    /// don't address a register
+    pub fn addr(&self) -> Option<u16> {
+        match *self {
+            Request::ReadSingle(addr)
+            | Request::WriteSingle(addr, _, _)
+            | Request::ReadSingleRO(addr)
+            | Request::WriteCoil(addr, _)
+            | Request::WriteMultiple(addr, _) => Some(addr),
+            Request::Custom(_, addr, _) => addr,
+            Request::CommEventCounter
+            | Request::ReadExceptionStatus
+            | Request::Loopback(_)
+            | Request::ReadFileRecord(_, _, _)
+            | Request::ReadDeviceId(_) => None,
+        }
+    }
+
+    pub fn is_read(&self) -> bool {
+        matches!(self, Request::ReadSingle(_) | Request::ReadSingleRO(_))
+    }
+
+    /// The RTU frame that performs this request on device `device_addr`,
+    /// `None` if the request has no address and value body, or a body of its
+    /// own
+    pub fn to_frame(&self, device_addr: u8) -> Option<RequestFrame> {
+        let (addr, value) = match *self {
+            Request::ReadSingle(addr) | Request::ReadSingleRO(addr) => (addr, 1),
+            Request::WriteSingle(addr, _original, val) => (addr, val),
+            // sub-function 0x0000 is Return Query Data
+            Request::Loopback(pattern) => (0x0000, pattern),
+            Request::WriteCoil(addr, on) => {
+                (addr, if on { 0xFF00 } else { 0x0000 })
+            }
+            Request::CommEventCounter
+            | Request::ReadExceptionStatus
+            | Request::ReadFileRecord(_, _, _)
+            | Request::ReadDeviceId(_)
+            | Request::WriteMultiple(_, _)
+            | Request::Custom(_, _, _) => return None,
+        };
+
+        Some(RequestFrame {
+            device_addr,
+            function: self.function(),
+            addr,
+            value,
+        })
+    }
+
+    /// Encoded request for device `device_addr`, the values of a Write
+    /// Multiple are all zero and a custom function has no payload since the
+    /// request doesn't hold them
+    pub fn to_bytes(&self, device_addr: u8) -> Vec<u8> {
+        if let Request::ReadFileRecord(file, record, record_len) = *self {
+            return read_file_record_frame(
+                device_addr,
+                file,
+                record,
+                record_len,
+            )
+            .to_vec();
+        }
+        if let Request::WriteMultiple(addr, quantity) = *self {
+            return write_multiple_frame(
+                device_addr,
+                addr,
+                &vec![0; quantity as usize],
+            );
+        }
+        if let Request::ReadDeviceId(level) = *self {
+            return read_device_id_frame(device_addr, level.read_code())
+                .to_vec();
+        }
+        if let Request::Custom(function, addr, quantity) = *self {
+            return custom_frame(
+                device_addr,
+                function,
+                &custom_header(addr, quantity),
+            );
+        }
+
+        match self.to_frame(device_addr) {
+            Some(frame) => frame.to_bytes().to_vec(),
+            None => bare_frame(device_addr, self.function()).to_vec(),
+        }
+    }
+
+    /// Function code of the request
+    pub fn function(&self) -> u8 {
+        match self {
+            Request::ReadSingle(_) => 0x03,
+            Request::WriteSingle(_, _, _) => 0x06,
+            Request::ReadSingleRO(_) => 0x04,
+            Request::CommEventCounter => 0x0B,
+            Request::ReadExceptionStatus => 0x07,
+            Request::Loopback(_) => 0x08,
+            Request::WriteCoil(_, _) => 0x05,
+            Request::ReadFileRecord(_, _, _) => 0x14,
+            Request::ReadDeviceId(_) => 0x2B,
+            Request::WriteMultiple(_, _) => 0x10,
+            Request::Custom(function, _, _) => *function,
+        }
+    }
+}
+
+/// Put a `,` between every three digits of the whole part of a formatted
+/// number, anything that isn't a plain decimal number is left as it is
+fn group_thousands(num: &str) -> String {
+    let (sign, unsigned) = match num.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", num),
+    };
+    let split = unsigned.find('.').unwrap_or(unsigned.len());
+    let (whole, fraction) = unsigned.split_at(split);
+    if whole.is_empty() || !whole.chars().all(|c| c.is_ascii_digit()) {
+        return num.to_string();
+    }
+
+    let mut grouped = String::new();
+    for (idx, digit) in whole.chars().enumerate() {
+        if idx > 0 && (whole.len() - idx) % 3 == 0 {
+            grouped.push(',');
         }
+        grouped.push(digit);
     }
+
+    format!("{}{}{}", sign, grouped, fraction)
+}
+
+/// Address and quantity of a custom function request, big endian, whichever
+/// of them is set
+fn custom_header(addr: Option<u16>, quantity: Option<u16>) -> Vec<u8> {
+    addr.into_iter().chain(quantity).flat_map(u16::to_be_bytes).collect()
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Operation {
     pub name: String,
     pub req: Request,
-    eval_str: String,
+    /// Converts raw register values read from the device.
+    /// Parsed once on creation, responses are evaluated against it directly.
+    /// The bound closure from `Expr::bind` holds an `Rc` context so it can't
+    /// travel with the operation between threads
+    pub read_eval: Arc<Expr>,
+    /// `read_eval` uses `next`, so reads fetch the register after the
+    /// operation's address as well
+    pub companion: bool,
+    /// `read_eval` uses `prev` or `dt`, so it has no value before a previous
+    /// sample of the operation arrived
+    pub uses_prev: bool,
+    /// The written value was saturated to fit in a register
+    pub clamped: bool,
+    /// Why the write conversion looks wrong, see `check_write_eval`. The op
+    /// can still be sent
+    pub write_warning: Option<String>,
+    /// Labels shown next to matching read values
+    pub labels: Arc<Vec<(f64, String)>>,
+    /// Raw register values a Write Multiple writes
+    pub write_values: Arc<Vec<u16>>,
+    /// Bytes a custom function sends after its address and quantity
+    pub payload: Arc<Vec<u8>>,
+    pub data_type: DataType,
+    /// How a `DateTime` read packs its registers
+    pub date_time_layout: DateTimeLayout,
+    /// Registers to read for types without a fixed size
+    pub quantity: u16,
+    pub byte_swap: bool,
+    /// 64-bit values start with the least significant register
+    pub word_swap: bool,
+    /// Start bit and width of the field extracted from the read value
+    pub bit_field: Option<(u16, u16)>,
+    /// Width of a two's complement value in the (extracted) register, the
+    /// value is unsigned if not set
+    pub signed_bits: Option<u16>,
+    /// Read values are multiplied by this after `read_eval`, for registers
+    /// documented as e.g. "value x 0.1"
+    pub scale: f64,
+    /// Added to read values after `scale`
+    pub offset: f64,
+    /// Read timeout override, the port default applies if not set
+    pub timeout: Option<Duration>,
+    /// Device address override, the port's address applies if not set
+    pub device_addr: Option<u8>,
+    /// Wait between the request and reading the response, overrides the
+    /// port's delay
+    pub read_delay: Option<Duration>,
+    /// Only read in the first cycle of a continuous quarry, for constants
+    /// such as serial numbers
+    pub read_once: bool,
+    /// Decimal places read values are shown with, all of them if not set
+    pub precision: Option<usize>,
+    /// How read values are shown, see `Operation::format_display`
+    pub value_format: ValueFormat,
+    /// Shown after read values, e.g. `°C`, empty for none
+    pub unit: String,
+    /// Continuous values below this raise an alarm
+    pub alarm_low: Option<f64>,
+    /// Continuous values above this raise an alarm
+    pub alarm_high: Option<f64>,
 }
 
-impl TryFrom<OpView> for Operation {
-    type Error = Error;
+/// Sent by loopback tests without a pattern, alternating bits catch stuck lines
+pub const DEFAULT_LOOPBACK_PATTERN: u16 = 0xA537;
 
-    fn try_from(value: OpView) -> Result<Self, Self::Error> {
-        let eval_func = match Expr::from_str(&value.eval_str) {
-            Ok(eval) => match eval.bind("val") {
-                Ok(func) => func,
-                Err(_) => {
-                    return Err(Error::with_message(
-                        ErrKind::MathOperationParseError,
-                        "Expression must contain \"val\"".to_string(),
-                    ))
-                }
-            },
-            Err(_) => {
-                return Err(Error::with_message(
-                    ErrKind::MathOperationParseError,
-                    format!(
-                        "Could not parse \"{}\" into valid math expression",
-                        value.eval_str
-                    ),
-                ))
+/// Registers a single read request may ask for
+pub const MAX_READ_QUANTITY: u16 = 125;
+/// Registers a single Write Multiple request may carry
+pub const MAX_WRITE_QUANTITY: usize = 123;
+/// Coils or discrete inputs a single read request may ask for
+pub const MAX_READ_BITS: u16 = 2000;
+/// Coils a single Write Multiple Coils request may carry
+pub const MAX_WRITE_BITS: u16 = 1968;
+/// Bytes after the function code of an RTU frame, which is at most 256 bytes
+/// with the device address, function code and CRC
+pub const MAX_PDU_DATA: usize = 252;
+
+/// Most items a single request of `function` may carry and what they are,
+/// per the Modbus application protocol specification. Functions that aren't
+/// listed carry a fixed quantity
+pub fn quantity_limit(function: u8) -> Option<(u16, &'static str)> {
+    match function {
+        0x01 => Some((MAX_READ_BITS, "coils")),
+        0x02 => Some((MAX_READ_BITS, "discrete inputs")),
+        0x03 | 0x04 => Some((MAX_READ_QUANTITY, "registers")),
+        0x0F => Some((MAX_WRITE_BITS, "coils")),
+        0x10 => Some((MAX_WRITE_QUANTITY as u16, "registers")),
+        _ => None,
+    }
+}
+
+/// Variables available to read conversion expressions, `val` is the register
+/// value, `addr` the register address, `dev` the responding device address,
+/// `prev` the previous sample's `val`, `dt` the seconds since that sample and
+/// `next` the value of the register after `addr`. `next` stays last, see
+/// `Operation::companion`
+pub const READ_VARS: &[&str] = &["val", "addr", "dev", "prev", "dt", "next"];
+
+/// Variables available to write conversion expressions, `val` is the entered
+/// value and `addr` the register address
+pub const WRITE_VARS: &[&str] = &["val", "addr"];
+
+/// Parse a conversion expression that may only use the variables in `vars`
+pub fn parse_eval(eval_str: &str, vars: &[&str]) -> Result<Expr, Error> {
+    let eval = match Expr::from_str(eval_str) {
+        Ok(eval) => eval,
+        Err(_) => {
+            return Err(Error::with_message(
+                ErrKind::MathOperationParseError,
+                format!(
+                    "Could not parse \"{}\" into valid math expression",
+                    eval_str
+                ),
+            ))
+        }
+    };
+
+    if eval.clone().bindn(vars).is_err() {
+        return Err(Error::with_message(
+            ErrKind::MathOperationParseError,
+            format!(
+                "\"{}\" may only use the variables {}",
+                eval_str,
+                vars.join(", ")
+            ),
+        ));
+    }
+
+    Ok(eval)
+}
+
+/// Why the write conversion `eval` of a register at `addr` can't be undone
+/// by a read conversion, `None` if nothing is obviously wrong. A conversion
+/// that ignores `val` or isn't monotonic writes the same raw value for
+/// different entered values, which usually means the read conversion was
+/// copied into it
+pub fn check_write_eval(
+    eval: &Expr,
+    eval_str: &str,
+    addr: u16,
+) -> Option<String> {
+    let hint = "the write conversion should undo the read conversion, e.g. \
+        val * 10 for a read conversion of val / 10";
+    if eval.clone().bindn(&WRITE_VARS[1..]).is_ok() {
+        return Some(format!(
+            "Write conversion \"{}\" doesn't use val, every value writes the \
+            same raw value, {}",
+            eval_str, hint
+        ));
+    }
+
+    // unwrap because parse_eval already checked the binding
+    let func = eval.clone().bind2("val", "addr").unwrap();
+    // values outside the conversion's domain evaluate to NaN and are skipped
+    let samples = (-64..=64)
+        .map(|step| func(step as f64 * 1024f64, addr as f64))
+        .filter(|raw| raw.is_finite())
+        .collect::<Vec<_>>();
+    let rising = samples.windows(2).all(|pair| pair[0] < pair[1]);
+    let falling = samples.windows(2).all(|pair| pair[0] > pair[1]);
+
+    if rising || falling {
+        None
+    } else {
+        Some(format!(
+            "Write conversion \"{}\" isn't monotonic, different values write \
+            the same raw value, {}",
+            eval_str, hint
+        ))
+    }
+}
+
+/// Parse `value=label` pairs separated by `;`
+pub fn parse_labels(labels: &str) -> Result<Vec<(f64, String)>, Error> {
+    labels
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let parsed = entry.split_once('=').and_then(|(val, label)| {
+                Some((val.trim().parse_num::<f64>().ok()?, label.trim()))
+            });
+
+            match parsed {
+                Some((val, label)) => Ok((val, label.to_string())),
+                None => Err(Error::with_message(
+                    ErrKind::RequestParseError,
+                    format!("\"{}\" is not a valid value=label pair", entry),
+                )),
             }
+        })
+        .collect()
+}
+
+/// Parse a bit field, an empty start means no bit field and an empty width
+/// means a single bit
+pub fn parse_bit_field(
+    start: &str,
+    width: &str,
+) -> Result<Option<(u16, u16)>, Error> {
+    if start.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let start_bit = start.parse_num::<u16>().ok().filter(|start| *start < 16);
+    let width_bits = if width.trim().is_empty() {
+        Some(1)
+    } else {
+        width.parse_num::<u16>().ok()
+    };
+
+    match (start_bit, width_bits) {
+        (Some(start), Some(width)) if (1..=16 - start).contains(&width) => {
+            Ok(Some((start, width)))
+        }
+        _ => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "Bit {} with width {} does not fit in a register",
+                start, width
+            ),
+        )),
+    }
+}
+
+/// Parse the width of a signed value, empty means the value is unsigned
+pub fn parse_signed_bits(width: &str) -> Result<Option<u16>, Error> {
+    if width.trim().is_empty() {
+        return Ok(None);
+    }
+
+    match width.parse_num::<u16>() {
+        Ok(width) if (1..=16).contains(&width) => Ok(Some(width)),
+        _ => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("\"{}\" is not a valid signed width in [1, 16]", width),
+        )),
+    }
+}
+
+/// Parse a timeout in milliseconds, empty means no override
+pub fn parse_timeout(timeout_ms: &str) -> Result<Option<Duration>, Error> {
+    if timeout_ms.trim().is_empty() {
+        return Ok(None);
+    }
+
+    match timeout_ms.parse_num::<u64>() {
+        Ok(ms) if ms > 0 => Ok(Some(Duration::from_millis(ms))),
+        _ => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("\"{}\" is not a valid timeout in ms", timeout_ms),
+        )),
+    }
+}
+
+/// Parse a delay in milliseconds, empty means no override
+pub fn parse_delay(delay_ms: &str) -> Result<Option<Duration>, Error> {
+    if delay_ms.trim().is_empty() {
+        return Ok(None);
+    }
+
+    match delay_ms.parse_num::<u64>() {
+        Ok(ms) => Ok(Some(Duration::from_millis(ms))),
+        Err(_) => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("\"{}\" is not a valid delay in ms", delay_ms),
+        )),
+    }
+}
+
+/// Parse a block of register values separated by commas, semicolons or
+/// whitespace, e.g. pasted from a CSV. Values are decimal or `0x` hex, or all
+/// hex if `hex` is set. Every value that doesn't parse is reported with its
+/// position
+pub fn parse_register_block(block: &str, hex: bool) -> Result<Vec<u16>, Error> {
+    let mut values = vec![];
+    let mut invalid = vec![];
+    for (idx, token) in block
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .enumerate()
+    {
+        let value = if hex {
+            let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+            u16::from_str_radix(digits, 16).ok()
+        } else {
+            token.parse_num::<u16>().ok()
         };
 
-        let op_addr = match value.op_addr.parse_num::<u16>() {
-            Ok(addr) => addr,
-            Err(_) => {
-                return Err(Error::with_message(
+        match value {
+            Some(value) => values.push(value),
+            None => invalid.push(format!("#{} \"{}\"", idx + 1, token)),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("Invalid register values: {}", invalid.join(", ")),
+        ));
+    }
+    if !(1..=MAX_WRITE_QUANTITY).contains(&values.len()) {
+        return Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "Write Multiple takes 1 to {} values, got {}",
+                MAX_WRITE_QUANTITY,
+                values.len()
+            ),
+        ));
+    }
+
+    Ok(values)
+}
+
+/// Characters a numeric text input accepts, see `mask_input`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InputMask {
+    /// Whole numbers, decimal or `0x` hex
+    Integer,
+    /// Numbers with a sign, fraction or exponent as well
+    Number,
+    /// Numbers separated by commas, semicolons or whitespace
+    List,
+}
+
+impl InputMask {
+    fn accepts(self, c: char) -> bool {
+        let integer = c.is_ascii_hexdigit() || c == 'x' || c == 'X';
+        let number = integer || c == '-' || c == '+' || c == '.';
+        match self {
+            InputMask::Integer => integer,
+            InputMask::Number => number,
+            InputMask::List => {
+                number || c == ',' || c == ';' || c.is_ascii_whitespace()
+            }
+        }
+    }
+}
+
+/// Split what was typed or pasted into a numeric input into the characters
+/// it keeps and those it drops. Only characters that can never be part of a
+/// valid value are dropped, so incomplete input such as a lone `0x` or `-`
+/// is kept while typing
+pub fn mask_input(input: &str, mask: InputMask) -> (String, String) {
+    input.chars().partition(|&c| mask.accepts(c))
+}
+
+/// Parse the 64-bit values of a Write Multiple, separated like
+/// `parse_register_block`. Values are returned as the bits of `data_type`,
+/// each takes four registers
+pub fn parse_u64_block(
+    block: &str,
+    data_type: DataType,
+) -> Result<Vec<u64>, Error> {
+    let mut values = vec![];
+    let mut invalid = vec![];
+    for (idx, token) in block
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .enumerate()
+    {
+        let value = match data_type {
+            DataType::I64 => token.parse_num::<i64>().ok().map(|val| val as u64),
+            DataType::F64 => token.parse::<f64>().ok().map(f64::to_bits),
+            _ => token.parse_num::<u64>().ok(),
+        };
+
+        match value {
+            Some(value) => values.push(value),
+            None => invalid.push(format!("#{} \"{}\"", idx + 1, token)),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("Invalid {} values: {}", data_type, invalid.join(", ")),
+        ));
+    }
+    if !(1..=MAX_WRITE_QUANTITY / 4).contains(&values.len()) {
+        return Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "Write Multiple takes 1 to {} {} values, got {}",
+                MAX_WRITE_QUANTITY / 4,
+                data_type,
+                values.len()
+            ),
+        ));
+    }
+
+    Ok(values)
+}
+
+/// Registers a Write Multiple sends for `values`, the inverse of how a read
+/// of `data_type` decodes them. 64-bit values are split into four registers,
+/// least significant first with `word_swap`, other values are single
+/// registers. Every register has its bytes swapped with `byte_swap`
+pub fn encode_registers(
+    values: &[u64],
+    data_type: DataType,
+    byte_swap: bool,
+    word_swap: bool,
+) -> Vec<u16> {
+    let mut regs = vec![];
+    for &val in values {
+        if data_type.is_64_bit() {
+            let mut words = [48, 32, 16, 0].map(|shift| (val >> shift) as u16);
+            if word_swap {
+                words.reverse();
+            }
+            regs.extend_from_slice(&words);
+        } else {
+            regs.push(val as u16);
+        }
+    }
+
+    if byte_swap {
+        for reg in &mut regs {
+            *reg = reg.swap_bytes();
+        }
+    }
+
+    regs
+}
+
+/// Parse the payload of a custom function, hex bytes separated by whitespace,
+/// commas or semicolons, e.g. `01 A0 ff`. An `0x` prefix is allowed and an
+/// empty payload is valid. Every byte that doesn't parse is reported with its
+/// position
+pub fn parse_hex_bytes(payload: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    let mut invalid = vec![];
+    for (idx, token) in payload
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .enumerate()
+    {
+        let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+        match u8::from_str_radix(digits, 16) {
+            Ok(byte) if !digits.is_empty() => bytes.push(byte),
+            _ => invalid.push(format!("#{} \"{}\"", idx + 1, token)),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("Invalid payload bytes: {}", invalid.join(", ")),
+        ));
+    }
+
+    Ok(bytes)
+}
+
+/// Parse the low and high alarm limits, empty means no limit
+pub fn parse_alarm_limits(
+    low: &str,
+    high: &str,
+) -> Result<(Option<f64>, Option<f64>), Error> {
+    let parse = |limit: &str, what: &str| {
+        if limit.trim().is_empty() {
+            return Ok(None);
+        }
+
+        match limit.parse_num::<f64>() {
+            Ok(limit) => Ok(Some(limit)),
+            Err(_) => Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!("\"{}\" is not a valid {} alarm limit", limit, what),
+            )),
+        }
+    };
+
+    match (parse(low, "low")?, parse(high, "high")?) {
+        (Some(low), Some(high)) if low > high => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!("Low alarm limit {} is above the high limit {}", low, high),
+        )),
+        limits => Ok(limits),
+    }
+}
+
+/// Parse the linear scaling of read values, empty means a scale of 1 and an
+/// offset of 0
+pub fn parse_scaling(scale: &str, offset: &str) -> Result<(f64, f64), Error> {
+    let parse = |factor: &str, what: &str, default: f64| {
+        if factor.trim().is_empty() {
+            return Ok(default);
+        }
+
+        match factor.parse_num::<f64>() {
+            Ok(factor) if factor.is_finite() => Ok(factor),
+            _ => Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!("\"{}\" is not a valid {}", factor, what),
+            )),
+        }
+    };
+
+    Ok((parse(scale, "scale", 1f64)?, parse(offset, "offset", 0f64)?))
+}
+
+/// Parse the decimal places values are shown with, empty means all of them
+pub fn parse_precision(precision: &str) -> Result<Option<usize>, Error> {
+    if precision.trim().is_empty() {
+        return Ok(None);
+    }
+
+    match precision.parse_num::<u8>() {
+        Ok(places) if places <= 15 => Ok(Some(places as usize)),
+        _ => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "\"{}\" is not a valid number of decimal places in [0, 15]",
+                precision
+            ),
+        )),
+    }
+}
+
+/// Data table a PLC-style address refers to, named by its first digit
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PlcTable {
+    /// 0xxxx
+    Coil,
+    /// 1xxxx
+    DiscreteInput,
+    /// 3xxxx
+    InputRegister,
+    /// 4xxxx
+    HoldingRegister,
+}
+
+impl PlcTable {
+    /// The op type that accesses this table for an op of type `op_type`, the
+    /// address decides between holding and input register reads
+    pub fn op_type(self, op_type: OpType) -> Result<OpType, Error> {
+        match (op_type, self) {
+            (
+                OpType::ReadSingle | OpType::ReadSingleRO,
+                PlcTable::HoldingRegister,
+            ) => Ok(OpType::ReadSingle),
+            (
+                OpType::ReadSingle | OpType::ReadSingleRO,
+                PlcTable::InputRegister,
+            ) => Ok(OpType::ReadSingleRO),
+            (OpType::WriteSingle, PlcTable::HoldingRegister) => {
+                Ok(OpType::WriteSingle)
+            }
+            (OpType::WriteCoil, PlcTable::Coil) => Ok(OpType::WriteCoil),
+            (OpType::WriteMultiple, PlcTable::HoldingRegister) => {
+                Ok(OpType::WriteMultiple)
+            }
+            (OpType::ReadSingle | OpType::ReadSingleRO, _) => {
+                Err(Error::with_message(
                     ErrKind::RequestParseError,
-                    format!(
-                        "\"{}\" is no a valid register address",
-                        value.op_addr
+                    "Only 4xxxx and 3xxxx registers can be read".to_string(),
+                ))
+            }
+            (OpType::WriteCoil, _) => Err(Error::with_message(
+                ErrKind::RequestParseError,
+                "Write Coil needs a 0xxxx coil address".to_string(),
+            )),
+            _ => Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!("{} needs a 4xxxx holding register address", op_type),
+            )),
+        }
+    }
+}
+
+/// Parse a PLC-style address, 5 digit (`40001`) or 6 digit (`400001`), into
+/// its table and zero-based protocol address. Shorter entries are 5 digit
+/// addresses with the leading zeros of the coil table dropped
+pub fn parse_plc_addr(addr: &str) -> Result<(PlcTable, u16), Error> {
+    let addr = addr.trim();
+    let invalid = |reason: &str| {
+        Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "PLC address \"{}\" {}, expected e.g. 40001 or 400001",
+                addr, reason
+            ),
+        )
+    };
+
+    if addr.is_empty() || !addr.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid("is not a number"));
+    }
+    if addr.len() > 6 {
+        return Err(invalid("has too many digits"));
+    }
+    let digits = format!("{:0>5}", addr);
+
+    let table = match &digits[..1] {
+        "0" => PlcTable::Coil,
+        "1" => PlcTable::DiscreteInput,
+        "3" => PlcTable::InputRegister,
+        "4" => PlcTable::HoldingRegister,
+        _ => return Err(invalid("doesn't start with 0, 1, 3 or 4")),
+    };
+
+    // unwrap because the digits were checked
+    match digits[1..].parse::<u32>().unwrap() {
+        offset @ 1..=0x10000 => Ok((table, (offset - 1) as u16)),
+        _ => Err(invalid("is out of range")),
+    }
+}
+
+/// Parse a number that must lie in `range`, `what` names it in the error
+pub fn parse_in_range(
+    val: &str,
+    what: &str,
+    range: RangeInclusive<u16>,
+) -> Result<u16, Error> {
+    match val.parse_num::<u16>() {
+        Ok(num) if range.contains(&num) => Ok(num),
+        _ => Err(Error::with_message(
+            ErrKind::RequestParseError,
+            format!(
+                "\"{}\" is not a valid {} in [{}, {}]",
+                val,
+                what,
+                range.start(),
+                range.end()
+            ),
+        )),
+    }
+}
+
+impl Operation {
+    /// An operation sending `req` that reads plain `U16` values without
+    /// conversion
+    pub fn new(name: String, req: Request) -> Self {
+        Self {
+            name,
+            req,
+            // unwrap because `val` is always a valid expression
+            read_eval: Arc::new(parse_eval("val", READ_VARS).unwrap()),
+            companion: false,
+            uses_prev: false,
+            clamped: false,
+            write_warning: None,
+            labels: Arc::new(Vec::new()),
+            write_values: Arc::new(Vec::new()),
+            payload: Arc::new(Vec::new()),
+            data_type: DataType::U16,
+            date_time_layout: DateTimeLayout::BcdYearFirst,
+            quantity: 1,
+            byte_swap: false,
+            word_swap: false,
+            bit_field: None,
+            signed_bits: None,
+            scale: 1f64,
+            offset: 0f64,
+            timeout: None,
+            device_addr: None,
+            read_delay: None,
+            read_once: false,
+            precision: None,
+            value_format: ValueFormat::Plain,
+            unit: String::new(),
+            alarm_low: None,
+            alarm_high: None,
+        }
+    }
+
+    /// Evaluate the read conversion expression for a response from device
+    /// `dev` carrying value `val` and companion register value `next`.
+    /// `prev` is the value of the previous sample and `dt` the seconds since
+    pub fn eval(
+        &self,
+        dev: u8,
+        val: f64,
+        next: f64,
+        (prev, dt): (f64, f64),
+    ) -> f64 {
+        thread_local! {
+            static BUILTIN: Context<'static> = Context::new();
+        }
+
+        let addr = self.req.addr().unwrap_or_default();
+
+        // self.read_eval was bound to READ_VARS in operation creation
+        // so here it is guaranteed to be valid
+        BUILTIN.with(|builtin| {
+            self.read_eval
+                .eval_with_context((
+                    ("val", val),
+                    (
+                        ("addr", addr as f64),
+                        (
+                            ("dev", dev as f64),
+                            (
+                                ("prev", prev),
+                                (("dt", dt), (("next", next), builtin)),
+                            ),
+                        ),
                     ),
                 ))
+                .unwrap()
+        })
+    }
+
+    /// Apply `scale` and `offset` to an evaluated read value
+    pub fn scale_value(&self, val: f64) -> f64 {
+        val * self.scale + self.offset
+    }
+
+    /// Extract the bit field from a read value, values pass through unchanged
+    /// if no bit field was set
+    pub fn extract_bits(&self, val: u16) -> u16 {
+        match self.bit_field {
+            Some((start, width)) => {
+                (val >> start) & (((1u32 << width) - 1) as u16)
             }
-        };
+            None => val,
+        }
+    }
 
-        let req = {
-            match value.op_type {
-                OpType::ReadSingle => Request::ReadSingle(op_addr),
-                OpType::WriteSingle => {
-                    let val = match value.op_val.parse_num::<f64>() {
-                        Ok(val) => val,
-                        Err(_) => {
-                            return Err(Error::with_message(
-                                ErrKind::RequestParseError,
-                                format!(
-                                    "\"{}\" is no a valid register value",
-                                    value.op_val
-                                ),
-                            ))
-                        }
-                    };
-
-                    let eval_val = eval_func(val).round();
-                    if eval_val < 0f64 || eval_val > u16::MAX as f64 {
-                        return Err(Error::with_message(
-                                ErrKind::MathOperationResultInOutOfRangeValue,
-                                format!("{} cannot be evaluated to a value in the range [0, 0xFFFF]", value.op_val))
-                            );
-                    }
-
-                    Request::WriteSingle(op_addr, val, eval_val as u16)
+    /// Read the low `signed_bits` bits of `val` as a two's complement value
+    pub fn sign_extend(&self, val: u16) -> f64 {
+        match self.signed_bits {
+            Some(width) => {
+                let shift = 16 - width;
+                ((val << shift) as i16 >> shift) as f64
+            }
+            None => val as f64,
+        }
+    }
+
+    /// Which alarm limit `val` is past, `None` if it's within both
+    pub fn alarm(&self, val: f64) -> Option<String> {
+        match (self.alarm_low, self.alarm_high) {
+            (Some(low), _) if val < low => {
+                Some(format!("below the low limit {}", low))
+            }
+            (_, Some(high)) if val > high => {
+                Some(format!("above the high limit {}", high))
+            }
+            _ => None,
+        }
+    }
+
+    /// The label for a read value, if one was set
+    pub fn label(&self, val: f64) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label_val, _)| *label_val == val)
+            .map(|(_, label)| label.as_str())
+    }
+
+    /// Number of registers a read of this operation fetches
+    pub fn read_quantity(&self) -> u16 {
+        match self.data_type {
+            DataType::Ascii => self.quantity,
+            DataType::U64 | DataType::I64 | DataType::F64 => 4,
+            DataType::DateTime => self.date_time_layout.registers(),
+            DataType::U16 | DataType::Bcd => {
+                if self.companion {
+                    2
+                } else {
+                    1
                 }
-                OpType::ReadSingleRO => Request::ReadSingleRO(op_addr),
             }
+        }
+    }
+
+    /// Check the request against the quantity limit of its function, a
+    /// device rejects an oversized request or the frame can't encode it
+    pub fn check_quantity(&self) -> Result<(), Error> {
+        let function = self.req.function();
+        let (limit, items) = match quantity_limit(function) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let quantity = match self.req {
+            Request::WriteMultiple(_, quantity) => quantity,
+            // the payload may be laid out differently than the standard
+            // function of the same code
+            Request::Custom(_, _, _) => return Ok(()),
+            _ => self.read_quantity(),
         };
 
-        Ok(Self { name: value.name, req, eval_str: value.eval_str })
+        if (1..=limit).contains(&quantity) {
+            Ok(())
+        } else {
+            Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!(
+                    "{} (function 0x{:02X}) takes 1 to {} {}, got {}",
+                    self.req.variant_string(),
+                    function,
+                    limit,
+                    items,
+                    quantity
+                ),
+            ))
+        }
     }
-}
 
-impl Operation {
-    pub fn get_eval(&self) -> Box<dyn Fn(f64) -> f64> {
-        // self.eval_str should have been checked in operation creation
-        // so here it is guaranteed to be valid
-        Box::new(Expr::from_str(&self.eval_str).unwrap().bind("val").unwrap())
+    /// A read value rounded to the op's precision, as logged and published
+    pub fn format_value(&self, val: f64) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, val),
+            None => val.to_string(),
+        }
     }
 
-    pub fn to_modbus_bytes(&self, port_conf: &PortConfig) -> [u8; 8] {
-        const CRC_GEN: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_MODBUS);
+    /// A read value as shown to the user, in the op's value format and
+    /// rounded to its precision, followed by its unit
+    pub fn format_display(&self, val: f64) -> String {
+        let shown = match self.value_format {
+            ValueFormat::Plain => self.format_value(val),
+            ValueFormat::Percent => {
+                let percent = val * 100f64;
+                // round away the float noise of scaling, e.g. 0.123 * 100
+                let percent = match self.precision {
+                    Some(_) => percent,
+                    None => (percent * 1e9).round() / 1e9,
+                };
+                format!("{}%", self.format_value(percent))
+            }
+            ValueFormat::Scientific => match self.precision {
+                Some(precision) => format!("{:.*e}", precision, val),
+                None => format!("{:e}", val),
+            },
+            ValueFormat::Thousands => group_thousands(&self.format_value(val)),
+        };
+
+        if self.unit.is_empty() {
+            shown
+        } else {
+            format!("{} {}", shown, self.unit)
+        }
+    }
 
-        let mut req_bytes: [u8; 8] =
-            [port_conf.device_addr, 0, 0, 0, 0, 0, 0, 0];
+    /// Address of the device this operation talks to
+    pub fn target_addr(&self, port_conf: &PortConfig) -> u8 {
+        self.device_addr.unwrap_or(port_conf.device_addr)
+    }
 
-        let (addr, val) = match self.req {
-            Request::ReadSingle(addr) => {
-                req_bytes[1] = 0x03;
-                (addr, 1)
+    /// Encoded request for device `device_addr`, with the values of a Write
+    /// Multiple or the payload of a custom function
+    pub fn request_bytes(&self, device_addr: u8) -> Vec<u8> {
+        match self.req {
+            Request::WriteMultiple(addr, _) => {
+                write_multiple_frame(device_addr, addr, &self.write_values)
             }
-            Request::WriteSingle(addr, _original, val) => {
-                req_bytes[1] = 0x06;
-                (addr, val)
+            Request::Custom(function, addr, quantity) => {
+                let mut data = custom_header(addr, quantity);
+                data.extend_from_slice(&self.payload);
+                custom_frame(device_addr, function, &data)
             }
-            Request::ReadSingleRO(addr) => {
-                req_bytes[1] = 0x04;
-                (addr, 1)
+            req => req.to_bytes(device_addr),
+        }
+    }
+
+    pub fn to_modbus_bytes(&self, port_conf: &PortConfig) -> Vec<u8> {
+        let device_addr = self.target_addr(port_conf);
+        match self.req.to_frame(device_addr) {
+            Some(mut frame) => {
+                if self.req.is_read() {
+                    frame.value = self.read_quantity();
+                }
+                port_conf.crc.reencode(frame.to_bytes().to_vec())
             }
+            None => port_conf.crc.reencode(self.request_bytes(device_addr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plc_addr_five_digits() {
+        assert_eq!(parse_plc_addr("40001"), Ok((PlcTable::HoldingRegister, 0)));
+        assert_eq!(parse_plc_addr("30010"), Ok((PlcTable::InputRegister, 9)));
+        assert_eq!(parse_plc_addr("10001"), Ok((PlcTable::DiscreteInput, 0)));
+        // leading zeros of coil addresses may be left out
+        assert_eq!(parse_plc_addr("00001"), Ok((PlcTable::Coil, 0)));
+        assert_eq!(parse_plc_addr("17"), Ok((PlcTable::Coil, 16)));
+    }
+
+    #[test]
+    fn plc_addr_six_digits() {
+        assert_eq!(parse_plc_addr("400001"), Ok((PlcTable::HoldingRegister, 0)));
+        assert_eq!(
+            parse_plc_addr("465536"),
+            Ok((PlcTable::HoldingRegister, 0xFFFF))
+        );
+        assert_eq!(parse_plc_addr("010001"), Ok((PlcTable::Coil, 10000)));
+    }
+
+    #[test]
+    fn plc_addr_rejects_invalid() {
+        assert!(parse_plc_addr("40000").is_err());
+        assert!(parse_plc_addr("465537").is_err());
+        assert!(parse_plc_addr("20001").is_err());
+        assert!(parse_plc_addr("4000001").is_err());
+        assert!(parse_plc_addr("40001.5").is_err());
+        assert!(parse_plc_addr("").is_err());
+    }
+
+    #[test]
+    fn mask_keeps_partial_numbers() {
+        assert_eq!(
+            mask_input("0x", InputMask::Integer),
+            ("0x".to_string(), String::new())
+        );
+        assert_eq!(
+            mask_input("12k3", InputMask::Integer),
+            ("123".to_string(), "k".to_string())
+        );
+        assert_eq!(
+            mask_input("-1.5", InputMask::Integer),
+            ("15".to_string(), "-.".to_string())
+        );
+        assert_eq!(
+            mask_input("-1.5e3", InputMask::Number),
+            ("-1.5e3".to_string(), String::new())
+        );
+        // pasted from a spreadsheet
+        assert_eq!(
+            mask_input("1,\t2;\n0x1F V", InputMask::List),
+            ("1,\t2;\n0x1F ".to_string(), "V".to_string())
+        );
+    }
+
+    #[test]
+    fn format_value_rounds_to_precision() {
+        let mut op = Operation::new("temp".to_string(), Request::ReadSingle(0));
+        assert_eq!(op.format_value(23.400000001), "23.400000001");
+
+        op.precision = parse_precision("1").unwrap();
+        assert_eq!(op.format_value(23.400000001), "23.4");
+        op.precision = parse_precision("0").unwrap();
+        assert_eq!(op.format_value(23.5), "24");
+
+        assert!(parse_precision("16").is_err());
+        assert!(parse_precision("-1").is_err());
+    }
+
+    #[test]
+    fn scaling_applies_after_eval() {
+        let mut op = Operation::new("temp".to_string(), Request::ReadSingle(0));
+        assert_eq!(op.scale_value(215f64), 215f64);
+
+        let (scale, offset) = parse_scaling("0.1", "-40").unwrap();
+        op.scale = scale;
+        op.offset = offset;
+        op.precision = Some(1);
+        assert_eq!(op.format_value(op.scale_value(615f64)), "21.5");
+        assert_eq!(parse_scaling(" ", ""), Ok((1f64, 0f64)));
+
+        assert!(parse_scaling("x", "").is_err());
+        assert!(parse_scaling("", "inf").is_err());
+    }
+
+    #[test]
+    fn format_display_in_value_format() {
+        let mut op = Operation::new("count".to_string(), Request::ReadSingle(0));
+        op.value_format = ValueFormat::Percent;
+        assert_eq!(op.format_display(0.123), "12.3%");
+        op.precision = Some(1);
+        assert_eq!(op.format_display(0.25), "25.0%");
+
+        op.value_format = ValueFormat::Scientific;
+        assert_eq!(op.format_display(1234.5), "1.2e3");
+        op.precision = None;
+        assert_eq!(op.format_display(1234.5), "1.2345e3");
+
+        op.value_format = ValueFormat::Thousands;
+        assert_eq!(op.format_display(1234567.25), "1,234,567.25");
+        assert_eq!(op.format_display(-1234f64), "-1,234");
+        assert_eq!(op.format_display(999f64), "999");
+        assert_eq!(op.format_display(f64::NAN), "NaN");
+
+        op.value_format = ValueFormat::Plain;
+        op.unit = "°C".to_string();
+        assert_eq!(op.format_display(21.5), "21.5 °C");
+        // logged values are never grouped
+        assert_eq!(op.format_value(1234f64), "1234");
+    }
+
+    #[test]
+    fn register_block_from_paste() {
+        assert_eq!(
+            parse_register_block("1, 2,0x1F\n300;\t4", false),
+            Ok(vec![1, 2, 0x1F, 300, 4])
+        );
+        assert_eq!(
+            parse_register_block("1A 0x2b ff", true),
+            Ok(vec![0x1A, 0x2B, 0xFF])
+        );
+
+        let err = parse_register_block("1, x, 3, 70000", false).unwrap_err();
+        assert!(err.message().contains("#2 \"x\""));
+        assert!(err.message().contains("#4 \"70000\""));
+        assert!(parse_register_block(" , ", false).is_err());
+    }
+
+    #[test]
+    fn write_eval_must_be_invertible() {
+        let check = |eval_str: &str| {
+            let eval = parse_eval(eval_str, WRITE_VARS).unwrap();
+            check_write_eval(&eval, eval_str, 0)
         };
 
-        req_bytes[2] = (addr >> 8) as u8;
-        req_bytes[3] = addr as u8;
-        req_bytes[4] = (val >> 8) as u8;
-        req_bytes[5] = val as u8;
+        assert_eq!(check("val * 10"), None);
+        assert_eq!(check("1000 - val / 2"), None);
+        // NaN outside the domain doesn't count
+        assert_eq!(check("sqrt(val)"), None);
+
+        assert!(check("42").unwrap().contains("doesn't use val"));
+        assert!(check("addr + 1").unwrap().contains("doesn't use val"));
+        assert!(check("val ^ 2").unwrap().contains("isn't monotonic"));
+        assert!(check("abs(val)").unwrap().contains("isn't monotonic"));
+    }
+
+    #[test]
+    fn hex_payload() {
+        assert_eq!(parse_hex_bytes(""), Ok(vec![]));
+        assert_eq!(
+            parse_hex_bytes("01 a0,0xFF;7"),
+            Ok(vec![0x01, 0xA0, 0xFF, 0x07])
+        );
+
+        let err = parse_hex_bytes("01 1FF 0x zz").unwrap_err();
+        assert!(err.message().contains("#2 \"1FF\""));
+        assert!(err.message().contains("#3 \"0x\""));
+        assert!(err.message().contains("#4 \"zz\""));
+    }
+
+    #[test]
+    fn alarm_past_limits() {
+        let mut op = Operation::new("temp".to_string(), Request::ReadSingle(0));
+        assert_eq!(op.alarm(1000.0), None);
+
+        (op.alarm_low, op.alarm_high) = parse_alarm_limits("", "80").unwrap();
+        assert_eq!(op.alarm(80.0), None);
+        assert!(op.alarm(80.5).is_some());
+        assert_eq!(op.alarm(-40.0), None);
+
+        (op.alarm_low, op.alarm_high) = parse_alarm_limits("-10", "80").unwrap();
+        assert!(op.alarm(-10.5).is_some());
+
+        assert!(parse_alarm_limits("90", "80").is_err());
+        assert!(parse_alarm_limits("hot", "").is_err());
+    }
+
+    #[test]
+    fn sign_extend_narrow_values() {
+        let mut op = Operation::new("adc".to_string(), Request::ReadSingle(0));
+        assert_eq!(op.sign_extend(0xFFF), 4095f64);
+
+        op.signed_bits = parse_signed_bits("12").unwrap();
+        assert_eq!(op.sign_extend(0xFFF), -1f64);
+        assert_eq!(op.sign_extend(0x800), -2048f64);
+        assert_eq!(op.sign_extend(0x7FF), 2047f64);
+        op.signed_bits = parse_signed_bits("16").unwrap();
+        assert_eq!(op.sign_extend(0x8000), -32768f64);
+
+        assert!(parse_signed_bits("0").is_err());
+        assert!(parse_signed_bits("17").is_err());
+    }
 
-        let crc = CRC_GEN.checksum(&req_bytes[..6]);
-        req_bytes[6] = crc as u8;
-        req_bytes[7] = (crc >> 8) as u8;
+    #[test]
+    fn quantity_limits_per_function() {
+        let mut op = Operation::new("block".to_string(), Request::ReadSingle(0));
+        op.data_type = DataType::Ascii;
+        op.quantity = MAX_READ_QUANTITY;
+        assert!(op.check_quantity().is_ok());
+        op.quantity = MAX_READ_QUANTITY + 1;
+        assert!(op.check_quantity().is_err());
 
-        req_bytes
+        let op = Operation::new(
+            "block".to_string(),
+            Request::WriteMultiple(0, MAX_WRITE_QUANTITY as u16 + 1),
+        );
+        assert!(op.check_quantity().is_err());
+        assert_eq!(quantity_limit(0x01), Some((2000, "coils")));
+        assert_eq!(quantity_limit(0x06), None);
     }
 }
@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+use iced::{
+    widget::{Row, Text},
+    Element, Length,
+};
+
+use string_to_num::ParseNum;
+
+use crate::port_op::{DataBits, Parity, PortOption, StopBits};
+use crate::response::Response;
+use crate::style::TextSize;
+
+/// Request rate, latency and bus load of the continuous quarry, computed
+/// once per tick over the responses received since the previous tick
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Latency and bytes on the bus of each response since the last tick
+    window: Vec<(Option<Duration>, usize)>,
+    last_tick: Option<Instant>,
+
+    /// Responses per second
+    rate: f64,
+    avg_latency: Option<Duration>,
+    /// Share of the bus' capacity used, in [0, 1] unless the estimate is off
+    utilization: f64,
+}
+
+/// Bits a byte takes on a serial line: start bit, the configured data bits,
+/// the parity bit and the stop bits
+fn bits_per_byte(port_option: &PortOption) -> f64 {
+    let parity = match port_option.parity {
+        Some(Parity::Odd) | Some(Parity::Even) => 1f64,
+        Some(Parity::None) | None => 0f64,
+    };
+    let stop = match port_option.stop_bits {
+        Some(StopBits::Two) => 2f64,
+        Some(StopBits::One) | None => 1f64,
+    };
+
+    let data = match port_option.data_bits {
+        DataBits::Seven => 7f64,
+        DataBits::Eight => 8f64,
+    };
+
+    1f64 + data + parity + stop
+}
+
+impl Metrics {
+    /// Start measuring from scratch, used when the continuous quarry starts
+    pub fn reset(&mut self) {
+        *self = Self { last_tick: Some(Instant::now()), ..Self::default() };
+    }
+
+    pub fn record(&mut self, response: &Response) {
+        self.window.push((response.latency(), response.wire_len()));
+    }
+
+    /// Compute the metrics over the window ending at `now`
+    pub fn tick(&mut self, now: Instant, port_option: &PortOption) {
+        let elapsed = self
+            .last_tick
+            .map_or(1f64, |last| now.duration_since(last).as_secs_f64());
+        self.last_tick = Some(now);
+        if elapsed <= 0f64 {
+            return;
+        }
+
+        self.rate = self.window.len() as f64 / elapsed;
+
+        let latencies: Vec<Duration> =
+            self.window.iter().filter_map(|(latency, _)| *latency).collect();
+        self.avg_latency = if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().sum::<Duration>() / latencies.len() as u32)
+        };
+
+        let bytes: usize = self.window.iter().map(|(_, len)| len).sum();
+        self.utilization = match port_option.baud.parse_num::<u32>() {
+            Ok(baud) if baud > 0 => {
+                bytes as f64 * bits_per_byte(port_option)
+                    / (baud as f64 * elapsed)
+            }
+            _ => 0f64,
+        };
+
+        self.window.clear();
+    }
+
+    pub fn view<'a, M: 'a>(&self) -> Element<'a, M> {
+        Row::new()
+            .width(Length::Fill)
+            .spacing(20)
+            .padding(5)
+            .push(
+                Text::new(format!("{:.1} req/s", self.rate))
+                    .size(TextSize::BODY),
+            )
+            .push(
+                Text::new(match self.avg_latency {
+                    Some(latency) => format!(
+                        "avg latency {:.1} ms",
+                        latency.as_secs_f64() * 1000f64
+                    ),
+                    None => "avg latency -".to_string(),
+                })
+                .size(TextSize::BODY),
+            )
+            .push(
+                Text::new(format!(
+                    "bus utilization {:.0}%",
+                    self.utilization * 100f64
+                ))
+                .size(TextSize::BODY),
+            )
+            .into()
+    }
+}
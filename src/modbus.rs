@@ -0,0 +1,517 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ErrKind, Error};
+
+const CRC_GEN: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_MODBUS);
+const CCITT_GEN: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
+
+/// Modbus CRC-16 of `bytes`
+pub fn checksum(bytes: &[u8]) -> u16 {
+    CRC_GEN.checksum(bytes)
+}
+
+/// Checksum at the end of every frame, a few non-compliant devices use
+/// CRC-16/CCITT or no checksum at all
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrcAlgorithm {
+    #[default]
+    Modbus,
+    /// CRC-16/CCITT-FALSE
+    Ccitt,
+    None,
+}
+
+pub const CRC_ALGORITHMS: &[CrcAlgorithm] =
+    &[CrcAlgorithm::Modbus, CrcAlgorithm::Ccitt, CrcAlgorithm::None];
+
+impl Display for CrcAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CrcAlgorithm::Modbus => "CRC MODBUS",
+                CrcAlgorithm::Ccitt => "CRC CCITT",
+                CrcAlgorithm::None => "No CRC",
+            }
+        )
+    }
+}
+
+impl CrcAlgorithm {
+    /// Bytes the checksum takes at the end of a frame
+    pub fn trailer_len(self) -> usize {
+        match self {
+            CrcAlgorithm::None => 0,
+            _ => 2,
+        }
+    }
+
+    /// Checksum of `bytes`, `None` if frames carry no checksum
+    pub fn checksum(self, bytes: &[u8]) -> Option<u16> {
+        match self {
+            CrcAlgorithm::Modbus => Some(CRC_GEN.checksum(bytes)),
+            CrcAlgorithm::Ccitt => Some(CCITT_GEN.checksum(bytes)),
+            CrcAlgorithm::None => None,
+        }
+    }
+}
+
+/// Order the two checksum bytes are sent in, Modbus sends the low byte first
+/// but a few non-compliant devices swap them
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrcByteOrder {
+    #[default]
+    LowFirst,
+    HighFirst,
+}
+
+pub const CRC_BYTE_ORDERS: &[CrcByteOrder] =
+    &[CrcByteOrder::LowFirst, CrcByteOrder::HighFirst];
+
+impl Display for CrcByteOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CrcByteOrder::LowFirst => "CRC Low First",
+                CrcByteOrder::HighFirst => "CRC High First",
+            }
+        )
+    }
+}
+
+impl CrcByteOrder {
+    /// The CRC as its two trailer bytes, in the order they are sent
+    fn encode(self, crc: u16) -> [u8; 2] {
+        match self {
+            CrcByteOrder::LowFirst => crc.to_le_bytes(),
+            CrcByteOrder::HighFirst => crc.to_be_bytes(),
+        }
+    }
+
+    /// The CRC carried by the two trailer bytes of a frame
+    fn decode(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            CrcByteOrder::LowFirst => u16::from_le_bytes(bytes),
+            CrcByteOrder::HighFirst => u16::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// Checksum at the end of every frame and the order its bytes are sent in
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CrcConfig {
+    pub algorithm: CrcAlgorithm,
+    pub byte_order: CrcByteOrder,
+}
+
+impl From<CrcAlgorithm> for CrcConfig {
+    fn from(algorithm: CrcAlgorithm) -> Self {
+        Self { algorithm, byte_order: CrcByteOrder::LowFirst }
+    }
+}
+
+impl CrcConfig {
+    /// Bytes the checksum takes at the end of a frame
+    pub fn trailer_len(self) -> usize {
+        self.algorithm.trailer_len()
+    }
+
+    /// Checksum of `bytes`, `None` if frames carry no checksum
+    pub fn checksum(self, bytes: &[u8]) -> Option<u16> {
+        self.algorithm.checksum(bytes)
+    }
+
+    /// The checksum at the end of `frame` as sent, `frame` must hold one
+    pub fn frame_crc(self, frame: &[u8]) -> u16 {
+        let len = frame.len();
+        self.byte_order.decode([frame[len - 2], frame[len - 1]])
+    }
+
+    /// Append the checksum of `frame` to it, in this byte order
+    pub fn append(self, frame: &mut Vec<u8>) {
+        if let Some(crc) = self.checksum(frame) {
+            frame.extend_from_slice(&self.byte_order.encode(crc));
+        }
+    }
+
+    /// Replace the Modbus CRC of an encoded frame with this checksum
+    pub fn reencode(self, mut frame: Vec<u8>) -> Vec<u8> {
+        if self != CrcConfig::default() {
+            frame.truncate(frame.len().saturating_sub(2));
+            self.append(&mut frame);
+        }
+        frame
+    }
+
+    /// Whether the checksum at the end of `frame` matches its content,
+    /// `None` if frames carry no checksum or it is too short to hold one
+    pub fn check(self, frame: &[u8]) -> Option<bool> {
+        let len = frame.len();
+        if len < 4 {
+            return None;
+        }
+
+        Some(self.checksum(&frame[..len - 2])? == self.frame_crc(frame))
+    }
+}
+
+/// A fixed length Modbus RTU request frame, covers every function code whose
+/// request body is a register address followed by a 16 bit value, which is
+/// the quantity for reads and the register value for single writes
+///
+/// On the wire the frame is `[device_addr, function, addr_hi, addr_lo,
+/// value_hi, value_lo, crc_lo, crc_hi]`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RequestFrame {
+    pub device_addr: u8,
+    pub function: u8,
+    pub addr: u16,
+    pub value: u16,
+}
+
+impl RequestFrame {
+    pub const LEN: usize = 8;
+
+    /// Encode the frame, address and value are big endian and the CRC is
+    /// appended low byte first
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes: [u8; Self::LEN] = [
+            self.device_addr,
+            self.function,
+            (self.addr >> 8) as u8,
+            self.addr as u8,
+            (self.value >> 8) as u8,
+            self.value as u8,
+            0,
+            0,
+        ];
+
+        let crc = checksum(&bytes[..6]);
+        bytes[6] = crc as u8;
+        bytes[7] = (crc >> 8) as u8;
+
+        bytes
+    }
+
+    /// Decode a frame produced by [`RequestFrame::to_bytes`], fails if the
+    /// length or the CRC is wrong
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::LEN {
+            return Err(Error::with_message(
+                ErrKind::InvalidFrame,
+                format!(
+                    "Request frame must be {} bytes long, got {}",
+                    Self::LEN,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let msg_crc = (bytes[6] as u16) | ((bytes[7] as u16) << 8);
+        if checksum(&bytes[..6]) != msg_crc {
+            return Err(Error::with_message(
+                ErrKind::InvalidFrame,
+                "Request frame failed CRC check".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            device_addr: bytes[0],
+            function: bytes[1],
+            addr: ((bytes[2] as u16) << 8) | bytes[3] as u16,
+            value: ((bytes[4] as u16) << 8) | bytes[5] as u16,
+        })
+    }
+}
+
+/// Encode a request that has no body, only the device address and function
+/// code, e.g. Get Comm Event Counter (0x0B)
+///
+/// On the wire the frame is `[device_addr, function, crc_lo, crc_hi]`
+pub fn bare_frame(device_addr: u8, function: u8) -> [u8; 4] {
+    let crc = checksum(&[device_addr, function]);
+    [device_addr, function, crc as u8, (crc >> 8) as u8]
+}
+
+/// Encode a Read File Record (0x14) request for a single sub-request of
+/// reference type 6, reading `record_len` registers from `record` of `file`
+///
+/// On the wire the frame is `[device_addr, 0x14, byte_count, 6, file_hi,
+/// file_lo, record_hi, record_lo, len_hi, len_lo, crc_lo, crc_hi]`
+pub fn read_file_record_frame(
+    device_addr: u8,
+    file: u16,
+    record: u16,
+    record_len: u16,
+) -> [u8; 12] {
+    let [file_hi, file_lo] = file.to_be_bytes();
+    let [record_hi, record_lo] = record.to_be_bytes();
+    let [len_hi, len_lo] = record_len.to_be_bytes();
+    let body = [
+        device_addr,
+        0x14,
+        0x07,
+        0x06,
+        file_hi,
+        file_lo,
+        record_hi,
+        record_lo,
+        len_hi,
+        len_lo,
+    ];
+
+    let crc = checksum(&body);
+    let mut frame = [0; 12];
+    frame[..10].copy_from_slice(&body);
+    frame[10] = crc as u8;
+    frame[11] = (crc >> 8) as u8;
+    frame
+}
+
+/// Encode a Write Multiple Registers (0x10) request writing `values` to
+/// consecutive registers from `addr`
+///
+/// On the wire the frame is `[device_addr, 0x10, addr_hi, addr_lo, qty_hi,
+/// qty_lo, byte_count, values.., crc_lo, crc_hi]`, every value big endian
+pub fn write_multiple_frame(
+    device_addr: u8,
+    addr: u16,
+    values: &[u16],
+) -> Vec<u8> {
+    let mut frame = vec![device_addr, 0x10];
+    frame.extend_from_slice(&addr.to_be_bytes());
+    frame.extend_from_slice(&(values.len() as u16).to_be_bytes());
+    frame.push((values.len() * 2) as u8);
+    for value in values {
+        frame.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let crc = checksum(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+/// Encode a request of any function code with the bytes in `data` after it
+///
+/// On the wire the frame is `[device_addr, function, data.., crc_lo, crc_hi]`
+pub fn custom_frame(device_addr: u8, function: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![device_addr, function];
+    frame.extend_from_slice(data);
+
+    let crc = checksum(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+/// Encode a Read Device Identification (0x2B, MEI type 0x0E) request with
+/// read device ID code `read_code`, starting at object 0
+///
+/// On the wire the frame is `[device_addr, 0x2B, 0x0E, read_code, 0x00,
+/// crc_lo, crc_hi]`
+pub fn read_device_id_frame(device_addr: u8, read_code: u8) -> [u8; 7] {
+    let body = [device_addr, 0x2B, 0x0E, read_code, 0x00];
+
+    let crc = checksum(&body);
+    let mut frame = [0; 7];
+    frame[..5].copy_from_slice(&body);
+    frame[5] = crc as u8;
+    frame[6] = (crc >> 8) as u8;
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_sender::Request;
+
+    #[test]
+    fn checksum_known_vectors() {
+        assert_eq!(checksum(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]), 0x0A84);
+        assert_eq!(checksum(&[0x01, 0x03, 0x02, 0x12, 0x34]), 0x33B5);
+    }
+
+    #[test]
+    fn reencode_with_other_checksums() {
+        let frame = Request::ReadSingle(0x0000)
+            .to_frame(0x01)
+            .unwrap()
+            .to_bytes()
+            .to_vec();
+        let crc = |algorithm| CrcConfig::from(algorithm);
+        assert_eq!(crc(CrcAlgorithm::Modbus).reencode(frame.clone()), frame);
+        assert_eq!(
+            crc(CrcAlgorithm::None).reencode(frame.clone()),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x01]
+        );
+
+        let ccitt = crc(CrcAlgorithm::Ccitt).reencode(frame);
+        assert_eq!(ccitt.len(), 8);
+        assert_eq!(crc(CrcAlgorithm::Ccitt).check(&ccitt), Some(true));
+        assert_eq!(crc(CrcAlgorithm::Modbus).check(&ccitt), Some(false));
+        assert_eq!(crc(CrcAlgorithm::None).check(&ccitt), None);
+    }
+
+    #[test]
+    fn swapped_crc_bytes() {
+        // 01 03 00 00 00 01 has the Modbus CRC 0x0A84, sent as 84 0A
+        let frame = Request::ReadSingle(0x0000)
+            .to_frame(0x01)
+            .unwrap()
+            .to_bytes()
+            .to_vec();
+        let swapped = CrcConfig {
+            algorithm: CrcAlgorithm::Modbus,
+            byte_order: CrcByteOrder::HighFirst,
+        };
+
+        let reencoded = swapped.reencode(frame.clone());
+        assert_eq!(reencoded[6..], [0x0A, 0x84]);
+        assert_eq!(swapped.check(&reencoded), Some(true));
+        assert_eq!(swapped.check(&frame), Some(false));
+        assert_eq!(CrcConfig::default().check(&reencoded), Some(false));
+    }
+
+    #[test]
+    fn encode_read_single() {
+        assert_eq!(
+            Request::ReadSingle(0x0000).to_frame(0x01).unwrap().to_bytes(),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A]
+        );
+        assert_eq!(
+            Request::ReadSingle(0xD010).to_frame(0x11).unwrap().to_bytes(),
+            [0x11, 0x03, 0xD0, 0x10, 0x00, 0x01, 0xBF, 0x9F]
+        );
+    }
+
+    #[test]
+    fn encode_read_single_ro() {
+        assert_eq!(
+            Request::ReadSingleRO(0x0000).to_frame(0x01).unwrap().to_bytes(),
+            [0x01, 0x04, 0x00, 0x00, 0x00, 0x01, 0x31, 0xCA]
+        );
+    }
+
+    #[test]
+    fn encode_write_single() {
+        assert_eq!(
+            Request::WriteSingle(0x0001, 3.0, 3)
+                .to_frame(0x01)
+                .unwrap()
+                .to_bytes(),
+            [0x01, 0x06, 0x00, 0x01, 0x00, 0x03, 0x98, 0x0B]
+        );
+    }
+
+    #[test]
+    fn encode_comm_event_counter() {
+        assert_eq!(Request::CommEventCounter.to_frame(0x01), None);
+        assert_eq!(
+            Request::CommEventCounter.to_bytes(0x01),
+            [0x01, 0x0B, 0x41, 0xE7]
+        );
+    }
+
+    #[test]
+    fn encode_read_exception_status() {
+        assert_eq!(
+            Request::ReadExceptionStatus.to_bytes(0x01),
+            [0x01, 0x07, 0x41, 0xE2]
+        );
+    }
+
+    #[test]
+    fn encode_loopback() {
+        assert_eq!(
+            Request::Loopback(0xA537).to_bytes(0x01),
+            [0x01, 0x08, 0x00, 0x00, 0xA5, 0x37, 0xDA, 0x8D]
+        );
+    }
+
+    #[test]
+    fn encode_read_file_record() {
+        assert_eq!(Request::ReadFileRecord(4, 1, 2).to_frame(0x01), None);
+        assert_eq!(
+            Request::ReadFileRecord(4, 1, 2).to_bytes(0x01),
+            [
+                0x01, 0x14, 0x07, 0x06, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02,
+                0xD8, 0xE5
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_write_multiple() {
+        let mut op = crate::message_sender::Operation::new(
+            "block".to_string(),
+            Request::WriteMultiple(0x0001, 2),
+        );
+        op.write_values = std::sync::Arc::new(vec![0x000A, 0x0102]);
+
+        // example from the Modbus application protocol specification
+        assert_eq!(
+            op.request_bytes(0x11),
+            [
+                0x11, 0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01,
+                0x02, 0xC6, 0xF0
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_custom() {
+        let mut op = crate::message_sender::Operation::new(
+            "vendor".to_string(),
+            Request::Custom(0x41, Some(0x0010), None),
+        );
+        op.payload = std::sync::Arc::new(vec![0xAB]);
+
+        let bytes = op.request_bytes(0x01);
+        assert_eq!(bytes[..5], [0x01, 0x41, 0x00, 0x10, 0xAB]);
+        assert_eq!(bytes[5..], checksum(&bytes[..5]).to_le_bytes());
+        assert_eq!(
+            Request::Custom(0x03, Some(0x0000), Some(0x0001)).to_bytes(0x01),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A]
+        );
+    }
+
+    #[test]
+    fn encode_read_device_id() {
+        use crate::message_sender::DeviceIdLevel;
+
+        assert_eq!(
+            Request::ReadDeviceId(DeviceIdLevel::Basic).to_frame(0x01),
+            None
+        );
+        assert_eq!(
+            Request::ReadDeviceId(DeviceIdLevel::Basic).to_bytes(0x01),
+            [0x01, 0x2B, 0x0E, 0x01, 0x00, 0x70, 0x77]
+        );
+        assert_eq!(
+            Request::ReadDeviceId(DeviceIdLevel::Regular).to_bytes(0x01),
+            [0x01, 0x2B, 0x0E, 0x02, 0x00, 0x70, 0x87]
+        );
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let frame = RequestFrame {
+            device_addr: 0x11,
+            function: 0x06,
+            addr: 0xD010,
+            value: 0xABCD,
+        };
+        assert_eq!(RequestFrame::from_bytes(&frame.to_bytes()), Ok(frame));
+    }
+
+    #[test]
+    fn decode_rejects_bad_frames() {
+        let bytes = [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0B];
+        assert!(RequestFrame::from_bytes(&bytes).is_err());
+        assert!(RequestFrame::from_bytes(&bytes[..7]).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use iced::{
+    widget::{Button, Checkbox, Column, Row, Scrollable, Text},
+    Alignment, Command, Element, Length,
+};
+
+use crate::error::{ErrKind, Error};
+use crate::port_op::{OpMessage, PortOption, RunEnd};
+use crate::style::TextSize;
+use crate::transport::{is_port_gone, PortConfig, Transport};
+
+/// Frames kept in the monitor view, the oldest are dropped first
+const MAX_FRAMES: usize = 1000;
+
+/// Bytes received in one go while monitoring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorFrame {
+    /// Time since the monitor started
+    pub at: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Handle messages that arrived while monitoring, returns how the monitor
+/// ends if it should stop
+fn monitor_canceled(rx: &Receiver<OpMessage>) -> Option<RunEnd> {
+    let busy = || {
+        Error::with_message(
+            ErrKind::PortBusyMonitoring,
+            "Port is busy monitoring the bus".to_string(),
+        )
+    };
+
+    while let Ok(op_msg) = rx.try_recv() {
+        // don't care if the sends fail
+        match op_msg {
+            OpMessage::StopMonitor | OpMessage::Disconnect => {
+                return Some(RunEnd::Done)
+            }
+            OpMessage::Shutdown => return Some(RunEnd::Shutdown),
+            OpMessage::OneShot(_, _, resp_tx)
+            | OpMessage::StartContinuous(_, _, _, resp_tx) => {
+                let _ = resp_tx.send(Err(busy()));
+            }
+            OpMessage::Scan(_, _, scan_tx)
+            | OpMessage::DetectBaud(_, _, scan_tx) => {
+                let _ = scan_tx.send(Err(busy()));
+            }
+            OpMessage::Monitor(_, monitor_tx) => {
+                let _ = monitor_tx.send(Err(busy()));
+            }
+            OpMessage::TestOpen(_, open_tx) => {
+                let _ = open_tx.send(Err(busy()));
+            }
+            OpMessage::StopContinuous
+            | OpMessage::PauseContinuous(_)
+            | OpMessage::StopScan => {}
+        }
+    }
+
+    None
+}
+
+/// Pass on everything received without sending anything, runs on
+/// port_op_thread, `StopMonitor` stops it. Received bytes are split where the
+/// bus goes silent for the read timeout, or the t3.5 gap with t3.5 framing
+pub fn monitor_bus(
+    transport: &mut dyn Transport,
+    rx: &Receiver<OpMessage>,
+    tx: &Sender<Result<MonitorFrame, Error>>,
+) -> RunEnd {
+    let start = Instant::now();
+
+    loop {
+        if let Some(end) = monitor_canceled(rx) {
+            return end;
+        }
+
+        let event = match transport.read_frame() {
+            Ok(bytes) if bytes.is_empty() => continue,
+            Ok(bytes) => Ok(MonitorFrame { at: start.elapsed(), bytes }),
+            Err(e) if is_port_gone(&e) => {
+                // don't care if the send fails, the monitor stops either way
+                let _ = tx.send(Err(Error::with_message(
+                    ErrKind::PortReadFailed,
+                    format!("Failed to read from port due to: {}", e),
+                )));
+                return RunEnd::Done;
+            }
+            Err(_) => {
+                // a framing or parity error drops the bytes, keep listening
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+        };
+
+        if tx.send(event).is_err() {
+            return RunEnd::Done;
+        }
+    }
+}
+
+/// Monitor the configured port, `silence_gap` splits frames at the t3.5 gap
+/// instead of the read timeout
+pub async fn monitor_start(
+    port_option: PortOption,
+    silence_gap: bool,
+    port_op_tx: Sender<OpMessage>,
+    sender: Sender<Result<MonitorFrame, Error>>,
+) -> Result<(), Error> {
+    let port_conf: PortConfig =
+        PortOption { t35_framing: silence_gap, ..port_option }.try_into()?;
+
+    if port_op_tx.send(OpMessage::Monitor(port_conf, sender)).is_err() {
+        Err(Error::new(ErrKind::PortOpThreadNotPresent))
+    } else {
+        Ok(())
+    }
+}
+
+pub async fn monitor_get_results(
+    rx: Arc<Mutex<Receiver<Result<MonitorFrame, Error>>>>,
+) -> Result<Vec<Result<MonitorFrame, Error>>, Error> {
+    // Locking really shouldn't fail, crash the process if that happens
+    let rx = rx.lock().unwrap();
+    let frame = if let Ok(frame) = rx.recv() {
+        frame
+    } else {
+        return Err(Error::with_message(
+            ErrKind::PortOpThreadNotPresent,
+            "port op thread not present".to_string(),
+        ));
+    };
+
+    let mut result = vec![frame];
+
+    while let Ok(frame) = rx.try_recv() {
+        result.push(frame);
+    }
+
+    Ok(result)
+}
+
+pub async fn monitor_stop(tx: Sender<OpMessage>) {
+    let _ = tx.send(OpMessage::StopMonitor);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorViewMessage {
+    AddFrame(MonitorFrame),
+    TogglePause,
+    Clear,
+    /// Applies from the next start
+    SetSilenceGap(bool),
+    Close,
+}
+
+/// Timestamped hex dump of everything the monitor received
+#[derive(Debug, Clone, Default)]
+pub struct MonitorView {
+    frames: VecDeque<MonitorFrame>,
+    /// Frames aren't added while paused, only counted
+    paused: bool,
+    skipped: usize,
+    silence_gap: bool,
+    visible: bool,
+}
+
+impl MonitorView {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn silence_gap(&self) -> bool {
+        self.silence_gap
+    }
+
+    /// Show the view for a new monitor, frames of the last one are kept
+    pub fn open(&mut self) {
+        self.visible = true;
+        self.paused = false;
+    }
+
+    pub fn update(
+        &mut self,
+        msg: MonitorViewMessage,
+    ) -> Command<MonitorViewMessage> {
+        match msg {
+            MonitorViewMessage::AddFrame(frame) => {
+                if self.paused {
+                    self.skipped += 1;
+                } else {
+                    self.frames.push_back(frame);
+                    if self.frames.len() > MAX_FRAMES {
+                        self.frames.pop_front();
+                    }
+                }
+            }
+            MonitorViewMessage::TogglePause => self.paused = !self.paused,
+            MonitorViewMessage::Clear => {
+                self.frames.clear();
+                self.skipped = 0;
+            }
+            MonitorViewMessage::SetSilenceGap(silence_gap) => {
+                self.silence_gap = silence_gap;
+            }
+            MonitorViewMessage::Close => self.visible = false,
+        }
+
+        Command::none()
+    }
+
+    pub fn view(&self) -> Element<MonitorViewMessage> {
+        let status = match (self.paused, self.skipped) {
+            (true, skipped) => format!("Paused, {} frames skipped", skipped),
+            (false, 0) => format!("{} frames", self.frames.len()),
+            (false, skipped) => format!(
+                "{} frames, {} skipped while paused",
+                self.frames.len(),
+                skipped
+            ),
+        };
+
+        let mut column =
+            Column::new().width(Length::Fill).height(Length::Shrink).push(
+                Row::new()
+                    .padding(5)
+                    .spacing(4)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(status).width(Length::Fill))
+                    .push(Checkbox::new(
+                        self.silence_gap,
+                        "Split at t3.5",
+                        MonitorViewMessage::SetSilenceGap,
+                    ))
+                    .push(
+                        Button::new(
+                            if self.paused { "Resume" } else { "Pause" },
+                        )
+                        .on_press(MonitorViewMessage::TogglePause),
+                    )
+                    .push(
+                        Button::new("Clear").on_press(MonitorViewMessage::Clear),
+                    )
+                    .push(
+                        Button::new("Close").on_press(MonitorViewMessage::Close),
+                    ),
+            );
+
+        // time since the monitor started and since the frame before
+        let mut last = None;
+        for frame in &self.frames {
+            let gap = last.map_or(Duration::ZERO, |last| frame.at - last);
+            last = Some(frame.at);
+
+            let hex = frame
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            column = column.push(
+                Text::new(format!(
+                    "{:>10.3} s +{:>8.3} s  {}",
+                    frame.at.as_secs_f64(),
+                    gap.as_secs_f64(),
+                    hex
+                ))
+                .size(TextSize::SMALL),
+            );
+        }
+
+        Scrollable::new(column).into()
+    }
+}
@@ -0,0 +1,513 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{
+    channel, Receiver, RecvTimeoutError, Sender, TryRecvError,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use iced::{
+    widget::{Checkbox, PickList, Row, Text, TextInput},
+    Alignment, Command, Element, Length,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::{ResponseEvent, ResponseHooks, ResponseSource};
+use crate::style::TextSize;
+
+/// Seconds the broker waits for a packet before dropping the connection
+const KEEP_ALIVE_SECS: u16 = 60;
+/// Least time between attempts to reach the broker after a failure
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the publisher thread checks whether it should stop while idle
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Times a QoS 1 publish is sent before giving up on its PUBACK
+const PUBLISH_ATTEMPTS: usize = 3;
+
+/// Delivery guarantee of published values, exactly once isn't supported
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Qos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+}
+
+const QOS_ALL: &[Qos] = &[Qos::AtMostOnce, Qos::AtLeastOnce];
+
+impl Display for Qos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Qos::AtMostOnce => "QoS 0",
+                Qos::AtLeastOnce => "QoS 1",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttMessage {
+    SetEnabled(bool),
+    SetBroker(String),
+    SetTopicPrefix(String),
+    SelectQos(Qos),
+}
+
+/// Publish the decoded value of every continuous op to an MQTT broker, at
+/// `{prefix}/{op name}`, and every failure to `{prefix}/status`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MqttView {
+    enabled: bool,
+    /// `host:port`, `mqtt://` may be put in front, the port defaults to 1883
+    broker: String,
+    topic_prefix: String,
+    qos: Qos,
+
+    /// Dropping it stops the publisher thread
+    #[serde(skip)]
+    stop: Option<Sender<()>>,
+    /// Connection state the publisher thread last reported
+    #[serde(skip)]
+    status: Arc<Mutex<String>>,
+}
+
+impl MqttView {
+    /// Start publishing if it was enabled when the layout was saved
+    pub fn resume(&mut self, hooks: &mut ResponseHooks) {
+        if self.enabled {
+            self.start(hooks);
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        message: MqttMessage,
+        hooks: &mut ResponseHooks,
+    ) -> Command<MqttMessage> {
+        match message {
+            MqttMessage::SetEnabled(enabled) => {
+                self.enabled = enabled;
+                if enabled {
+                    self.start(hooks);
+                } else {
+                    self.stop();
+                }
+            }
+            // settings apply the next time publishing is enabled
+            MqttMessage::SetBroker(broker) => self.broker = broker,
+            MqttMessage::SetTopicPrefix(prefix) => self.topic_prefix = prefix,
+            MqttMessage::SelectQos(qos) => self.qos = qos,
+        }
+
+        Command::none()
+    }
+
+    fn start(&mut self, hooks: &mut ResponseHooks) {
+        self.stop();
+
+        let (stop_tx, stop_rx) = channel();
+        let publisher = Publisher {
+            broker: broker_addr(&self.broker),
+            prefix: self.topic_prefix.trim_end_matches('/').to_string(),
+            qos: self.qos,
+            stream: None,
+            last_attempt: None,
+            next_packet_id: 0,
+            status: self.status.clone(),
+        };
+        let events = hooks.subscribe();
+        std::thread::spawn(move || publisher.run(events, stop_rx));
+
+        self.stop = Some(stop_tx);
+    }
+
+    fn stop(&mut self) {
+        self.stop = None;
+        // Locking really shouldn't fail, crash if that happens
+        *self.status.lock().unwrap() = String::new();
+    }
+
+    pub fn view(&self) -> Element<MqttMessage> {
+        // Locking really shouldn't fail, crash if that happens
+        let status = self.status.lock().unwrap().clone();
+
+        Row::new()
+            .padding(5)
+            .spacing(4)
+            .align_items(Alignment::Center)
+            .push(Checkbox::new(self.enabled, "MQTT", MqttMessage::SetEnabled))
+            .push(
+                TextInput::new(
+                    "Broker, e.g. localhost:1883",
+                    &self.broker,
+                    MqttMessage::SetBroker,
+                )
+                .width(Length::FillPortion(2))
+                .padding([0, 2]),
+            )
+            .push(
+                TextInput::new(
+                    "Topic Prefix",
+                    &self.topic_prefix,
+                    MqttMessage::SetTopicPrefix,
+                )
+                .width(Length::FillPortion(2))
+                .padding([0, 2]),
+            )
+            .push(PickList::new(QOS_ALL, Some(self.qos), MqttMessage::SelectQos))
+            .push(
+                Text::new(status)
+                    .size(TextSize::SMALL)
+                    .width(Length::FillPortion(3)),
+            )
+            .into()
+    }
+}
+
+/// Broker address with the scheme stripped and the default port added
+fn broker_addr(broker: &str) -> String {
+    let addr = broker.trim().trim_start_matches("mqtt://");
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:1883", addr)
+    }
+}
+
+/// A topic level from an op name, wildcards and separators aren't allowed
+fn topic_level(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
+
+/// Append the MQTT variable length encoding of `len`
+fn push_remaining_len(packet: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn push_str(body: &mut Vec<u8>, s: &str) {
+    body.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    body.extend_from_slice(s.as_bytes());
+}
+
+/// A packet with its fixed header
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    push_remaining_len(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// CONNECT of a clean session for `client_id`
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = vec![];
+    push_str(&mut body, "MQTT");
+    // protocol level 4 is 3.1.1, start a clean session
+    body.extend_from_slice(&[0x04, 0x02]);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    push_str(&mut body, client_id);
+    packet(0x10, &body)
+}
+
+/// PUBLISH of `payload` to `topic`, `packet_id` is only sent at QoS 1 and
+/// `dup` marks a QoS 1 packet that is sent again
+fn publish_packet(
+    topic: &str,
+    payload: &str,
+    qos: Qos,
+    packet_id: u16,
+    dup: bool,
+) -> Vec<u8> {
+    let mut body = vec![];
+    push_str(&mut body, topic);
+
+    let header = match qos {
+        Qos::AtMostOnce => 0x30,
+        Qos::AtLeastOnce => {
+            body.extend_from_slice(&packet_id.to_be_bytes());
+            if dup {
+                0x3A
+            } else {
+                0x32
+            }
+        }
+    };
+    body.extend_from_slice(payload.as_bytes());
+
+    packet(header, &body)
+}
+
+/// Check `puback` acknowledges the publish with `packet_id`
+fn check_puback(puback: [u8; 4], packet_id: u16) -> std::io::Result<()> {
+    let [header, len, id @ ..] = puback;
+    if header != 0x40 || len != 0x02 || u16::from_be_bytes(id) != packet_id {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("expected the PUBACK of packet {}", packet_id),
+        ));
+    }
+
+    Ok(())
+}
+
+/// A minimal MQTT 3.1.1 client that only publishes, runs on its own thread
+struct Publisher {
+    broker: String,
+    prefix: String,
+    qos: Qos,
+    stream: Option<TcpStream>,
+    last_attempt: Option<Instant>,
+    next_packet_id: u16,
+    status: Arc<Mutex<String>>,
+}
+
+impl Publisher {
+    /// Publish events until a message is received on, or the sender of,
+    /// `stop_rx` is dropped
+    fn run(mut self, events: Receiver<ResponseEvent>, stop_rx: Receiver<()>) {
+        let ping_interval = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+        let mut last_packet = Instant::now();
+
+        while let Err(TryRecvError::Empty) = stop_rx.try_recv() {
+            let event = match events.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    if last_packet.elapsed() >= ping_interval {
+                        self.ping();
+                        last_packet = Instant::now();
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if event.source != ResponseSource::Continuous {
+                continue;
+            }
+
+            let (topic, payload) = match &event.result {
+                Ok(resp) => match (resp.value(), resp.frame_error()) {
+                    (Some(value), None) => {
+                        let name = topic_level(&resp.op.name);
+                        let topic = match resp.op.device_addr {
+                            Some(addr) => {
+                                format!("{}/{}/{}", self.prefix, addr, name)
+                            }
+                            None => format!("{}/{}", self.prefix, name),
+                        };
+                        (topic, resp.op.format_value(value))
+                    }
+                    (_, Some(e)) => (
+                        format!("{}/status", self.prefix),
+                        format!("{}: {}", resp.op.name, e.message()),
+                    ),
+                    // strings and other values that aren't numbers
+                    (None, None) => continue,
+                },
+                Err(e) => {
+                    (format!("{}/status", self.prefix), e.message().to_string())
+                }
+            };
+
+            if let Err(e) = self.publish(&topic, &payload) {
+                self.set_status(format!("Publish failed: {}", e));
+                self.stream = None;
+            }
+            last_packet = Instant::now();
+        }
+
+        if let Some(mut stream) = self.stream.take() {
+            // DISCONNECT, the connection is dropped either way
+            let _ = stream.write_all(&[0xE0, 0x00]);
+        }
+    }
+
+    fn set_status(&self, status: String) {
+        // Locking really shouldn't fail, crash the thread if that happens
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// The open connection, connecting first if there is none. A failed
+    /// attempt isn't retried before `RECONNECT_INTERVAL` has passed
+    fn connection(&mut self) -> std::io::Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            let now = Instant::now();
+            if self.last_attempt.map_or(false, |last| {
+                now.duration_since(last) < RECONNECT_INTERVAL
+            }) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotConnected,
+                    "waiting to reconnect",
+                ));
+            }
+            self.last_attempt = Some(now);
+
+            let stream = self.connect()?;
+            self.set_status(format!("Connected to {}", self.broker));
+            self.stream = Some(stream);
+        }
+
+        // unwrap because the stream was just set if it wasn't before
+        Ok(self.stream.as_mut().unwrap())
+    }
+
+    fn connect(&self) -> std::io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(&self.broker)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let client_id = format!("modbus-tester-{}", std::process::id());
+        stream.write_all(&connect_packet(&client_id))?;
+
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("broker refused the connection, code {}", connack[3]),
+            ));
+        }
+
+        Ok(stream)
+    }
+
+    /// Publish at the configured QoS. A QoS 1 publish that isn't
+    /// acknowledged is sent again on a new connection, up to
+    /// `PUBLISH_ATTEMPTS` times
+    fn publish(&mut self, topic: &str, payload: &str) -> std::io::Result<()> {
+        let packet_id = match self.qos {
+            Qos::AtMostOnce => 0,
+            // packet ids must not be 0
+            Qos::AtLeastOnce => {
+                self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+                self.next_packet_id
+            }
+        };
+
+        let qos = self.qos;
+        let mut attempt = 1;
+        loop {
+            let packet =
+                publish_packet(topic, payload, qos, packet_id, attempt > 1);
+            let result = self.connection().and_then(|stream| {
+                stream.write_all(&packet)?;
+                if qos == Qos::AtLeastOnce {
+                    // only this client's packets are in flight
+                    let mut puback = [0u8; 4];
+                    stream.read_exact(&mut puback)?;
+                    check_puback(puback, packet_id)?;
+                }
+                Ok(())
+            });
+
+            let retry = qos == Qos::AtLeastOnce && attempt < PUBLISH_ATTEMPTS;
+            match result {
+                Err(_) if retry => {
+                    // reconnect right away instead of after
+                    // `RECONNECT_INTERVAL`
+                    self.stream = None;
+                    self.last_attempt = None;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Keep an idle connection open
+    fn ping(&mut self) {
+        let result = match self.stream.as_mut() {
+            Some(stream) => stream.write_all(&[0xC0, 0x00]).and_then(|()| {
+                let mut pingresp = [0u8; 2];
+                stream.read_exact(&mut pingresp)
+            }),
+            None => return,
+        };
+
+        if let Err(e) = result {
+            self.set_status(format!("Connection lost: {}", e));
+            self.stream = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remaining_len(len: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        push_remaining_len(&mut bytes, len);
+        bytes
+    }
+
+    #[test]
+    fn remaining_len_encoding() {
+        assert_eq!(remaining_len(0), [0x00]);
+        assert_eq!(remaining_len(127), [0x7F]);
+        assert_eq!(remaining_len(128), [0x80, 0x01]);
+        assert_eq!(remaining_len(16_383), [0xFF, 0x7F]);
+        assert_eq!(remaining_len(16_384), [0x80, 0x80, 0x01]);
+        assert_eq!(remaining_len(2_097_152), [0x80, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn connect_framing() {
+        assert_eq!(
+            connect_packet("id"),
+            [
+                0x10, 0x0E, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02,
+                0x00, 0x3C, 0x00, 0x02, b'i', b'd',
+            ]
+        );
+    }
+
+    #[test]
+    fn publish_framing() {
+        assert_eq!(
+            publish_packet("a/b", "1.5", Qos::AtMostOnce, 7, false),
+            [0x30, 0x08, 0x00, 0x03, b'a', b'/', b'b', b'1', b'.', b'5']
+        );
+        assert_eq!(
+            publish_packet("t", "1", Qos::AtLeastOnce, 0x0102, false),
+            [0x32, 0x06, 0x00, 0x01, b't', 0x01, 0x02, b'1']
+        );
+        assert_eq!(
+            publish_packet("t", "1", Qos::AtLeastOnce, 0x0102, true),
+            [0x3A, 0x06, 0x00, 0x01, b't', 0x01, 0x02, b'1']
+        );
+    }
+
+    #[test]
+    fn puback_must_match_the_packet_id() {
+        assert!(check_puback([0x40, 0x02, 0x01, 0x02], 0x0102).is_ok());
+        assert!(check_puback([0x40, 0x02, 0x01, 0x03], 0x0102).is_err());
+        assert!(check_puback([0x50, 0x02, 0x01, 0x02], 0x0102).is_err());
+    }
+
+    #[test]
+    fn broker_addresses() {
+        assert_eq!(broker_addr("localhost"), "localhost:1883");
+        assert_eq!(broker_addr(" mqtt://broker:8883 "), "broker:8883");
+        assert_eq!(broker_addr("mqtt://10.0.0.2"), "10.0.0.2:1883");
+    }
+
+    #[test]
+    fn topic_levels() {
+        assert_eq!(topic_level("Temp/Room #1+2"), "Temp_Room _1_2");
+        assert_eq!(topic_level("Pressure"), "Pressure");
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+use iced::{
+    widget::{Column, Text},
+    Element, Length,
+};
+
+use crate::error::{ErrKind, Error};
+use crate::ops::OpView;
+use crate::port_op::ErrorPolicy;
+use crate::response::Response;
+use crate::style::TextSize;
+
+/// Finished requests kept in the status list
+const MAX_FINISHED: usize = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OneShotStatus {
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+    Canceled,
+}
+
+impl Display for OneShotStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                OneShotStatus::Pending => "pending",
+                OneShotStatus::InProgress => "in progress",
+                OneShotStatus::Done => "done",
+                OneShotStatus::Failed => "failed",
+                OneShotStatus::Canceled => "canceled",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct QueueEntry {
+    id: usize,
+    name: String,
+    status: OneShotStatus,
+}
+
+/// One-shot requests are sent one at a time in the order they were clicked,
+/// the next one is only sent once the previous one got its response
+#[derive(Debug, Clone, Default)]
+pub struct OneShotQueue {
+    /// Every request still queued or in progress, and the latest finished
+    entries: VecDeque<QueueEntry>,
+    /// Ops of the pending entries, in order, with their position in a burst
+    waiting: VecDeque<(OpView, Option<(usize, usize)>)>,
+    in_progress: bool,
+    /// Position and size of the burst the request in progress belongs to
+    in_progress_burst: Option<(usize, usize)>,
+    /// Requests of the current burst that succeeded and failed so far
+    burst_tally: (usize, usize),
+    /// Summary of the last burst that ended, until it's taken
+    burst_summary: Option<String>,
+    next_id: usize,
+}
+
+impl OneShotQueue {
+    /// Whether a request is waiting for its response or queued
+    pub fn is_busy(&self) -> bool {
+        self.in_progress
+    }
+
+    /// Names of the ops queued or in progress
+    pub fn active_names(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    OneShotStatus::Pending | OneShotStatus::InProgress
+                )
+            })
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    /// Position and size of the burst the request in progress belongs to,
+    /// `None` if it was sent on its own
+    pub fn in_progress_burst(&self) -> Option<(usize, usize)> {
+        self.in_progress_burst
+    }
+
+    /// Queue an op `count` times in a row, returns it back if it should be
+    /// sent right away
+    pub fn push_burst(&mut self, op: OpView, count: usize) -> Option<OpView> {
+        for idx in 1..=count {
+            self.next_id += 1;
+            self.entries.push_back(QueueEntry {
+                id: self.next_id,
+                name: op.name.clone(),
+                status: OneShotStatus::Pending,
+            });
+            let burst = (count > 1).then_some((idx, count));
+            self.waiting.push_back((op.clone(), burst));
+        }
+
+        if self.in_progress {
+            None
+        } else {
+            self.start_next()
+        }
+    }
+
+    /// Record the response of the request in progress, returns the next op
+    /// to send if there is one. With `ErrorPolicy::Stop` a failed request of
+    /// a burst cancels the rest of the burst
+    pub fn finish(
+        &mut self,
+        response: &Result<Response, Error>,
+        policy: ErrorPolicy,
+    ) -> Option<OpView> {
+        let status = match response {
+            Ok(resp) if resp.frame_error().is_none() => OneShotStatus::Done,
+            // the device answered, but not with what was asked for
+            Ok(_) => OneShotStatus::Failed,
+            Err(e) if e.kind() == ErrKind::RequestCanceled => {
+                OneShotStatus::Canceled
+            }
+            Err(_) => OneShotStatus::Failed,
+        };
+        let name = match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.status == OneShotStatus::InProgress)
+        {
+            Some(entry) => {
+                entry.status = status;
+                entry.name.clone()
+            }
+            None => String::new(),
+        };
+
+        if let Some((idx, count)) = self.in_progress_burst {
+            match status {
+                OneShotStatus::Done => self.burst_tally.0 += 1,
+                _ => self.burst_tally.1 += 1,
+            }
+
+            let stopped = status == OneShotStatus::Failed
+                && policy == ErrorPolicy::Stop
+                && idx < count;
+            if stopped {
+                // the rest of the burst was queued right after this request,
+                // unless it was canceled while this request was in progress
+                let rest = self
+                    .waiting
+                    .iter()
+                    .take_while(|(_, burst)| {
+                        matches!(burst, Some((i, c)) if *c == count && *i > idx)
+                    })
+                    .count();
+                self.waiting.drain(..rest);
+                for entry in self
+                    .entries
+                    .iter_mut()
+                    .filter(|entry| entry.status == OneShotStatus::Pending)
+                    .take(rest)
+                {
+                    entry.status = OneShotStatus::Canceled;
+                }
+            }
+
+            // a canceled burst is no longer queued either
+            let ended = self
+                .waiting
+                .front()
+                .map_or(true, |(_, burst)| *burst != Some((idx + 1, count)));
+            if ended {
+                let (done, failed) = std::mem::take(&mut self.burst_tally);
+                let outcome = if idx == count {
+                    format!("Burst of {} finished", name)
+                } else {
+                    format!("Burst of {} stopped at {}/{}", name, idx, count)
+                };
+                self.burst_summary = Some(format!(
+                    "{}: {} succeeded, {} failed",
+                    outcome, done, failed
+                ));
+            }
+        }
+
+        self.in_progress = false;
+        self.in_progress_burst = None;
+        self.start_next()
+    }
+
+    /// Summary of the last burst that ended since this was last called
+    pub fn take_burst_summary(&mut self) -> Option<String> {
+        self.burst_summary.take()
+    }
+
+    /// Drop every queued request, the one in progress is canceled by its
+    /// owner
+    pub fn cancel_pending(&mut self) {
+        self.waiting.clear();
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.status == OneShotStatus::Pending)
+        {
+            entry.status = OneShotStatus::Canceled;
+        }
+        self.trim();
+    }
+
+    fn start_next(&mut self) -> Option<OpView> {
+        let (op, burst) = self.waiting.pop_front().unzip();
+        if op.is_some() {
+            self.in_progress = true;
+            self.in_progress_burst = burst.flatten();
+            if let Some(entry) = self
+                .entries
+                .iter_mut()
+                .find(|entry| entry.status == OneShotStatus::Pending)
+            {
+                entry.status = OneShotStatus::InProgress;
+            }
+        }
+
+        self.trim();
+        op
+    }
+
+    /// Forget the oldest finished entries beyond `MAX_FINISHED`
+    fn trim(&mut self) {
+        let finished = |entry: &QueueEntry| {
+            !matches!(
+                entry.status,
+                OneShotStatus::Pending | OneShotStatus::InProgress
+            )
+        };
+
+        let mut excess = self
+            .entries
+            .iter()
+            .filter(|entry| finished(entry))
+            .count()
+            .saturating_sub(MAX_FINISHED);
+        self.entries.retain(|entry| {
+            if excess > 0 && finished(entry) {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    pub fn view<'a, M: 'a>(&self) -> Element<'a, M> {
+        let mut column = Column::new().width(Length::Fill);
+        for entry in self.entries.iter() {
+            column = column.push(
+                Text::new(format!(
+                    "#{} {}: {}",
+                    entry.id, entry.name, entry.status
+                ))
+                .size(TextSize::SMALL),
+            );
+        }
+
+        column.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_sender::{Operation, Request};
+    use crate::modbus::checksum;
+    use crate::ops::OpType;
+
+    fn op(name: &str) -> OpView {
+        OpView::new(
+            name.to_string(),
+            OpType::ReadSingle,
+            "0".to_string(),
+            String::new(),
+            "val".to_string(),
+            "val".to_string(),
+        )
+    }
+
+    fn statuses(queue: &OneShotQueue) -> Vec<OneShotStatus> {
+        queue.entries.iter().map(|entry| entry.status).collect()
+    }
+
+    /// A valid reply to a single register read
+    fn done() -> Result<Response, Error> {
+        let mut bytes = vec![1, 0x03, 2, 0, 1];
+        let crc = checksum(&bytes);
+        bytes.extend([crc as u8, (crc >> 8) as u8]);
+        Ok(Response::new(
+            Operation::new("op".to_string(), Request::ReadSingle(0)),
+            bytes,
+        ))
+    }
+
+    #[test]
+    fn requests_are_sent_in_order() {
+        let mut queue = OneShotQueue::default();
+        assert_eq!(
+            queue.push_burst(op("a"), 1).map(|op| op.name),
+            Some("a".into())
+        );
+        assert!(queue.push_burst(op("b"), 1).is_none());
+        assert!(queue.push_burst(op("c"), 1).is_none());
+        assert!(queue.is_busy());
+        assert_eq!(queue.active_names(), ["a", "b", "c"]);
+
+        let next = queue.finish(&done(), ErrorPolicy::Continue);
+        assert_eq!(next.map(|op| op.name), Some("b".to_string()));
+        let next = queue.finish(
+            &Err(Error::new(ErrKind::NoResponse)),
+            ErrorPolicy::Continue,
+        );
+        assert_eq!(next.map(|op| op.name), Some("c".to_string()));
+        assert!(queue.finish(&done(), ErrorPolicy::Continue).is_none());
+
+        assert!(!queue.is_busy());
+        assert_eq!(
+            statuses(&queue),
+            [OneShotStatus::Done, OneShotStatus::Failed, OneShotStatus::Done]
+        );
+    }
+
+    #[test]
+    fn canceling_drops_the_pending_requests() {
+        let mut queue = OneShotQueue::default();
+        queue.push_burst(op("a"), 1);
+        queue.push_burst(op("b"), 1);
+        queue.push_burst(op("c"), 1);
+        queue.cancel_pending();
+        assert_eq!(queue.active_names(), ["a"]);
+
+        let canceled = Err(Error::new(ErrKind::RequestCanceled));
+        assert!(queue.finish(&canceled, ErrorPolicy::Continue).is_none());
+        assert_eq!(statuses(&queue), [OneShotStatus::Canceled; 3]);
+    }
+
+    #[test]
+    fn old_finished_requests_are_forgotten() {
+        let mut queue = OneShotQueue::default();
+        for _ in 0..MAX_FINISHED + 2 {
+            queue.push_burst(op("a"), 1);
+            queue.finish(&done(), ErrorPolicy::Continue);
+        }
+        assert_eq!(queue.entries.len(), MAX_FINISHED);
+        assert_eq!(queue.entries[0].id, 3);
+    }
+
+    #[test]
+    fn a_repeated_request_is_sent_as_a_numbered_burst() {
+        let mut queue = OneShotQueue::default();
+        assert!(queue.push_burst(op("a"), 3).is_some());
+        assert_eq!(queue.in_progress_burst(), Some((1, 3)));
+
+        queue.finish(&done(), ErrorPolicy::Continue);
+        assert_eq!(queue.in_progress_burst(), Some((2, 3)));
+        assert!(queue.take_burst_summary().is_none());
+
+        let failed = Err(Error::new(ErrKind::NoResponse));
+        queue.finish(&failed, ErrorPolicy::Continue);
+        assert_eq!(queue.in_progress_burst(), Some((3, 3)));
+        assert!(queue.finish(&done(), ErrorPolicy::Continue).is_none());
+        assert_eq!(
+            queue.take_burst_summary(),
+            Some("Burst of a finished: 2 succeeded, 1 failed".into())
+        );
+    }
+
+    #[test]
+    fn stop_policy_cancels_the_rest_of_the_burst() {
+        let mut queue = OneShotQueue::default();
+        queue.push_burst(op("a"), 3);
+        queue.push_burst(op("b"), 1);
+
+        let failed = Err(Error::new(ErrKind::NoResponse));
+        let next = queue.finish(&failed, ErrorPolicy::Stop);
+        assert_eq!(next.map(|op| op.name), Some("b".to_string()));
+        assert_eq!(
+            statuses(&queue),
+            [
+                OneShotStatus::Failed,
+                OneShotStatus::Canceled,
+                OneShotStatus::Canceled,
+                OneShotStatus::InProgress,
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_request_after_cancel_keeps_later_requests() {
+        let mut queue = OneShotQueue::default();
+        assert!(queue.push_burst(op("burst"), 3).is_some());
+        queue.cancel_pending();
+        assert!(queue.push_burst(op("single"), 1).is_none());
+
+        // the request in progress failed before it saw the cancel
+        let failed = Err(Error::new(ErrKind::NoResponse));
+        let next = queue.finish(&failed, ErrorPolicy::Stop);
+        assert_eq!(next.map(|op| op.name), Some("single".to_string()));
+        assert_eq!(
+            statuses(&queue),
+            [
+                OneShotStatus::Failed,
+                OneShotStatus::Canceled,
+                OneShotStatus::Canceled,
+                OneShotStatus::InProgress,
+            ]
+        );
+        assert_eq!(
+            queue.take_burst_summary(),
+            Some("Burst of burst stopped at 1/3: 0 succeeded, 1 failed".into())
+        );
+    }
+}
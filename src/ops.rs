@@ -1,47 +1,86 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::collections::HashSet;
+use std::fmt::Debug;
 use std::ops::Deref;
+use std::sync::Arc;
 
 use iced::{
     alignment::{Horizontal, Vertical},
-    widget::{Button, Column, PickList, Row, Scrollable, Text, TextInput},
-    Alignment, Command, Element, Length,
+    widget::{
+        text_input, Button, Checkbox, Column, PickList, Row, Scrollable, Space,
+        Text, TextInput,
+    },
+    Alignment, Color, Command, Element, Length,
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::message_sender::Operation;
+use string_to_num::ParseNum;
 
+use crate::error::{ErrKind, Error};
+use crate::message_sender::{
+    check_write_eval, encode_registers, mask_input, parse_alarm_limits,
+    parse_bit_field, parse_delay, parse_eval, parse_hex_bytes, parse_in_range,
+    parse_labels, parse_plc_addr, parse_precision, parse_register_block,
+    parse_scaling, parse_signed_bits, parse_timeout, parse_u64_block, InputMask,
+    Operation, Request, DEFAULT_LOOPBACK_PATTERN, MAX_PDU_DATA,
+    MAX_READ_QUANTITY, MAX_WRITE_QUANTITY, READ_VARS, WRITE_VARS,
+};
+use crate::modbus::{CrcAlgorithm, CrcConfig};
+use crate::style::TextSize;
+use crate::transport::PortConfig;
 
-/// Type of available operations without operation info
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
-pub enum OpType {
-    ReadSingle,
-    WriteSingle,
-    ReadSingleRO,
-}
 
-const OP_TYPE_ALL: &[OpType] =
-    &[OpType::ReadSingle, OpType::WriteSingle, OpType::ReadSingleRO];
-
-impl Display for OpType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                OpType::ReadSingle => {
-                    "Read Single"
-                }
-                OpType::WriteSingle => {
-                    "Write Single"
-                }
-                OpType::ReadSingleRO => {
-                    "Read Single RO"
-                }
-            }
-        )
-    }
-}
+pub use crate::message_sender::{
+    DataType, DateTimeLayout, DeviceIdLevel, OpType, ValueFormat,
+};
+
+const OP_TYPE_ALL: &[OpType] = &[
+    OpType::ReadSingle,
+    OpType::WriteSingle,
+    OpType::ReadSingleRO,
+    OpType::CommEventCounter,
+    OpType::ReadExceptionStatus,
+    OpType::Loopback,
+    OpType::WriteCoil,
+    OpType::ReadFileRecord,
+    OpType::ReadDeviceId,
+    OpType::WriteMultiple,
+    OpType::Custom,
+];
+
+/// Most times a single Send may fire its request
+const MAX_REPEAT: u16 = 100;
+
+const DEVICE_ID_LEVEL_ALL: &[DeviceIdLevel] =
+    &[DeviceIdLevel::Basic, DeviceIdLevel::Regular];
+
+const DATA_TYPE_ALL: &[DataType] = &[
+    DataType::U16,
+    DataType::Ascii,
+    DataType::Bcd,
+    DataType::U64,
+    DataType::I64,
+    DataType::F64,
+    DataType::DateTime,
+];
+
+/// Types a Write Multiple can encode its values as
+const WRITE_DATA_TYPE_ALL: &[DataType] =
+    &[DataType::U16, DataType::U64, DataType::I64, DataType::F64];
+
+const VALUE_FORMAT_ALL: &[ValueFormat] = &[
+    ValueFormat::Plain,
+    ValueFormat::Percent,
+    ValueFormat::Scientific,
+    ValueFormat::Thousands,
+];
+
+const DATE_TIME_LAYOUT_ALL: &[DateTimeLayout] = &[
+    DateTimeLayout::BcdYearFirst,
+    DateTimeLayout::BcdSecondFirst,
+    DateTimeLayout::Bytes,
+    DateTimeLayout::Words,
+];
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct OpView {
@@ -49,7 +88,138 @@ pub struct OpView {
     pub(crate) op_type: OpType,
     pub(crate) op_addr: String,
     pub(crate) op_val: String,
-    pub(crate) eval_str: String,
+    /// Converts a raw register value read from the device into the displayed
+    /// value, `engineering = read_eval(raw)`
+    #[serde(alias = "eval_str")]
+    pub(crate) read_eval: String,
+    /// Converts an entered value into the raw register value to write,
+    /// `raw = write_eval(engineering)`
+    #[serde(default = "default_eval")]
+    pub(crate) write_eval: String,
+    /// Saturate written values that evaluate outside [0, 0xFFFF] instead of
+    /// refusing the write
+    #[serde(default)]
+    pub(crate) clamp: bool,
+    /// Labels for read values, written as `value=label` pairs separated by
+    /// `;`, e.g. `0=Stopped;3=Running`
+    #[serde(default)]
+    pub(crate) labels: String,
+    /// How read registers are interpreted
+    #[serde(default)]
+    pub(crate) data_type: DataType,
+    /// How a date/time read packs its registers
+    #[serde(default)]
+    pub(crate) date_time_layout: DateTimeLayout,
+    /// Number of registers to read, only used by types without a fixed size
+    #[serde(default)]
+    pub(crate) quantity: String,
+    /// Swap the high and low byte of each register before interpreting it
+    #[serde(default)]
+    pub(crate) byte_swap: bool,
+    /// Combine 64-bit values starting from the least significant register
+    /// instead of the most significant one
+    #[serde(default)]
+    pub(crate) word_swap: bool,
+    /// First bit of the bit field to extract from the register, empty to use
+    /// the whole register
+    #[serde(default)]
+    pub(crate) bit_start: String,
+    /// Width of the bit field, defaults to a single bit
+    #[serde(default)]
+    pub(crate) bit_width: String,
+    /// Width of a signed value in the extracted bits, empty for unsigned
+    #[serde(default)]
+    pub(crate) signed_bits: String,
+    /// Read values are multiplied by this after the read conversion, empty
+    /// for 1
+    #[serde(default)]
+    pub(crate) scale: String,
+    /// Added to scaled read values, empty for 0
+    #[serde(default)]
+    pub(crate) offset: String,
+    /// Show the write value in hex, only whole non-negative values are
+    /// converted
+    #[serde(default)]
+    pub(crate) hex: bool,
+    /// Amount the stepper buttons change the write value by, empty for 1
+    #[serde(default)]
+    pub(crate) step: String,
+    /// File number of a Read File Record op
+    #[serde(default)]
+    pub(crate) file_number: String,
+    /// First record of a Read File Record op
+    #[serde(default)]
+    pub(crate) record_number: String,
+    /// Registers read by a Read File Record op
+    #[serde(default)]
+    pub(crate) record_len: String,
+    /// Objects read by a Read Device ID op
+    #[serde(default)]
+    pub(crate) device_id_level: DeviceIdLevel,
+    /// Function code of a custom op, in [1, 127]
+    #[serde(default)]
+    pub(crate) function_code: String,
+    /// Read timeout in milliseconds for this op, empty to use the port default
+    #[serde(default)]
+    pub(crate) timeout_ms: String,
+    /// Delay before reading the response in milliseconds, empty to use the
+    /// port default
+    #[serde(default)]
+    pub(crate) read_delay_ms: String,
+    /// Read only once when the continuous quarry starts
+    #[serde(default)]
+    pub(crate) read_once: bool,
+    /// Times Send fires the request in a row, empty for once
+    #[serde(default)]
+    pub(crate) repeat: String,
+    /// Decimal places read values are shown with, empty to show all
+    #[serde(default)]
+    pub(crate) precision: String,
+    /// How read values are shown, after the read conversion
+    #[serde(default)]
+    pub(crate) value_format: ValueFormat,
+    /// Shown after read values, empty for none
+    #[serde(default)]
+    pub(crate) unit: String,
+    /// Continuous values below this are flagged, empty for no limit
+    #[serde(default)]
+    pub(crate) alarm_low: String,
+    /// Continuous values above this are flagged, empty for no limit
+    #[serde(default)]
+    pub(crate) alarm_high: String,
+
+    /// The address is PLC-style (`40001`), set from the owning list
+    #[serde(skip)]
+    pub(crate) plc_addressing: bool,
+    /// A one-shot request of this op is queued or waiting for its response
+    #[serde(skip)]
+    in_flight: bool,
+    /// Show the frame a request of this op would send
+    #[serde(skip)]
+    show_frame: bool,
+    /// Address of the port's device for the frame preview, set from the
+    /// owning list
+    #[serde(skip)]
+    pub(crate) port_device_addr: Option<u8>,
+    /// Checksum of the port for the frame preview, set from the owning list
+    #[serde(skip)]
+    pub(crate) port_crc: CrcConfig,
+
+    /// Why this op can't be converted to an `Operation`, updated on each edit
+    #[serde(skip)]
+    error: Option<Error>,
+    /// What looks wrong with an op that can still be sent, updated on each
+    /// edit
+    #[serde(skip)]
+    warning: Option<String>,
+    /// Characters the last edit of a numeric input dropped, cleared by the
+    /// next edit
+    #[serde(skip)]
+    rejected: Option<String>,
+}
+
+fn default_eval() -> String {
+    "val".to_string()
 }
 
 impl OpView {
@@ -58,17 +228,173 @@ impl OpView {
         op_type: OpType,
         op_addr: String,
         op_val: String,
-        eval_str: String,
+        read_eval: String,
+        write_eval: String,
     ) -> Self {
-        Self { name, op_type, op_addr, op_val, eval_str }
+        Self {
+            name,
+            op_type,
+            op_addr,
+            op_val,
+            read_eval,
+            write_eval,
+            clamp: false,
+            labels: String::new(),
+            data_type: DataType::U16,
+            date_time_layout: DateTimeLayout::BcdYearFirst,
+            quantity: String::new(),
+            byte_swap: false,
+            word_swap: false,
+            bit_start: String::new(),
+            bit_width: String::new(),
+            signed_bits: String::new(),
+            scale: String::new(),
+            offset: String::new(),
+            file_number: String::new(),
+            record_number: String::new(),
+            record_len: String::new(),
+            device_id_level: DeviceIdLevel::Basic,
+            function_code: String::new(),
+            timeout_ms: String::new(),
+            read_delay_ms: String::new(),
+            read_once: false,
+            repeat: String::new(),
+            precision: String::new(),
+            value_format: ValueFormat::Plain,
+            unit: String::new(),
+            alarm_low: String::new(),
+            alarm_high: String::new(),
+            hex: false,
+            step: String::new(),
+            plc_addressing: false,
+            in_flight: false,
+            show_frame: false,
+            port_device_addr: None,
+            port_crc: CrcConfig::default(),
+            error: None,
+            warning: None,
+            rejected: None,
+        }
+    }
+
+    /// Nudge the write value one step up or down and return the op to send,
+    /// `None` if either value doesn't parse or the stepped value can't be
+    /// written. A clamping op steps onto the register bound once and stops
+    pub fn step(&mut self, up: bool) -> Option<OpView> {
+        let step = if self.step.trim().is_empty() {
+            1f64
+        } else {
+            self.step.parse_num::<f64>().ok()?
+        };
+        let val = self.op_val.parse_num::<f64>().ok()?;
+
+        let mut stepped = self.clone();
+        stepped.op_val =
+            self.format_value(if up { val + step } else { val - step });
+
+        let current = Operation::try_from(self.clone()).ok();
+        let next = Operation::try_from(stepped.clone()).ok()?;
+        if next.clamped && current.map_or(false, |op| op.clamped) {
+            return None;
+        }
+
+        *self = stepped;
+        self.validate();
+        Some(self.clone())
+    }
+
+    /// Format a write value in the base chosen for this op
+    fn format_value(&self, val: f64) -> String {
+        if self.hex
+            && val.fract() == 0f64
+            && (0f64..=u64::MAX as f64).contains(&val)
+        {
+            format!("0x{:X}", val as u64)
+        } else {
+            // round away the float noise of repeatedly adding fractional steps
+            ((val * 1e9).round() / 1e9).to_string()
+        }
+    }
+
+    /// Hex of the frame a request of this op would send to `device_addr`
+    /// with the checksum `crc`, with the checksum apart so it can be checked
+    /// by hand. Nothing is sent
+    fn frame_preview(&self, device_addr: Option<u8>, crc: CrcConfig) -> String {
+        let device_addr = match device_addr {
+            Some(device_addr) => device_addr,
+            None => return "Frame: enter a valid device address".to_string(),
+        };
+        let op = match Operation::try_from(self.clone()) {
+            Ok(op) => op,
+            // the error is already shown below
+            Err(_) => return "Frame: -".to_string(),
+        };
+
+        let port_conf = PortConfig { device_addr, crc, ..PortConfig::default() };
+        let bytes = op.to_modbus_bytes(&port_conf);
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let (body, trailer) =
+            bytes.split_at(bytes.len().saturating_sub(crc.trailer_len()));
+
+        match crc.algorithm {
+            CrcAlgorithm::None => format!("Frame: {}", hex(body)),
+            _ => format!("Frame: {} CRC {}", hex(body), hex(trailer)),
+        }
+    }
+
+    /// Last written state of a coil op
+    fn coil_on(&self) -> bool {
+        self.op_val.parse_num::<f64>().map_or(false, |val| val != 0f64)
     }
 
-    pub fn view(&self) -> Element<OpViewMessage> {
-        Row::new()
-            .width(Length::FillPortion(10))
+    /// Times Send fires the request, the count must have been validated
+    pub fn repeat_count(&self) -> usize {
+        self.repeat.parse_num::<usize>().unwrap_or(1)
+    }
+
+    /// Re-check whether this op converts to a valid `Operation`
+    pub fn validate(&mut self) {
+        match Operation::try_from(self.clone()) {
+            Ok(op) => {
+                self.error = None;
+                self.warning = op.write_warning;
+            }
+            Err(e) => {
+                self.error = Some(e);
+                self.warning = None;
+            }
+        }
+    }
+
+    /// `name_id` identifies the name input so keyboard navigation can focus
+    /// it
+    pub fn view(&self, name_id: text_input::Id) -> Element<OpViewMessage> {
+        let mut send = Button::new(
+            Text::new(if self.in_flight { "..." } else { "Send" })
+                .vertical_alignment(Vertical::Center)
+                .horizontal_alignment(Horizontal::Center)
+                .size(TextSize::HEADING),
+        )
+        .width(Length::FillPortion(8))
+        .padding([0, 2]);
+        // an invalid op would only fail after being sent, so disable it, and
+        // while it is in flight the click already registered
+        if self.error.is_none() && !self.in_flight {
+            send = send.on_press(OpViewMessage::SendRequest(self.clone()));
+        }
+
+        let row = Row::new()
+            .width(Length::Fill)
             .align_items(Alignment::Center)
             .push(
                 TextInput::new("Name", &self.name, OpViewMessage::SetName)
+                    .id(name_id)
                     .width(Length::FillPortion(15))
                     .padding([0, 2]),
             )
@@ -82,20 +408,146 @@ impl OpView {
                 .padding([0, 2]),
             )
             .push({
-                let row = Row::new()
+                let mut row = Row::new()
                     .width(Length::FillPortion(30))
-                    .align_items(Alignment::Center)
-                    .push(
+                    .align_items(Alignment::Center);
+                if self.op_type.has_address() {
+                    row = row.push(
                         TextInput::new(
-                            "Address",
+                            if self.plc_addressing {
+                                "Address, e.g. 40001"
+                            } else {
+                                "Address"
+                            },
                             &self.op_addr,
                             OpViewMessage::SetOpAddr,
                         )
                         .width(Length::Fill)
                         .padding([0, 2]),
                     );
+                }
 
-                if self.op_type == OpType::WriteSingle {
+                if self.op_type == OpType::ReadFileRecord {
+                    row.push(
+                        TextInput::new(
+                            "File",
+                            &self.file_number,
+                            OpViewMessage::SetFileNumber,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Record",
+                            &self.record_number,
+                            OpViewMessage::SetRecordNumber,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Length",
+                            &self.record_len,
+                            OpViewMessage::SetRecordLength,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                } else if self.op_type == OpType::ReadDeviceId {
+                    row.push(
+                        PickList::new(
+                            DEVICE_ID_LEVEL_ALL,
+                            Some(self.device_id_level),
+                            OpViewMessage::SelectDeviceIdLevel,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                } else if self.op_type == OpType::WriteMultiple {
+                    row.push(
+                        TextInput::new(
+                            "Values, e.g. 1, 2, 0x1F",
+                            &self.op_val,
+                            OpViewMessage::SetOpValue,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Registers",
+                            &self.quantity,
+                            OpViewMessage::SetQuantity,
+                        )
+                        .width(Length::Units(72))
+                        .padding([0, 2]),
+                    )
+                    .push(Checkbox::new(
+                        self.hex,
+                        "Hex",
+                        OpViewMessage::SetHex,
+                    ))
+                } else if self.op_type == OpType::Custom {
+                    row.push(
+                        TextInput::new(
+                            "Function",
+                            &self.function_code,
+                            OpViewMessage::SetFunctionCode,
+                        )
+                        .width(Length::Units(72))
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Address, optional",
+                            &self.op_addr,
+                            OpViewMessage::SetOpAddr,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Quantity, optional",
+                            &self.quantity,
+                            OpViewMessage::SetQuantity,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Payload hex, e.g. 01 A0",
+                            &self.op_val,
+                            OpViewMessage::SetOpValue,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                } else if self.op_type == OpType::WriteCoil {
+                    row.push(Checkbox::new(
+                        self.coil_on(),
+                        "On",
+                        OpViewMessage::ToggleCoil,
+                    ))
+                } else if self.op_type == OpType::Loopback {
+                    row.push(
+                        TextInput::new(
+                            "Pattern",
+                            &self.op_val,
+                            OpViewMessage::SetOpValue,
+                        )
+                        .width(Length::Fill)
+                        .padding([0, 2]),
+                    )
+                    .push(Checkbox::new(
+                        self.hex,
+                        "Hex",
+                        OpViewMessage::SetHex,
+                    ))
+                } else if self.op_type == OpType::WriteSingle {
                     row.push(
                         TextInput::new(
                             "Value",
@@ -105,35 +557,353 @@ impl OpView {
                         .width(Length::Fill)
                         .padding([0, 2]),
                     )
+                    .push(Button::new("-").on_press(OpViewMessage::Step(false)))
+                    .push(
+                        TextInput::new(
+                            "Step",
+                            &self.step,
+                            OpViewMessage::SetStep,
+                        )
+                        .width(Length::Units(48))
+                        .padding([0, 2]),
+                    )
+                    .push(Button::new("+").on_press(OpViewMessage::Step(true)))
+                    .push(Checkbox::new(self.hex, "Hex", OpViewMessage::SetHex))
+                    .push(Checkbox::new(
+                        self.clamp,
+                        "Clamp",
+                        OpViewMessage::SetClamp,
+                    ))
                 } else {
                     row
                 }
             })
             .push(
+                // writes only ever use the write conversion, and reads only
+                // the read conversion, so show the one that applies
+                match self.op_type {
+                    OpType::WriteSingle => TextInput::new(
+                        "Write Conversion",
+                        &self.write_eval,
+                        OpViewMessage::SetWriteEval,
+                    )
+                    .width(Length::FillPortion(25))
+                    .padding([0, 2])
+                    .into(),
+                    OpType::ReadSingle | OpType::ReadSingleRO => TextInput::new(
+                        "Read Conversion",
+                        &self.read_eval,
+                        OpViewMessage::SetReadEval,
+                    )
+                    .width(Length::FillPortion(25))
+                    .padding([0, 2])
+                    .into(),
+                    // the status byte has no conversion, but can be labeled
+                    OpType::ReadExceptionStatus => TextInput::new(
+                        "Labels",
+                        &self.labels,
+                        OpViewMessage::SetLabels,
+                    )
+                    .width(Length::FillPortion(25))
+                    .padding([0, 2])
+                    .into(),
+                    OpType::CommEventCounter
+                    | OpType::Loopback
+                    | OpType::WriteCoil
+                    | OpType::ReadFileRecord
+                    | OpType::ReadDeviceId
+                    | OpType::WriteMultiple
+                    | OpType::Custom => {
+                        Element::from(Space::with_width(Length::FillPortion(25)))
+                    }
+                },
+            )
+            .push(
+                TextInput::new(
+                    "Timeout ms",
+                    &self.timeout_ms,
+                    OpViewMessage::SetTimeout,
+                )
+                .width(Length::Units(96))
+                .padding([0, 2]),
+            )
+            .push(
+                TextInput::new(
+                    "Delay ms",
+                    &self.read_delay_ms,
+                    OpViewMessage::SetReadDelay,
+                )
+                .width(Length::Units(80))
+                .padding([0, 2]),
+            )
+            .push(Checkbox::new(
+                self.show_frame,
+                "Frame",
+                OpViewMessage::SetShowFrame,
+            ));
+        // read, write and read back to check the device took the value
+        let row = if self.op_type == OpType::WriteSingle {
+            let mut verify = Button::new(
+                Text::new("Verify")
+                    .vertical_alignment(Vertical::Center)
+                    .horizontal_alignment(Horizontal::Center)
+                    .size(TextSize::HEADING),
+            )
+            .padding([0, 2]);
+            if self.error.is_none() && !self.in_flight {
+                verify =
+                    verify.on_press(OpViewMessage::VerifyWrite(self.clone()));
+            }
+            row.push(verify)
+        } else {
+            row
+        }
+        .push(
+            TextInput::new("Repeat", &self.repeat, OpViewMessage::SetRepeat)
+                .width(Length::Units(64))
+                .padding([0, 2]),
+        )
+        .push(send);
+
+        let mut column = Column::new().width(Length::FillPortion(10)).push(row);
+        if matches!(self.op_type, OpType::ReadSingle | OpType::ReadSingleRO) {
+            column = column.push(self.read_format_view());
+        } else if self.op_type == OpType::WriteMultiple {
+            column = column.push(self.write_format_view());
+        }
+        if self.show_frame {
+            column = column.push(
+                Text::new(
+                    self.frame_preview(self.port_device_addr, self.port_crc),
+                )
+                .size(TextSize::SMALL),
+            );
+        }
+        if let Some(error) = &self.error {
+            column = column.push(
+                Text::new(error.message())
+                    .size(TextSize::SMALL)
+                    .style(Color::from_rgb(0.8, 0.2, 0.2)),
+            );
+        }
+        if let Some(warning) = &self.warning {
+            column = column.push(
+                Text::new(warning)
+                    .size(TextSize::SMALL)
+                    .style(Color::from_rgb(0.8, 0.6, 0.1)),
+            );
+        }
+        if let Some(rejected) = &self.rejected {
+            column = column.push(
+                Text::new(format!(
+                    "Ignored \"{}\", only numbers can be entered here",
+                    rejected
+                ))
+                .size(TextSize::SMALL)
+                .style(Color::from_rgb(0.8, 0.6, 0.1)),
+            );
+        }
+
+        column.into()
+    }
+
+    /// Second row of a read op, controls how the response is interpreted
+    fn read_format_view(&self) -> Element<OpViewMessage> {
+        let mut row = Row::new()
+            .width(Length::Fill)
+            .align_items(Alignment::Center)
+            .padding([2, 0])
+            .push(
+                PickList::new(
+                    DATA_TYPE_ALL,
+                    Some(self.data_type),
+                    OpViewMessage::SelectDataType,
+                )
+                .width(Length::Units(100))
+                .padding([0, 2]),
+            );
+
+        if self.data_type == DataType::DateTime {
+            row = row.push(
+                PickList::new(
+                    DATE_TIME_LAYOUT_ALL,
+                    Some(self.date_time_layout),
+                    OpViewMessage::SelectDateTimeLayout,
+                )
+                .width(Length::Units(200))
+                .padding([0, 2]),
+            );
+        }
+
+        if self.data_type == DataType::Ascii {
+            row = row.push(
                 TextInput::new(
-                    "Value Conversion",
-                    &self.eval_str,
-                    OpViewMessage::SetEval,
+                    "Registers",
+                    &self.quantity,
+                    OpViewMessage::SetQuantity,
                 )
-                .width(Length::FillPortion(25))
+                .width(Length::Units(96))
                 .padding([0, 2]),
+            );
+        }
+
+        row = row.push(Checkbox::new(
+            self.byte_swap,
+            "Byte Swap",
+            OpViewMessage::SetByteSwap,
+        ));
+
+        if self.data_type.is_64_bit() {
+            row = row.push(Checkbox::new(
+                self.word_swap,
+                "Word Swap",
+                OpViewMessage::SetWordSwap,
+            ));
+        }
+
+        row = row.push(Checkbox::new(
+            self.read_once,
+            "Read Once",
+            OpViewMessage::SetReadOnce,
+        ));
+
+        row.push(
+            TextInput::new("Bit", &self.bit_start, OpViewMessage::SetBitStart)
+                .width(Length::Units(48))
+                .padding([0, 2]),
+        )
+        .push(
+            TextInput::new("Width", &self.bit_width, OpViewMessage::SetBitWidth)
+                .width(Length::Units(64))
+                .padding([0, 2]),
+        )
+        .push(
+            TextInput::new(
+                "Signed Bits",
+                &self.signed_bits,
+                OpViewMessage::SetSignedBits,
             )
+            .width(Length::Units(96))
+            .padding([0, 2]),
+        )
+        .push(
+            TextInput::new("Scale", &self.scale, OpViewMessage::SetScale)
+                .width(Length::Units(72))
+                .padding([0, 2]),
+        )
+        .push(
+            TextInput::new("Offset", &self.offset, OpViewMessage::SetOffset)
+                .width(Length::Units(72))
+                .padding([0, 2]),
+        )
+        .push(
+            TextInput::new(
+                "Decimals",
+                &self.precision,
+                OpViewMessage::SetPrecision,
+            )
+            .width(Length::Units(72))
+            .padding([0, 2]),
+        )
+        .push(
+            PickList::new(
+                VALUE_FORMAT_ALL,
+                Some(self.value_format),
+                OpViewMessage::SelectValueFormat,
+            )
+            .width(Length::Units(120))
+            .padding([0, 2]),
+        )
+        .push(
+            TextInput::new("Unit", &self.unit, OpViewMessage::SetUnit)
+                .width(Length::Units(64))
+                .padding([0, 2]),
+        )
+        .push(
+            TextInput::new(
+                "Low Alarm",
+                &self.alarm_low,
+                OpViewMessage::SetAlarmLow,
+            )
+            .width(Length::Units(96))
+            .padding([0, 2]),
+        )
+        .push(
+            TextInput::new(
+                "High Alarm",
+                &self.alarm_high,
+                OpViewMessage::SetAlarmHigh,
+            )
+            .width(Length::Units(96))
+            .padding([0, 2]),
+        )
+        .push(
+            TextInput::new("Labels", &self.labels, OpViewMessage::SetLabels)
+                .width(Length::Fill)
+                .padding([0, 2]),
+        )
+        .into()
+    }
+
+    /// Second row of a Write Multiple op, controls how its values are
+    /// encoded, the same way a read of the type decodes them
+    fn write_format_view(&self) -> Element<OpViewMessage> {
+        // a type only reads can have was kept from before the op type changed
+        let data_type = WRITE_DATA_TYPE_ALL
+            .contains(&self.data_type)
+            .then_some(self.data_type);
+        let mut row = Row::new()
+            .width(Length::Fill)
+            .align_items(Alignment::Center)
+            .padding([2, 0])
             .push(
-                Button::new(
-                    Text::new("Send")
-                        .vertical_alignment(Vertical::Center)
-                        .horizontal_alignment(Horizontal::Center)
-                        .size(20),
+                PickList::new(
+                    WRITE_DATA_TYPE_ALL,
+                    data_type,
+                    OpViewMessage::SelectDataType,
                 )
-                .on_press(OpViewMessage::SendRequest(self.clone()))
-                .width(Length::FillPortion(8))
+                .width(Length::Units(100))
                 .padding([0, 2]),
             )
-            .into()
+            .push(Checkbox::new(
+                self.byte_swap,
+                "Byte Swap",
+                OpViewMessage::SetByteSwap,
+            ));
+
+        if self.data_type.is_64_bit() {
+            row = row.push(Checkbox::new(
+                self.word_swap,
+                "Word Swap",
+                OpViewMessage::SetWordSwap,
+            ));
+        }
+
+        row.into()
+    }
+
+    /// The part of a numeric input's new text it keeps, noting the characters
+    /// it drops
+    fn mask(&mut self, input: &str, mask: InputMask) -> String {
+        let (kept, dropped) = mask_input(input, mask);
+        if !dropped.is_empty() {
+            self.rejected = Some(dropped);
+        }
+        kept
+    }
+
+    /// How the value input is masked, it takes lists of values for Write
+    /// Multiple and custom payloads
+    fn value_mask(&self) -> InputMask {
+        match self.op_type {
+            OpType::WriteMultiple | OpType::Custom => InputMask::List,
+            _ => InputMask::Number,
+        }
     }
 
     pub fn update(&mut self, message: OpViewMessage) -> Command<OpViewMessage> {
-        match message {
+        self.rejected = None;
+        let command = match message {
             OpViewMessage::SetName(val) => {
                 self.name = val;
                 Command::none()
@@ -143,21 +913,151 @@ impl OpView {
                 Command::none()
             }
             OpViewMessage::SetOpAddr(val) => {
-                self.op_addr = val;
+                self.op_addr = self.mask(&val, InputMask::Integer);
                 Command::none()
             }
             OpViewMessage::SetOpValue(val) => {
-                self.op_val = val;
+                self.op_val = self.mask(&val, self.value_mask());
+                Command::none()
+            }
+            OpViewMessage::SetClamp(clamp) => {
+                self.clamp = clamp;
+                Command::none()
+            }
+            OpViewMessage::SetLabels(val) => {
+                self.labels = val;
+                Command::none()
+            }
+            OpViewMessage::SelectDataType(data_type) => {
+                self.data_type = data_type;
+                Command::none()
+            }
+            OpViewMessage::SelectDateTimeLayout(layout) => {
+                self.date_time_layout = layout;
+                Command::none()
+            }
+            OpViewMessage::SetQuantity(val) => {
+                self.quantity = val;
+                Command::none()
+            }
+            OpViewMessage::SetShowFrame(show_frame) => {
+                self.show_frame = show_frame;
+                Command::none()
+            }
+            OpViewMessage::SetByteSwap(byte_swap) => {
+                self.byte_swap = byte_swap;
+                Command::none()
+            }
+            OpViewMessage::SetWordSwap(word_swap) => {
+                self.word_swap = word_swap;
+                Command::none()
+            }
+            OpViewMessage::SetBitStart(val) => {
+                self.bit_start = val;
+                Command::none()
+            }
+            OpViewMessage::SetBitWidth(val) => {
+                self.bit_width = val;
+                Command::none()
+            }
+            OpViewMessage::SetSignedBits(val) => {
+                self.signed_bits = val;
+                Command::none()
+            }
+            OpViewMessage::SetScale(val) => {
+                self.scale = val;
+                Command::none()
+            }
+            OpViewMessage::SetOffset(val) => {
+                self.offset = val;
+                Command::none()
+            }
+            OpViewMessage::SetHex(hex) => {
+                self.hex = hex;
+                // present the entered value in the new base
+                if let Ok(val) = self.op_val.parse_num::<f64>() {
+                    self.op_val = self.format_value(val);
+                }
+                Command::none()
+            }
+            OpViewMessage::SetStep(val) => {
+                self.step = val;
+                Command::none()
+            }
+            OpViewMessage::SelectDeviceIdLevel(level) => {
+                self.device_id_level = level;
+                Command::none()
+            }
+            OpViewMessage::SetFileNumber(val) => {
+                self.file_number = val;
+                Command::none()
+            }
+            OpViewMessage::SetRecordNumber(val) => {
+                self.record_number = val;
                 Command::none()
             }
-            OpViewMessage::SetEval(val) => {
-                self.eval_str = val;
+            OpViewMessage::SetRecordLength(val) => {
+                self.record_len = val;
                 Command::none()
             }
-            OpViewMessage::SendRequest(_) => {
+            OpViewMessage::SetFunctionCode(val) => {
+                self.function_code = val;
+                Command::none()
+            }
+            OpViewMessage::SetTimeout(val) => {
+                self.timeout_ms = val;
+                Command::none()
+            }
+            OpViewMessage::SetReadDelay(val) => {
+                self.read_delay_ms = val;
+                Command::none()
+            }
+            OpViewMessage::SetReadOnce(read_once) => {
+                self.read_once = read_once;
+                Command::none()
+            }
+            OpViewMessage::SetRepeat(val) => {
+                self.repeat = val;
+                Command::none()
+            }
+            OpViewMessage::SetPrecision(val) => {
+                self.precision = val;
+                Command::none()
+            }
+            OpViewMessage::SelectValueFormat(value_format) => {
+                self.value_format = value_format;
+                Command::none()
+            }
+            OpViewMessage::SetUnit(val) => {
+                self.unit = val;
+                Command::none()
+            }
+            OpViewMessage::SetAlarmLow(val) => {
+                self.alarm_low = val;
+                Command::none()
+            }
+            OpViewMessage::SetAlarmHigh(val) => {
+                self.alarm_high = val;
+                Command::none()
+            }
+            OpViewMessage::SetReadEval(val) => {
+                self.read_eval = val;
+                Command::none()
+            }
+            OpViewMessage::SetWriteEval(val) => {
+                self.write_eval = val;
+                Command::none()
+            }
+            OpViewMessage::SendRequest(_)
+            | OpViewMessage::VerifyWrite(_)
+            | OpViewMessage::Step(_)
+            | OpViewMessage::ToggleCoil(_) => {
                 unreachable!();
             }
-        }
+        };
+
+        self.validate();
+        command
     }
 }
 
@@ -167,13 +1067,378 @@ pub enum OpViewMessage {
     SelectOpType(OpType),
     SetOpAddr(String),
     SetOpValue(String),
-    SetEval(String),
+    SetClamp(bool),
+    SetLabels(String),
+    SelectDataType(DataType),
+    SelectDateTimeLayout(DateTimeLayout),
+    SetQuantity(String),
+    SetByteSwap(bool),
+    SetWordSwap(bool),
+    SetBitStart(String),
+    SetBitWidth(String),
+    SetSignedBits(String),
+    SetScale(String),
+    SetOffset(String),
+    SetFileNumber(String),
+    SetRecordNumber(String),
+    SetRecordLength(String),
+    SelectDeviceIdLevel(DeviceIdLevel),
+    SetFunctionCode(String),
+    SetTimeout(String),
+    SetReadDelay(String),
+    SetReadOnce(bool),
+    SetRepeat(String),
+    SetPrecision(String),
+    SelectValueFormat(ValueFormat),
+    SetUnit(String),
+    SetAlarmLow(String),
+    SetAlarmHigh(String),
+    SetStep(String),
+    SetHex(bool),
+    /// Preview the request frame without sending it
+    SetShowFrame(bool),
+    /// Step the write value up (`true`) or down and send it
+    Step(bool),
+    /// Switch a coil op and send it
+    ToggleCoil(bool),
+    SetReadEval(String),
+    SetWriteEval(String),
     SendRequest(OpView),
+    /// Write the value and read it back, handled by the owner of the list
+    VerifyWrite(OpView),
+}
+
+/// Removed ops kept for undo
+const MAX_UNDO: usize = 16;
+
+/// Parse the raw protocol address of an op
+fn parse_addr(value: &OpView) -> Result<u16, Error> {
+    match value.op_addr.parse_num::<u16>() {
+        Ok(addr) => Ok(addr),
+        // the address input is hidden for these
+        Err(_) if !value.op_type.has_address() => Ok(0),
+        Err(_) => {
+            let reason = match value.op_addr.parse_num::<f64>() {
+                Ok(addr) if addr.fract() != 0f64 => "is not a whole number",
+                Ok(_) => "is out of range",
+                Err(_) => "is not a number",
+            };
+
+            Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!(
+                    "Register address \"{}\" {}, it must be a whole number \
+                    in [0, 65535]",
+                    value.op_addr, reason
+                ),
+            ))
+        }
+    }
+}
+
+impl TryFrom<OpView> for Operation {
+    type Error = Error;
+
+    fn try_from(value: OpView) -> Result<Self, Self::Error> {
+        let read_eval = parse_eval(&value.read_eval, READ_VARS)?;
+        // the companion register is only read if the expression needs it
+        let companion =
+            read_eval.clone().bindn(&READ_VARS[..READ_VARS.len() - 1]).is_err();
+        let uses_prev =
+            read_eval.clone().bindn(&["val", "addr", "dev", "next"]).is_err();
+
+        let labels = parse_labels(&value.labels)?;
+        let bit_field = parse_bit_field(&value.bit_start, &value.bit_width)?;
+        let signed_bits = parse_signed_bits(&value.signed_bits)?;
+        let (scale, offset) = parse_scaling(&value.scale, &value.offset)?;
+        let timeout = parse_timeout(&value.timeout_ms)?;
+        let precision = parse_precision(&value.precision)?;
+        let (alarm_low, alarm_high) =
+            parse_alarm_limits(&value.alarm_low, &value.alarm_high)?;
+        // only checked here, the count never reaches the port thread
+        if !value.repeat.trim().is_empty() {
+            parse_in_range(&value.repeat, "repeat count", 1..=MAX_REPEAT)?;
+        }
+        let read_delay = parse_delay(&value.read_delay_ms)?;
+
+        let (op_type, op_addr) =
+            if value.plc_addressing && value.op_type.has_address() {
+                let (table, addr) = parse_plc_addr(&value.op_addr)?;
+                (table.op_type(value.op_type)?, addr)
+            } else {
+                (value.op_type, parse_addr(&value)?)
+            };
+
+        let quantity = if value.data_type == DataType::Ascii
+            && matches!(value.op_type, OpType::ReadSingle | OpType::ReadSingleRO)
+        {
+            match value.quantity.parse_num::<u16>() {
+                Ok(quantity) if (1..=MAX_READ_QUANTITY).contains(&quantity) => {
+                    quantity
+                }
+                _ => {
+                    return Err(Error::with_message(
+                        ErrKind::RequestParseError,
+                        format!(
+                            "\"{}\" is not a valid register count in [1, {}]",
+                            value.quantity, MAX_READ_QUANTITY
+                        ),
+                    ))
+                }
+            }
+        } else {
+            1
+        };
+
+        let mut clamped = false;
+        let mut write_warning = None;
+        let mut write_values = vec![];
+        let mut payload = vec![];
+        let req = {
+            match op_type {
+                OpType::ReadSingle => Request::ReadSingle(op_addr),
+                OpType::WriteSingle => {
+                    let write_eval = parse_eval(&value.write_eval, WRITE_VARS)?;
+                    write_warning = check_write_eval(
+                        &write_eval,
+                        &value.write_eval,
+                        op_addr,
+                    );
+                    // unwrap because parse_eval already checked the binding
+                    let write_func = write_eval.bind2("val", "addr").unwrap();
+
+                    let val = match value.op_val.parse_num::<f64>() {
+                        Ok(val) => val,
+                        Err(_) => {
+                            return Err(Error::with_message(
+                                ErrKind::RequestParseError,
+                                format!(
+                                    "\"{}\" is no a valid register value",
+                                    value.op_val
+                                ),
+                            ))
+                        }
+                    };
+
+                    let mut eval_val = write_func(val, op_addr as f64).round();
+                    if eval_val < 0f64 || eval_val > u16::MAX as f64 {
+                        if !value.clamp {
+                            return Err(Error::with_message(
+                                ErrKind::MathOperationResultInOutOfRangeValue,
+                                format!(
+                                    "{} cannot be evaluated to a value in the \
+                                    range [0, 0xFFFF]",
+                                    value.op_val
+                                ),
+                            ));
+                        }
+
+                        eval_val = eval_val.clamp(0f64, u16::MAX as f64);
+                        clamped = true;
+                    }
+
+                    Request::WriteSingle(op_addr, val, eval_val as u16)
+                }
+                OpType::ReadSingleRO => Request::ReadSingleRO(op_addr),
+                OpType::CommEventCounter => Request::CommEventCounter,
+                OpType::ReadExceptionStatus => Request::ReadExceptionStatus,
+                OpType::Loopback => {
+                    let pattern = if value.op_val.trim().is_empty() {
+                        DEFAULT_LOOPBACK_PATTERN
+                    } else {
+                        match value.op_val.parse_num::<u16>() {
+                            Ok(pattern) => pattern,
+                            Err(_) => {
+                                return Err(Error::with_message(
+                                    ErrKind::RequestParseError,
+                                    format!(
+                                        "\"{}\" is not a valid 16 bit pattern",
+                                        value.op_val
+                                    ),
+                                ))
+                            }
+                        }
+                    };
+
+                    Request::Loopback(pattern)
+                }
+                OpType::WriteCoil => {
+                    let on = if value.op_val.trim().is_empty() {
+                        false
+                    } else {
+                        match value.op_val.parse_num::<f64>() {
+                            Ok(val) => val != 0f64,
+                            Err(_) => {
+                                return Err(Error::with_message(
+                                    ErrKind::RequestParseError,
+                                    format!(
+                                        "\"{}\" is not a valid coil state",
+                                        value.op_val
+                                    ),
+                                ))
+                            }
+                        }
+                    };
+
+                    Request::WriteCoil(op_addr, on)
+                }
+                // the response carries at most 120 registers of one record
+                OpType::ReadFileRecord => Request::ReadFileRecord(
+                    parse_in_range(
+                        &value.file_number,
+                        "file number",
+                        1..=0xFFFF,
+                    )?,
+                    parse_in_range(
+                        &value.record_number,
+                        "record number",
+                        0..=0x270F,
+                    )?,
+                    parse_in_range(&value.record_len, "record length", 1..=120)?,
+                ),
+                OpType::ReadDeviceId => {
+                    Request::ReadDeviceId(value.device_id_level)
+                }
+                OpType::WriteMultiple => {
+                    let values = if value.data_type.is_64_bit() {
+                        parse_u64_block(&value.op_val, value.data_type)?
+                    } else {
+                        parse_register_block(&value.op_val, value.hex)?
+                            .into_iter()
+                            .map(u64::from)
+                            .collect()
+                    };
+                    // the device reads them back the way this op would
+                    write_values = encode_registers(
+                        &values,
+                        value.data_type,
+                        value.byte_swap,
+                        value.word_swap,
+                    );
+                    // an entered count catches a block that was cut short
+                    if !value.quantity.trim().is_empty() {
+                        let count = parse_in_range(
+                            &value.quantity,
+                            "register count",
+                            1..=MAX_WRITE_QUANTITY as u16,
+                        )?;
+                        if count as usize != write_values.len() {
+                            return Err(Error::with_message(
+                                ErrKind::RequestParseError,
+                                format!(
+                                    "{} values given for {} registers",
+                                    write_values.len(),
+                                    count
+                                ),
+                            ));
+                        }
+                    }
+                    Request::WriteMultiple(op_addr, write_values.len() as u16)
+                }
+                OpType::Custom => {
+                    // setting the exception bit would be a reply, not a request
+                    let function = parse_in_range(
+                        &value.function_code,
+                        "function code",
+                        1..=127,
+                    )? as u8;
+                    let optional = |val: &str, what: &str| {
+                        if val.trim().is_empty() {
+                            Ok(None)
+                        } else {
+                            parse_in_range(val, what, 0..=0xFFFF).map(Some)
+                        }
+                    };
+                    let addr = optional(&value.op_addr, "register address")?;
+                    let quantity = optional(&value.quantity, "quantity")?;
+
+                    payload = parse_hex_bytes(&value.op_val)?;
+                    let data_len = 2 * (addr.is_some() as usize)
+                        + 2 * (quantity.is_some() as usize)
+                        + payload.len();
+                    if data_len > MAX_PDU_DATA {
+                        return Err(Error::with_message(
+                            ErrKind::RequestParseError,
+                            format!(
+                                "A request carries at most {} bytes after \
+                                the function code, got {}",
+                                MAX_PDU_DATA, data_len
+                            ),
+                        ));
+                    }
+                    Request::Custom(function, addr, quantity)
+                }
+            }
+        };
+
+        let op = Self {
+            name: value.name,
+            req,
+            read_eval: Arc::new(read_eval),
+            companion,
+            uses_prev,
+            clamped,
+            write_warning,
+            labels: Arc::new(labels),
+            write_values: Arc::new(write_values),
+            payload: Arc::new(payload),
+            data_type: value.data_type,
+            date_time_layout: value.date_time_layout,
+            quantity,
+            byte_swap: value.byte_swap,
+            word_swap: value.word_swap,
+            bit_field,
+            signed_bits,
+            scale,
+            offset,
+            read_once: value.read_once,
+            timeout,
+            device_addr: None,
+            read_delay,
+            precision,
+            value_format: value.value_format,
+            unit: value.unit.trim().to_string(),
+            alarm_low,
+            alarm_high,
+        };
+        op.check_quantity()?;
+
+        Ok(op)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct OpViewList {
     ops: Vec<OpView>,
+
+    #[serde(skip)]
+    import_path: String,
+    /// Removed ops and the index they were at, most recent last
+    #[serde(skip)]
+    removed: Vec<(usize, OpView)>,
+    /// Addresses of every op are PLC-style, see `parse_plc_addr`
+    #[serde(skip)]
+    plc_addressing: bool,
+    /// Device address of the port, shown in frame previews
+    #[serde(skip)]
+    device_addr: Option<u8>,
+    /// Checksum of the port, used by frame previews
+    #[serde(skip)]
+    crc: CrcConfig,
+    /// Prefix of the ids of the name inputs, unique per list
+    #[serde(skip)]
+    input_id: &'static str,
+    /// Row last edited or moved to with the keyboard
+    #[serde(skip)]
+    focused: Option<usize>,
+    /// Templates that can be inserted, set from the owner's library
+    #[serde(skip)]
+    template_names: Vec<String>,
+    #[serde(skip)]
+    template: Option<String>,
+    /// Name the list is saved as a template under
+    #[serde(skip)]
+    template_name: String,
 }
 
 impl Deref for OpViewList {
@@ -187,7 +1452,17 @@ impl Deref for OpViewList {
 impl TryFrom<OpViewList> for Vec<Operation> {
     type Error = crate::error::Error;
 
+    /// Fails on the first invalid op, or if two ops share a name since the
+    /// continuous view tells responses apart by name
     fn try_from(value: OpViewList) -> Result<Self, Self::Error> {
+        let mut names = HashSet::new();
+        if let Some(op) = value.ops.iter().find(|op| !names.insert(&op.name)) {
+            return Err(Error::with_message(
+                ErrKind::DuplicateOperationName,
+                format!("More than one operation is named \"{}\"", op.name),
+            ));
+        }
+
         value.ops.into_iter().map(|op| op.try_into()).collect()
     }
 }
@@ -196,11 +1471,113 @@ impl TryFrom<OpViewList> for Vec<Operation> {
 pub enum OpViewListMessage {
     AddOperation,
     RemoveOperation(usize),
+    /// Put the most recently removed op back where it was
+    UndoRemove,
     OpViewMessage(usize, OpViewMessage),
     SendRequest(OpView),
+    VerifyWrite(OpView),
+    SetImportPath(String),
+    /// Import a register map CSV, handled by the owner of the list
+    ImportCsv(String),
+    AddOperations(Vec<OpView>),
+    SelectTemplate(String),
+    SetTemplateName(String),
+    /// Add the ops of a template, handled by the owner of the list
+    InsertTemplate(String),
+    /// Save the list as a template, handled by the owner of the list
+    SaveTemplate(String),
+    /// Move the keyboard focus to the name of the next or previous row
+    FocusNextRow,
+    FocusPreviousRow,
+    FocusRow(usize),
 }
 
 impl OpViewList {
+    /// Upgrade ops loaded from a layout saved with layout version `version`
+    pub fn migrate(&mut self, version: u32) {
+        // version 1 had a single conversion, which writes applied to the
+        // entered value, it was kept as the read conversion after the split
+        if version < 2 {
+            for op in self.ops.iter_mut().filter(|op| {
+                op.op_type == OpType::WriteSingle
+                    && op.write_eval == default_eval()
+            }) {
+                op.write_eval =
+                    std::mem::replace(&mut op.read_eval, default_eval());
+            }
+        }
+    }
+
+    /// Show the coil state a device echoed back on the coil ops it was
+    /// written by
+    pub fn confirm_coil(&mut self, name: &str, addr: u16, on: bool) {
+        for op in self.ops.iter_mut().filter(|op| {
+            op.op_type == OpType::WriteCoil
+                && op.name == name
+                && Operation::try_from((*op).clone())
+                    .map_or(false, |op| op.req.addr() == Some(addr))
+        }) {
+            op.op_val = if on { "1" } else { "0" }.to_string();
+        }
+    }
+
+    /// Mark the ops with one-shot requests queued or in progress by name
+    pub fn mark_in_flight(&mut self, names: &[&str]) {
+        for op in self.ops.iter_mut() {
+            op.in_flight = names.contains(&op.name.as_str());
+        }
+    }
+
+    /// Switch every op between PLC-style and protocol addresses
+    pub fn set_plc_addressing(&mut self, plc_addressing: bool) {
+        self.plc_addressing = plc_addressing;
+        for op in self.ops.iter_mut() {
+            op.plc_addressing = plc_addressing;
+            op.validate();
+        }
+    }
+
+    /// Set the port's device address and checksum used by the frame previews
+    pub fn set_preview_port(&mut self, device_addr: Option<u8>, crc: CrcConfig) {
+        self.device_addr = device_addr;
+        self.crc = crc;
+        for op in self.ops.iter_mut() {
+            op.port_device_addr = device_addr;
+            op.port_crc = crc;
+        }
+    }
+
+    /// Set the templates that can be inserted into the list
+    pub fn set_template_names(&mut self, names: Vec<String>) {
+        if !self.template.as_ref().map_or(true, |name| names.contains(name)) {
+            self.template = None;
+        }
+        self.template_names = names;
+    }
+
+    /// Set the prefix of the name input ids, lists shown at the same time
+    /// need different ones
+    pub fn set_input_id(&mut self, input_id: &'static str) {
+        self.input_id = input_id;
+    }
+
+    fn name_input_id(&self, idx: usize) -> text_input::Id {
+        text_input::Id::new(format!("{}-name-{}", self.input_id, idx))
+    }
+
+    /// Focus the name input of row `idx`
+    fn focus_row(&mut self, idx: usize) -> Command<OpViewListMessage> {
+        self.focused = Some(idx);
+        text_input::focus(self.name_input_id(idx))
+    }
+
+    /// Validate every op, used once after loading a layout
+    pub fn validate(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.validate();
+        }
+    }
+
     pub fn view(&self) -> Element<OpViewListMessage> {
         let mut column =
             Column::new().width(Length::FillPortion(50)).height(Length::Shrink);
@@ -216,20 +1593,36 @@ impl OpViewList {
                             Text::new("-")
                                 .vertical_alignment(Vertical::Center)
                                 .horizontal_alignment(Horizontal::Center)
-                                .size(20),
+                                .size(TextSize::HEADING),
                         )
                         .on_press(OpViewListMessage::RemoveOperation(idx)),
                     )
-                    .push(op.view().map(move |msg| {
-                        if let OpViewMessage::SendRequest(op_view) = msg {
-                            OpViewListMessage::SendRequest(op_view)
-                        } else {
-                            OpViewListMessage::OpViewMessage(idx, msg)
+                    .push(op.view(self.name_input_id(idx)).map(move |msg| {
+                        match msg {
+                            OpViewMessage::SendRequest(op_view) => {
+                                OpViewListMessage::SendRequest(op_view)
+                            }
+                            OpViewMessage::VerifyWrite(op_view) => {
+                                OpViewListMessage::VerifyWrite(op_view)
+                            }
+                            msg => OpViewListMessage::OpViewMessage(idx, msg),
                         }
                     })),
             );
         }
 
+        let mut undo = Button::new(
+            Text::new(match self.removed.last() {
+                Some((_, op)) => format!("Undo Remove \"{}\"", op.name),
+                None => "Undo Remove".to_string(),
+            })
+            .vertical_alignment(Vertical::Center)
+            .horizontal_alignment(Horizontal::Center),
+        );
+        if !self.removed.is_empty() {
+            undo = undo.on_press(OpViewListMessage::UndoRemove);
+        }
+
         column = column.push(
             Row::new()
                 .push(
@@ -241,6 +1634,59 @@ impl OpViewList {
                     .width(Length::Fill)
                     .on_press(OpViewListMessage::AddOperation),
                 )
+                .push(undo)
+                .padding(5),
+        );
+
+        column = column.push(
+            Row::new()
+                .align_items(Alignment::Center)
+                .push(
+                    TextInput::new(
+                        "Register map CSV path",
+                        &self.import_path,
+                        OpViewListMessage::SetImportPath,
+                    )
+                    .width(Length::Fill)
+                    .padding([0, 2]),
+                )
+                .push(Button::new("Import").on_press(
+                    OpViewListMessage::ImportCsv(self.import_path.clone()),
+                ))
+                .padding(5),
+        );
+
+        let mut insert = Button::new("Insert");
+        if let Some(template) = &self.template {
+            insert = insert
+                .on_press(OpViewListMessage::InsertTemplate(template.clone()));
+        }
+        column = column.push(
+            Row::new()
+                .align_items(Alignment::Center)
+                .spacing(4)
+                .push(
+                    PickList::new(
+                        self.template_names.as_slice(),
+                        self.template.clone(),
+                        OpViewListMessage::SelectTemplate,
+                    )
+                    .placeholder("Template")
+                    .width(Length::Fill),
+                )
+                .push(insert)
+                .push(
+                    TextInput::new(
+                        "Template name",
+                        &self.template_name,
+                        OpViewListMessage::SetTemplateName,
+                    )
+                    .width(Length::Fill)
+                    .padding([0, 2]),
+                )
+                .push(Button::new("Save as Template").on_press(
+                    OpViewListMessage::SaveTemplate(self.template_name.clone()),
+                ))
                 .padding(5),
         );
 
@@ -258,18 +1704,113 @@ impl OpViewList {
                     OpType::ReadSingle,
                     "".to_string(),
                     "".to_string(),
-                    "val".to_string(),
+                    default_eval(),
+                    default_eval(),
                 ));
-                Command::none()
+                // a new op has no address yet
+                let op = self.ops.last_mut().unwrap();
+                op.plc_addressing = self.plc_addressing;
+                op.port_device_addr = self.device_addr;
+                op.port_crc = self.crc;
+                op.validate();
+                self.focus_row(self.ops.len() - 1)
             }
             OpViewListMessage::RemoveOperation(idx) => {
-                self.ops.remove(idx);
+                let op = self.ops.remove(idx);
+                if self.removed.len() == MAX_UNDO {
+                    self.removed.remove(0);
+                }
+                self.removed.push((idx, op));
+                Command::none()
+            }
+            OpViewListMessage::UndoRemove => {
+                if let Some((idx, mut op)) = self.removed.pop() {
+                    // the list may have shrunk since
+                    let idx = idx.min(self.ops.len());
+                    op.plc_addressing = self.plc_addressing;
+                    op.port_device_addr = self.device_addr;
+                    op.port_crc = self.crc;
+                    op.validate();
+                    self.ops.insert(idx, op);
+                }
                 Command::none()
             }
-            OpViewListMessage::OpViewMessage(idx, msg) => self.ops[idx]
-                .update(msg)
-                .map(move |msg| OpViewListMessage::OpViewMessage(idx, msg)),
-            OpViewListMessage::SendRequest(_) => {
+            OpViewListMessage::OpViewMessage(idx, OpViewMessage::Step(up)) => {
+                match self.ops[idx].step(up) {
+                    Some(op) => Command::perform(
+                        async move { op },
+                        OpViewListMessage::SendRequest,
+                    ),
+                    None => Command::none(),
+                }
+            }
+            OpViewListMessage::OpViewMessage(
+                idx,
+                OpViewMessage::ToggleCoil(on),
+            ) => {
+                let op = &mut self.ops[idx];
+                op.op_val = if on { "1" } else { "0" }.to_string();
+                op.validate();
+
+                Command::perform(
+                    {
+                        let op = op.clone();
+                        async move { op }
+                    },
+                    OpViewListMessage::SendRequest,
+                )
+            }
+            OpViewListMessage::OpViewMessage(idx, msg) => {
+                self.focused = Some(idx);
+                self.ops[idx]
+                    .update(msg)
+                    .map(move |msg| OpViewListMessage::OpViewMessage(idx, msg))
+            }
+            OpViewListMessage::SetImportPath(path) => {
+                self.import_path = path;
+                Command::none()
+            }
+            OpViewListMessage::SelectTemplate(name) => {
+                self.template = Some(name);
+                Command::none()
+            }
+            OpViewListMessage::SetTemplateName(name) => {
+                self.template_name = name;
+                Command::none()
+            }
+            OpViewListMessage::AddOperations(mut ops) => {
+                for op in ops.iter_mut() {
+                    op.plc_addressing = self.plc_addressing;
+                    op.port_device_addr = self.device_addr;
+                    op.port_crc = self.crc;
+                    op.validate();
+                }
+                self.ops.append(&mut ops);
+                Command::none()
+            }
+            OpViewListMessage::FocusNextRow if !self.ops.is_empty() => {
+                let idx = self
+                    .focused
+                    .map_or(0, |idx| (idx + 1).min(self.ops.len() - 1));
+                self.focus_row(idx)
+            }
+            OpViewListMessage::FocusPreviousRow if !self.ops.is_empty() => {
+                let idx = self
+                    .focused
+                    .map_or(0, |idx| idx.min(self.ops.len()).saturating_sub(1));
+                self.focus_row(idx)
+            }
+            OpViewListMessage::FocusNextRow
+            | OpViewListMessage::FocusPreviousRow => Command::none(),
+            OpViewListMessage::FocusRow(idx) if idx < self.ops.len() => {
+                self.focus_row(idx)
+            }
+            OpViewListMessage::FocusRow(_) => Command::none(),
+            OpViewListMessage::SendRequest(_)
+            | OpViewListMessage::VerifyWrite(_)
+            | OpViewListMessage::ImportCsv(_)
+            | OpViewListMessage::InsertTemplate(_)
+            | OpViewListMessage::SaveTemplate(_) => {
                 unreachable!()
             }
         }
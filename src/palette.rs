@@ -0,0 +1,98 @@
+use iced::{
+    theme,
+    widget::{text_input, Button, Column, Container, Row, Text, TextInput},
+    Alignment, Command, Element, Length,
+};
+
+use crate::style::TextSize;
+
+/// Id of the search input, focused when the palette opens
+const INPUT_ID: &str = "palette";
+/// Matching entries listed at most
+const MAX_SHOWN: usize = 10;
+
+/// Searchable list of actions, opened with Ctrl+K
+#[derive(Debug, Clone, Default)]
+pub struct CommandPalette {
+    visible: bool,
+    query: String,
+}
+
+impl CommandPalette {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Show the palette with an empty query and focus its input
+    pub fn open<Message: 'static>(&mut self) -> Command<Message> {
+        self.visible = true;
+        self.query.clear();
+        text_input::focus(text_input::Id::new(INPUT_ID))
+    }
+
+    pub fn close(&mut self) {
+        self.visible = false;
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+    }
+
+    /// Entries whose label contains every word of the query, ignoring case
+    fn matches<Message>(
+        &self,
+        entries: Vec<(String, Message)>,
+    ) -> Vec<(String, Message)> {
+        let query = self.query.to_lowercase();
+
+        entries
+            .into_iter()
+            .filter(|(label, _)| {
+                let label = label.to_lowercase();
+                query.split_whitespace().all(|word| label.contains(word))
+            })
+            .take(MAX_SHOWN)
+            .collect()
+    }
+
+    /// The search input and the matching entries, enter runs the first match
+    pub fn view<'a, Message: Clone + 'a>(
+        &'a self,
+        entries: Vec<(String, Message)>,
+        on_query: impl Fn(String) -> Message + 'a,
+        on_close: Message,
+    ) -> Element<'a, Message> {
+        let matches = self.matches(entries);
+
+        let mut input = TextInput::new("Search actions", &self.query, on_query)
+            .id(text_input::Id::new(INPUT_ID))
+            .padding([0, 2]);
+        if let Some((_, msg)) = matches.first() {
+            input = input.on_submit(msg.clone());
+        }
+
+        let mut column = Column::new().width(Length::Units(480)).push(
+            Row::new()
+                .align_items(Alignment::Center)
+                .spacing(4)
+                .push(input)
+                .push(Button::new("Close").on_press(on_close)),
+        );
+
+        if matches.is_empty() {
+            column = column
+                .push(Text::new("No matching action").size(TextSize::SMALL));
+        }
+        for (label, msg) in matches {
+            column = column.push(
+                Button::new(Text::new(label).size(TextSize::SMALL))
+                    .width(Length::Fill)
+                    .padding(0)
+                    .style(theme::Button::Text)
+                    .on_press(msg),
+            );
+        }
+
+        Container::new(column).padding([0, 10]).into()
+    }
+}
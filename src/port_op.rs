@@ -1,23 +1,70 @@
 use std::fmt::{Display, Formatter};
-use std::io::Write;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
 
-use read_to_timeout::ReadToTimeout;
 use string_to_num::ParseNum;
 
+use crate::batch::*;
 use crate::error::{ErrKind, Error};
-use crate::message_sender::{Operation, Request};
-use crate::{OpView, OpViewList};
+use crate::gateway::GatewayView;
+use crate::message_sender::{Operation, Request, DEFAULT_LOOPBACK_PATTERN};
+use crate::modbus::{CrcAlgorithm, CrcByteOrder, CrcConfig};
+use crate::monitor::{monitor_bus, MonitorFrame};
+use crate::response::Response;
+use crate::scan::{detect_baud, scan_bus, ScanEvent};
 use crate::static_unreachable;
+use crate::transport::{
+    is_port_gone, wire_timeout, PortConfig, SerialTransport, Transport,
+    DEFAULT_TIMEOUT,
+};
+use crate::{OpView, OpViewList};
 
 
 pub const PARITIES: &[Parity] = &[Parity::None, Parity::Odd, Parity::Even];
 pub const STOP_BITS: &[StopBits] = &[StopBits::One, StopBits::Two];
+pub const DATA_BITS: &[DataBits] = &[DataBits::Eight, DataBits::Seven];
+pub const ERROR_POLICIES: &[ErrorPolicy] =
+    &[ErrorPolicy::Continue, ErrorPolicy::Stop];
 
+/// Common framings, written as data bits, parity and stop bits like field
+/// docs do
+pub const FRAMINGS: &[Framing] = &[
+    Framing::new(DataBits::Eight, Parity::None, StopBits::One),
+    Framing::new(DataBits::Eight, Parity::Even, StopBits::One),
+    Framing::new(DataBits::Eight, Parity::Odd, StopBits::One),
+    Framing::new(DataBits::Seven, Parity::Even, StopBits::One),
+    Framing::new(DataBits::Seven, Parity::Odd, StopBits::One),
+];
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+
+/// What a run of requests does when one of them fails, bursts of one-shot
+/// requests, bus scans and continuous polling stop or go on to collect every
+/// result
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorPolicy {
+    #[default]
+    Continue,
+    Stop,
+}
+
+impl Display for ErrorPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ErrorPolicy::Continue => "Continue on Error",
+                ErrorPolicy::Stop => "Stop on Error",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Parity {
     None,
     Odd,
@@ -40,7 +87,7 @@ impl Display for Parity {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StopBits {
     One,
     Two,
@@ -61,13 +108,104 @@ impl Display for StopBits {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DataBits {
+    Seven,
+    #[default]
+    Eight,
+}
+
+impl From<DataBits> for serialport::DataBits {
+    fn from(data_bits: DataBits) -> Self {
+        match data_bits {
+            DataBits::Seven => serialport::DataBits::Seven,
+            DataBits::Eight => serialport::DataBits::Eight,
+        }
+    }
+}
+
+impl Display for DataBits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataBits::Seven => write!(f, "7 Bits"),
+            DataBits::Eight => write!(f, "8 Bits"),
+        }
+    }
+}
+
+/// Data bits, parity and stop bits set together from a preset
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Framing {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Framing {
+    pub const fn new(
+        data_bits: DataBits,
+        parity: Parity,
+        stop_bits: StopBits,
+    ) -> Self {
+        Self { data_bits, parity, stop_bits }
+    }
+}
+
+impl Display for Framing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            match self.data_bits {
+                DataBits::Seven => 7,
+                DataBits::Eight => 8,
+            },
+            match self.parity {
+                Parity::None => 'N',
+                Parity::Odd => 'O',
+                Parity::Even => 'E',
+            },
+            match self.stop_bits {
+                StopBits::One => 1,
+                StopBits::Two => 2,
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PortOption {
     pub port_name: Option<String>,
     pub baud: String,
     pub stop_bits: Option<StopBits>,
     pub parity: Option<Parity>,
+    #[serde(default)]
+    pub data_bits: DataBits,
     pub device_addr: String,
+    /// Flag address bytes with the 9th bit through mark/space parity, for
+    /// proprietary multidrop buses, see `SerialTransport::write_nine_bit`
+    #[serde(default)]
+    pub nine_bit: bool,
+    /// End response frames after 3.5 character times of silence instead of
+    /// waiting out the read timeout
+    #[serde(default)]
+    pub t35_framing: bool,
+    /// Scale the read timeout with the baud and the length of each request
+    /// and its reply
+    #[serde(default)]
+    pub auto_timeout: bool,
+    /// Milliseconds to wait before reading a response, empty for none
+    #[serde(default)]
+    pub read_delay_ms: String,
+    /// Checksum for devices that don't use the Modbus CRC
+    #[serde(default)]
+    pub crc: CrcAlgorithm,
+    /// Order of the checksum bytes, for devices that swap them
+    #[serde(default)]
+    pub crc_byte_order: CrcByteOrder,
+    /// Debug only, decode responses that fail their CRC check. Never saved
+    #[serde(skip)]
+    pub skip_crc: bool,
 }
 
 impl Default for PortOption {
@@ -77,230 +215,354 @@ impl Default for PortOption {
             baud: "".to_string(),
             stop_bits: None,
             parity: None,
+            data_bits: DataBits::Eight,
             device_addr: "".to_string(),
+            nine_bit: false,
+            t35_framing: false,
+            auto_timeout: false,
+            read_delay_ms: "".to_string(),
+            crc: CrcAlgorithm::Modbus,
+            crc_byte_order: CrcByteOrder::LowFirst,
+            skip_crc: false,
         }
     }
 }
 
+/// An input of the port option bar
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortField {
+    PortName,
+    Parity,
+    StopBits,
+    Baud,
+    DeviceAddr,
+    ReadDelay,
+    CrcByteOrder,
+}
+
+impl Display for PortField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PortField::PortName => "Port",
+                PortField::Parity => "Parity",
+                PortField::StopBits => "Stop Bits",
+                PortField::Baud => "Baud",
+                PortField::DeviceAddr => "Address",
+                PortField::ReadDelay => "Read Delay",
+                PortField::CrcByteOrder => "CRC Byte Order",
+            }
+        )
+    }
+}
+
+pub const PORT_FIELDS: &[PortField] = &[
+    PortField::PortName,
+    PortField::Parity,
+    PortField::StopBits,
+    PortField::Baud,
+    PortField::DeviceAddr,
+    PortField::ReadDelay,
+    PortField::CrcByteOrder,
+];
+
+impl PortOption {
+    /// The preset matching the current settings, `None` if they were set
+    /// to something else by hand
+    pub fn framing(&self) -> Option<Framing> {
+        let framing =
+            Framing::new(self.data_bits, self.parity?, self.stop_bits?);
+        FRAMINGS.contains(&framing).then_some(framing)
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.data_bits = framing.data_bits;
+        self.parity = Some(framing.parity);
+        self.stop_bits = Some(framing.stop_bits);
+    }
+
+    /// Why `field` can't be used to open the port, `None` if it's valid
+    pub fn field_error(&self, field: PortField) -> Option<String> {
+        match field {
+            PortField::PortName if self.port_name.is_none() => {
+                Some("select a port".to_string())
+            }
+            PortField::Parity if self.parity.is_none() => {
+                Some("select a parity".to_string())
+            }
+            PortField::StopBits if self.stop_bits.is_none() => {
+                Some("select the stop bits".to_string())
+            }
+            PortField::Baud if self.baud.is_empty() => {
+                Some("enter a baud".to_string())
+            }
+            PortField::DeviceAddr if self.device_addr.is_empty() => {
+                Some("enter a device address".to_string())
+            }
+            PortField::Baud if self.baud.parse_num::<u32>().is_err() => {
+                Some(format!("\"{}\" is not a valid baud", self.baud))
+            }
+            PortField::DeviceAddr
+                if self.device_addr.parse_num::<u8>().is_err() =>
+            {
+                Some(format!(
+                    "\"{}\" is not a valid device address",
+                    self.device_addr
+                ))
+            }
+            PortField::ReadDelay
+                if !self.read_delay_ms.trim().is_empty()
+                    && self.read_delay_ms.parse_num::<u64>().is_err() =>
+            {
+                Some(format!(
+                    "\"{}\" is not a valid delay in ms",
+                    self.read_delay_ms
+                ))
+            }
+            PortField::CrcByteOrder
+                if self.crc == CrcAlgorithm::None
+                    && self.crc_byte_order != CrcByteOrder::LowFirst =>
+            {
+                Some("frames without a CRC have no byte order".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Every invalid field with the reason it's invalid
+    pub fn field_errors(&self) -> Vec<(PortField, String)> {
+        PORT_FIELDS
+            .iter()
+            .filter_map(|&field| Some((field, self.field_error(field)?)))
+            .collect()
+    }
+}
+
 impl TryFrom<PortOption> for PortConfig {
     type Error = Error;
 
     fn try_from(option: PortOption) -> Result<Self, Self::Error> {
-        if option.port_name.is_none()
-            || option.baud.is_empty()
-            || option.stop_bits.is_none()
-            || option.parity.is_none()
+        if let Some((field, message)) = option.field_errors().into_iter().next()
         {
             return Err(Error::with_message(
                 ErrKind::InvalidPortOption,
-                "Must select all port options".to_string(),
+                format!("{}: {}", field, message),
             ));
         }
 
-        let baud = match option.baud.parse_num::<u32>() {
-            Ok(baud) => baud,
-            Err(_) => {
-                return Err(Error::with_message(
-                    ErrKind::InvalidPortOption,
-                    format!("\"{}\" is not a valid baud", option.baud),
-                ));
-            }
-        };
-
-        let device_addr = match option.device_addr.parse_num::<u8>() {
-            Ok(addr) => addr,
-            Err(_) => {
-                return Err(Error::with_message(
-                    ErrKind::InvalidPortOption,
-                    format!(
-                        "\"{}\" is not a valid device address",
-                        option.device_addr
-                    ),
-                ))
-            }
-        };
-
         // These unwraps were already checked
         Ok(Self {
             port_name: option.port_name.unwrap(),
-            baud,
+            baud: option.baud.parse_num::<u32>().unwrap(),
             stop_bits: option.stop_bits.unwrap().into(),
             parity: option.parity.unwrap().into(),
-            device_addr,
+            data_bits: option.data_bits.into(),
+            device_addr: option.device_addr.parse_num::<u8>().unwrap(),
+            nine_bit: option.nine_bit,
+            t35_framing: option.t35_framing,
+            auto_timeout: option.auto_timeout,
+            read_delay: option
+                .read_delay_ms
+                .parse_num::<u64>()
+                .map_or(Duration::ZERO, Duration::from_millis),
+            skip_crc: option.skip_crc,
+            crc: CrcConfig {
+                algorithm: option.crc,
+                byte_order: option.crc_byte_order,
+            },
         })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct PortConfig {
-    pub port_name: String,
-    pub baud: u32,
-    pub stop_bits: serialport::StopBits,
-    pub parity: serialport::Parity,
-    pub device_addr: u8,
-}
+/// How often a pending one-shot request checks whether it was canceled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
 
-impl Default for PortConfig {
-    fn default() -> Self {
-        Self {
-            port_name: String::new(),
-            baud: 0,
-            stop_bits: serialport::StopBits::One,
-            parity: serialport::Parity::None,
-            device_addr: 0,
-        }
-    }
-}
+/// Send a single op and wait for its response. `cancel` is a generation
+/// counter, the wait is given up once it changes
+pub async fn one_shot_quarry(
+    op: OpView,
+    port_option: PortOption,
+    port_op_tx: Sender<OpMessage>,
+    cancel: Arc<AtomicUsize>,
+) -> Result<Response, Error> {
+    let generation = cancel.load(Ordering::SeqCst);
 
-impl PortConfig {
-    pub fn new(
-        port_name: String,
-        baud: u32,
-        stop_bits: StopBits,
-        parity: Parity,
-        device_addr: u8,
-    ) -> Self {
-        let parity = parity.into();
-        let stop_bits = stop_bits.into();
-        PortConfig { port_name, baud, stop_bits, parity, device_addr }
-    }
-}
+    let op: Operation = op.try_into()?;
+    let port_conf: PortConfig = port_option.try_into()?;
 
-#[derive(Clone, PartialEq, Debug)]
-pub struct Response {
-    pub op: Operation,
-    bytes: Vec<u8>,
+    send_op(op, port_conf, &port_op_tx, &cancel, generation)
 }
 
-impl Display for Response {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        const CRC_GEN: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_MODBUS);
-
-        fn make_msg(
-            f: &mut Formatter<'_>,
-            req: Request,
-            name: &str,
-            ret: &str,
-            bytes: &[u8],
-        ) -> std::fmt::Result {
-            let addr = match req {
-                Request::ReadSingle(addr) => addr,
-                Request::WriteSingle(addr, _, _) => addr,
-                Request::ReadSingleRO(addr) => addr,
-            };
-
-            write!(
-                f,
-                "{:?}: {}(0x{:02X}) -> {}: ",
-                req.variant_string(),
-                name,
-                addr,
-                ret,
-            )?;
-
-            let mut iter = bytes.iter();
-            write!(f, "{{ ")?;
-            if let Some(byte) = iter.next() {
-                write!(f, " {:02X}", byte)?;
-
-                for byte in iter {
-                    write!(f, " {:02X}", byte)?;
-                }
-            }
-            write!(f, " }}")?;
-
-            Ok(())
-        }
-
-        if self.bytes.len() < 5 {
-            return make_msg(
-                f,
-                self.op.req,
-                &self.op.name,
-                "!InvalidResponse",
-                &self.bytes,
-            );
-        }
+/// Send an op through the port thread and wait for its response, given up
+/// once `cancel` moves past `generation`
+fn send_op(
+    op: Operation,
+    port_conf: PortConfig,
+    port_op_tx: &Sender<OpMessage>,
+    cancel: &AtomicUsize,
+    generation: usize,
+) -> Result<Response, Error> {
+    let (response_tx, response_rx) = channel();
 
-        let msg_crc = (self.bytes[self.bytes.len() - 2] as u16)
-            | ((self.bytes[self.bytes.len() - 1] as u16) << 8);
-        if CRC_GEN.checksum(&self.bytes[0..(self.bytes.len() - 2)]) != msg_crc {
-            return make_msg(
-                f,
-                self.op.req,
-                &self.op.name,
-                "!CRCCheckFailed",
-                &self.bytes,
-            );
-        }
+    if port_op_tx.send(OpMessage::OneShot(port_conf, op, response_tx)).is_err() {
+        return Err(Error::new(ErrKind::PortOpThreadNotPresent));
+    }
 
-        let make_u16 = |msb, lsb| ((msb as u16) << 8) | lsb as u16;
-        let (_addr, value) = match self.op.req {
-            Request::ReadSingle(addr) | Request::ReadSingleRO(addr) => {
-                if self.bytes.len() != 7 {
-                    (addr, "!UnexpectedResponse".to_string())
-                } else {
-                    (
-                        addr,
-                        (*self.op.get_eval())(make_u16(
-                            self.bytes[3],
-                            self.bytes[4],
-                        ) as f64)
-                        .to_string(),
-                    )
+    loop {
+        match response_rx.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) => {
+                // the port thread finishes the request on its own, its
+                // response goes nowhere
+                if cancel.load(Ordering::SeqCst) != generation {
+                    return Err(Error::new(ErrKind::RequestCanceled));
                 }
             }
-            Request::WriteSingle(addr, original, _val) => {
-                if self.bytes.len() != 8 {
-                    (addr, "!UnexpectedResponse".to_string())
-                } else {
-                    (addr, original.to_string())
-                }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(Error::new(
+                    ErrKind::PortOpDroppedChannelTxWithoutResponse,
+                ))
             }
-        };
-
-        make_msg(f, self.op.req, &self.op.name, &value, &self.bytes)
+        }
     }
 }
 
-impl Response {
-    fn new(op: Operation, bytes: Vec<u8>) -> Self {
-        Self { op, bytes }
+/// Register value before a write, the write and the value read back after
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteVerification {
+    pub before: Response,
+    pub write: Response,
+    pub after: Response,
+    /// Raw value the write sent
+    pub written: u16,
+}
+
+impl WriteVerification {
+    /// Whether the register holds the written value after the write
+    pub fn passed(&self) -> bool {
+        self.after.value() == Some(self.written as f64)
     }
 }
 
-pub async fn one_shot_quarry(
+/// Read the register of a Write Single op, write it and read it back. Every
+/// response must be valid, the caller compares the values
+pub async fn verify_write(
     op: OpView,
     port_option: PortOption,
     port_op_tx: Sender<OpMessage>,
-) -> Result<Response, Error> {
+    cancel: Arc<AtomicUsize>,
+) -> Result<WriteVerification, Error> {
+    let generation = cancel.load(Ordering::SeqCst);
+
     let op: Operation = op.try_into()?;
     let port_conf: PortConfig = port_option.try_into()?;
+    let (addr, written) = match op.req {
+        Request::WriteSingle(addr, _, written) => (addr, written),
+        _ => {
+            return Err(Error::with_message(
+                ErrKind::RequestParseError,
+                format!("\"{}\" is not a Write Single op", op.name),
+            ))
+        }
+    };
+    // the raw register, without the write op's conversion
+    let read_op = Operation::new(op.name.clone(), Request::ReadSingle(addr));
 
-    let (response_tx, response_rx) = channel();
+    let send = |op: Operation| {
+        let response =
+            send_op(op, port_conf.clone(), &port_op_tx, &cancel, generation)?;
+        match response.frame_error() {
+            Some(e) => Err(e),
+            None => Ok(response),
+        }
+    };
 
-    if port_op_tx.send(OpMessage::OneShot(port_conf, op, response_tx)).is_err() {
-        return Err(Error::new(ErrKind::PortOpThreadNotPresent));
+    Ok(WriteVerification {
+        before: send(read_op.clone())?,
+        write: send(op)?,
+        after: send(read_op)?,
+        written,
+    })
+}
+
+/// Check the configured port can be opened, and with `echo` that the device
+/// answers a loopback test. Returns what passed, nothing is logged as a
+/// response
+pub async fn test_connection(
+    port_option: PortOption,
+    echo: bool,
+    port_op_tx: Sender<OpMessage>,
+    cancel: Arc<AtomicUsize>,
+) -> Result<String, Error> {
+    let generation = cancel.load(Ordering::SeqCst);
+    let port_conf: PortConfig = port_option.try_into()?;
+
+    if !echo {
+        let (open_tx, open_rx) = channel();
+        if port_op_tx
+            .send(OpMessage::TestOpen(port_conf.clone(), open_tx))
+            .is_err()
+        {
+            return Err(Error::new(ErrKind::PortOpThreadNotPresent));
+        }
+        return match open_rx.recv() {
+            Ok(result) => result.map(|()| format!("{} opened", port_conf)),
+            Err(_) => {
+                Err(Error::new(ErrKind::PortOpDroppedChannelTxWithoutResponse))
+            }
+        };
     }
 
-    if let Ok(result) = response_rx.recv() {
-        result
-    } else {
-        Err(Error::new(ErrKind::PortOpDroppedChannelTxWithoutResponse))
+    let op = Operation::new(
+        "Test Connection".to_string(),
+        Request::Loopback(DEFAULT_LOOPBACK_PATTERN),
+    );
+    let response =
+        send_op(op, port_conf.clone(), &port_op_tx, &cancel, generation)?;
+    if let Some(e) = response.frame_error() {
+        return Err(e);
+    }
+
+    match response.value() {
+        Some(1.0) => Ok(format!(
+            "{} opened, the device echoed the loopback test in {} ms",
+            port_conf,
+            response.latency().unwrap_or_default().as_millis()
+        )),
+        _ => Err(Error::with_message(
+            ErrKind::InvalidFrame,
+            format!(
+                "{} opened, but the device didn't echo the loopback test: {}",
+                port_conf,
+                response.display_value()
+            ),
+        )),
     }
 }
 
 pub async fn continuous_quarry_start(
     op_list: OpViewList,
     port_option: PortOption,
+    policy: ErrorPolicy,
+    batch_reads: bool,
+    gateway: GatewayView,
     port_op_tx: Sender<OpMessage>,
     sender: Sender<Result<Response, Error>>,
 ) -> Result<(), Error> {
-    let op_list = op_list.try_into()?;
+    let op_list: Vec<Operation> = op_list.try_into()?;
     let port_conf = port_option.try_into()?;
 
+    let op_list =
+        if batch_reads { coalesce_reads(op_list) } else { no_batching(op_list) };
+    let op_list = match gateway.device_addrs()? {
+        Some(device_addrs) => rotate_devices(op_list, &device_addrs),
+        None => op_list,
+    };
+
     if port_op_tx
-        .send(OpMessage::StartContinuous(port_conf, op_list, sender))
+        .send(OpMessage::StartContinuous(port_conf, policy, op_list, sender))
         .is_err()
     {
         Err(Error::new(ErrKind::PortOpThreadNotPresent))
@@ -336,66 +598,169 @@ pub async fn continuous_quarry_stop(tx: Sender<OpMessage>) {
     let _ = tx.send(OpMessage::StopContinuous);
 }
 
+/// Hold the continuous quarry without dropping its ops, or carry on polling
+pub async fn continuous_quarry_pause(tx: Sender<OpMessage>, paused: bool) {
+    let _ = tx.send(OpMessage::PauseContinuous(paused));
+}
+
+/// Stop whatever runs on port_op_thread, close the port and end the thread
+pub fn port_shutdown(tx: &Sender<OpMessage>) {
+    let _ = tx.send(OpMessage::Shutdown);
+}
+
+pub async fn port_disconnect(tx: Sender<OpMessage>) {
+    let _ = tx.send(OpMessage::Disconnect);
+}
+
+/// How a scan or monitor running on port_op_thread ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEnd {
+    /// Finished or stopped, the thread waits for the next message
+    Done,
+    /// `Shutdown` arrived, the thread should exit
+    Shutdown,
+}
+
 /// Message to control port operations on port_op_thread
 /// This message should be send through mpsc channel
 pub enum OpMessage {
     OneShot(PortConfig, Operation, Sender<Result<Response, Error>>),
-    StartContinuous(PortConfig, Vec<Operation>, Sender<Result<Response, Error>>),
+    /// Each inner list is polled with a single request, see `batch`. With
+    /// `ErrorPolicy::Stop` polling ends at the first failed request
+    StartContinuous(
+        PortConfig,
+        ErrorPolicy,
+        Vec<Vec<Operation>>,
+        Sender<Result<Response, Error>>,
+    ),
     StopContinuous,
+    /// Stop or carry on polling the continuous ops, one-shot requests are
+    /// still sent while paused
+    PauseContinuous(bool),
+    Scan(PortConfig, ErrorPolicy, Sender<Result<ScanEvent, Error>>),
+    /// Probes `BAUD_RATES`, the baud in the config is ignored
+    DetectBaud(PortConfig, ErrorPolicy, Sender<Result<ScanEvent, Error>>),
+    StopScan,
+    /// Pass on everything received without sending, see `monitor_bus`
+    Monitor(PortConfig, Sender<Result<MonitorFrame, Error>>),
+    /// Open the port and close it again, to check it can be used
+    TestOpen(PortConfig, Sender<Result<(), Error>>),
+    StopMonitor,
+    /// Release the port, stopping whatever continuous quarry or scan holds it
+    Disconnect,
+    /// Release the port and end port_op_thread
+    Shutdown,
 }
 
 pub fn port_op_thread(
     rx: Receiver<OpMessage>,
+) -> Result<(), Box<std::sync::mpsc::RecvError>> {
+    serve_port_ops(rx, |port_conf| {
+        Ok(Box::new(SerialTransport::open(port_conf)?))
+    })
+}
+
+/// The loop of `port_op_thread`, with ports opened by `open` so it can run
+/// over a `MemoryTransport`
+pub(crate) fn serve_port_ops(
+    rx: Receiver<OpMessage>,
+    mut open: impl FnMut(&PortConfig) -> Result<Box<dyn Transport>, Error>,
 ) -> Result<(), Box<std::sync::mpsc::RecvError>> {
     let mut op_queue = vec![];
 
     loop {
         op_queue.clear();
         // There should always be a sender present, if not panic
-        let (port_conf, response_tx, continuous) = match rx.recv()? {
+        let (port_conf, response_tx, continuous, policy) = match rx.recv()? {
             OpMessage::OneShot(port_conf, op, tx) => {
-                op_queue.push(op);
-                (port_conf, tx, false)
+                op_queue.push(vec![op]);
+                (port_conf, tx, false, ErrorPolicy::Continue)
             }
-            OpMessage::StartContinuous(port_conf, ops, tx) => {
+            OpMessage::StartContinuous(port_conf, policy, ops, tx) => {
                 if ops.is_empty() {
                     continue;
                 }
                 op_queue = ops;
-                (port_conf, tx, true)
+                (port_conf, tx, true, policy)
             }
-            OpMessage::StopContinuous => {
+            // the port is only held while an operation runs, so there is
+            // nothing to release between operations
+            OpMessage::StopContinuous
+            | OpMessage::PauseContinuous(_)
+            | OpMessage::StopScan
+            | OpMessage::StopMonitor
+            | OpMessage::Disconnect => {
+                continue;
+            }
+            OpMessage::Shutdown => return Ok(()),
+            OpMessage::Scan(port_conf, policy, tx) => {
+                let end = match open(&port_conf) {
+                    Ok(mut transport) => scan_bus(
+                        transport.as_mut(),
+                        &port_conf,
+                        policy,
+                        &rx,
+                        &tx,
+                    ),
+                    Err(e) => {
+                        // don't care if send fails
+                        let _ = tx.send(Err(e));
+                        RunEnd::Done
+                    }
+                };
+                if end == RunEnd::Shutdown {
+                    return Ok(());
+                }
+                continue;
+            }
+            OpMessage::DetectBaud(port_conf, policy, tx) => {
+                if detect_baud(&port_conf, policy, &rx, &tx, &mut open)
+                    == RunEnd::Shutdown
+                {
+                    return Ok(());
+                }
+                continue;
+            }
+            OpMessage::Monitor(port_conf, tx) => {
+                let end = match open(&port_conf) {
+                    Ok(mut transport) => {
+                        monitor_bus(transport.as_mut(), &rx, &tx)
+                    }
+                    Err(e) => {
+                        // don't care if send fails
+                        let _ = tx.send(Err(e));
+                        RunEnd::Done
+                    }
+                };
+                if end == RunEnd::Shutdown {
+                    return Ok(());
+                }
+                continue;
+            }
+            OpMessage::TestOpen(port_conf, tx) => {
+                // the port is closed again as the transport is dropped
+                // don't care if send fails
+                let _ = tx.send(open(&port_conf).map(|_| ()));
                 continue;
             }
         };
 
         // open port, if failed, send error back through response_tx
-        let mut port =
-            match serialport::new(port_conf.port_name.clone(), port_conf.baud)
-                .parity(port_conf.parity)
-                .stop_bits(port_conf.stop_bits)
-                .timeout(Duration::from_millis(50))
-                .open()
-            {
-                Ok(port) => port,
-                Err(_) => {
-                    // don't care if send fails because response_tx is dropped
-                    // after continue
-                    let _ = response_tx.send(Err(Error::with_message(
-                        ErrKind::FailedToOpenTargetPort,
-                        format!(
-                            "Failed to open port \"{}\"",
-                            port_conf.port_name
-                        ),
-                    )));
-                    continue;
-                }
-            };
+        let mut transport = match open(&port_conf) {
+            Ok(transport) => transport,
+            Err(e) => {
+                // don't care if send fails because response_tx is dropped
+                // after continue
+                let _ = response_tx.send(Err(e));
+                continue;
+            }
+        };
 
         let mut iter = op_queue.iter();
+        let mut paused = false;
         loop {
             let recv_result = rx.try_recv(); // must bind to longer life time
-            let (req, response_tx, extra_oneshot) = if let Ok(op_msg) =
+            let (group, response_tx, extra_oneshot) = if let Ok(op_msg) =
                 &recv_result
             {
                 match op_msg {
@@ -409,10 +774,10 @@ pub fn port_op_thread(
                             )));
                             continue;
                         } else {
-                            (op, resp_tx, true)
+                            (std::slice::from_ref(op), resp_tx, true)
                         }
                     }
-                    OpMessage::StartContinuous(_, _, resp_tx) => {
+                    OpMessage::StartContinuous(_, _, _, resp_tx) => {
                         // don't care if the send fails
                         let _ = resp_tx.send(Err(Error::with_message(
                             ErrKind::AttemptToStartMultipleContinuousQuarry,
@@ -421,43 +786,175 @@ pub fn port_op_thread(
                         )));
                         continue;
                     }
-                    OpMessage::StopContinuous => {
+                    OpMessage::StopContinuous | OpMessage::Disconnect => {
                         break;
                     }
+                    // the port is closed as the transport is dropped
+                    OpMessage::Shutdown => return Ok(()),
+                    OpMessage::PauseContinuous(pause) => {
+                        paused = *pause;
+                        continue;
+                    }
+                    OpMessage::Scan(_, _, scan_tx)
+                    | OpMessage::DetectBaud(_, _, scan_tx) => {
+                        // don't care if the send fails
+                        let _ = scan_tx.send(Err(Error::with_message(
+                            ErrKind::PortBusyScanning,
+                            "Cannot scan the bus while a continuous quarry is \
+                            running"
+                                .to_string(),
+                        )));
+                        continue;
+                    }
+                    OpMessage::Monitor(_, monitor_tx) => {
+                        // don't care if the send fails
+                        let _ = monitor_tx.send(Err(Error::with_message(
+                            ErrKind::PortBusyMonitoring,
+                            "Cannot monitor the bus while a continuous quarry \
+                            is running"
+                                .to_string(),
+                        )));
+                        continue;
+                    }
+                    OpMessage::TestOpen(new_port_conf, open_tx) => {
+                        // the port is already open for the quarry
+                        // don't care if the send fails
+                        let _ = open_tx.send(if *new_port_conf == port_conf {
+                            Ok(())
+                        } else {
+                            Err(Error::with_message(
+                                ErrKind::PortTypeUnequal,
+                                "The continuous quarry is using a different \
+                                port config"
+                                    .to_string(),
+                            ))
+                        });
+                        continue;
+                    }
+                    OpMessage::StopScan | OpMessage::StopMonitor => {
+                        continue;
+                    }
                 }
+            } else if continuous && paused {
+                std::thread::sleep(Duration::from_millis(40));
+                continue;
             } else {
                 match iter.next() {
-                    Some(req) => (req, &response_tx, false),
+                    Some(group) => (group.as_slice(), &response_tx, false),
                     None => {
-                        // None case only happens in continuous quarry
+                        // None case only happens in continuous quarry, read
+                        // once ops were read in the cycle that just ended
+                        op_queue.retain(|group| !group[0].read_once);
                         iter = op_queue.iter();
 
-                        // unwrap because there's no way for a new op_queue iter to be empty
-                        (iter.next().unwrap(), &response_tx, false)
+                        match iter.next() {
+                            Some(group) => {
+                                (group.as_slice(), &response_tx, false)
+                            }
+                            // every op was read once, only one-shots and the
+                            // stop request are left to wait for
+                            None => {
+                                std::thread::sleep(Duration::from_millis(40));
+                                continue;
+                            }
+                        }
                     }
                 }
             };
 
-            if let Err(e) = port.write_all(&req.to_modbus_bytes(&port_conf)) {
+            let frame = group_frame(group, &port_conf);
+            // a batched group waits as long as its slowest op, ops without a
+            // timeout of their own may have it derived from the baud
+            let timeout =
+                group.iter().filter_map(|op| op.timeout).max().or_else(|| {
+                    port_conf.auto_timeout.then(|| {
+                        wire_timeout(
+                            port_conf.baud,
+                            frame.len(),
+                            group_reply_len(group),
+                        )
+                    })
+                });
+            if let Some(timeout) = timeout {
+                // the default timeout is kept if this fails
+                let _ = transport.set_timeout(timeout);
+            }
+
+            let start = Instant::now();
+            if let Err(e) = transport.write_frame(&frame) {
+                let names =
+                    group.iter().map(|op| op.name.as_str()).collect::<Vec<_>>();
                 // don't care if send failed because response_tx is dropped after break
                 let _ = response_tx.send(Err(Error::with_message(
                     ErrKind::PortWriteFailed,
-                    format!("Failed to write msg to port due to: {}", e),
+                    format!(
+                        "Failed to write {} to port due to: {}",
+                        names.join(", "),
+                        e
+                    ),
                 )));
-                break;
+
+                // the other ops keep being polled unless the port is gone or
+                // the policy stops at the first error
+                if !continuous
+                    || is_port_gone(&e)
+                    || (policy == ErrorPolicy::Stop && !extra_oneshot)
+                {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(40));
+                continue;
             }
 
-            let mut response = Vec::new();
-            let _ = port.read_to_timeout(&mut response);
+            // slow devices only start replying a while after the request
+            let read_delay = group
+                .iter()
+                .filter_map(|op| op.read_delay)
+                .max()
+                .unwrap_or(port_conf.read_delay);
+            if !read_delay.is_zero() {
+                std::thread::sleep(read_delay);
+            }
 
-            if response_tx
-                .send(Ok(Response::new(req.clone(), response)))
-                .is_err()
+            // a failed read is reported as an empty, invalid response
+            let response = transport.read_frame().unwrap_or_default();
+            let latency = start.elapsed();
+            if timeout.is_some() {
+                let _ = transport.set_timeout(DEFAULT_TIMEOUT);
+            }
+
+            let responses = split_response(
+                group,
+                response,
+                port_conf.skip_crc,
+                port_conf.crc,
+            )
+            .into_iter()
+            .map(|response| {
+                let device_addr = response.op.target_addr(&port_conf);
+                response
+                    .with_latency(latency)
+                    .with_skip_crc(port_conf.skip_crc)
+                    .with_crc(port_conf.crc)
+                    .with_device_addr(device_addr)
+            })
+            .collect::<Vec<_>>();
+            // one-shots sent in between don't end the poll
+            let stop = policy == ErrorPolicy::Stop
+                && !extra_oneshot
+                && responses.iter().any(|resp| resp.frame_error().is_some());
+
+            // a canceled one-shot dropped its receiver, that only ends the
+            // loop if nothing else is being polled
+            if responses
+                .into_iter()
+                .any(|response| response_tx.send(Ok(response)).is_err())
+                && !(continuous && extra_oneshot)
             {
                 break;
             }
 
-            if !continuous && !extra_oneshot {
+            if stop || (!continuous && !extra_oneshot) {
                 break;
             }
             std::thread::sleep(Duration::from_millis(40));
@@ -466,3 +963,141 @@ pub fn port_op_thread(
 
     static_unreachable!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_sender::Request;
+    use crate::modbus::checksum;
+    use crate::transport::MemoryTransport;
+
+    /// Run `serve_port_ops` over `transport`, the thread ends once the
+    /// returned sender is dropped
+    fn spawn(transport: &MemoryTransport) -> Sender<OpMessage> {
+        let transport = transport.clone();
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            serve_port_ops(rx, |_| Ok(Box::new(transport.clone())))
+        });
+        tx
+    }
+
+    fn port_conf() -> PortConfig {
+        PortConfig { device_addr: 1, ..PortConfig::default() }
+    }
+
+    fn read(name: &str, addr: u16) -> Operation {
+        Operation::new(name.to_string(), Request::ReadSingle(addr))
+    }
+
+    /// Reply of device 1 to a single register read
+    fn read_reply(value: u16) -> Vec<u8> {
+        let mut reply = vec![1, 0x03, 2, (value >> 8) as u8, value as u8];
+        let crc = checksum(&reply);
+        reply.extend([crc as u8, (crc >> 8) as u8]);
+        reply
+    }
+
+    #[test]
+    fn one_shot_round_trip() {
+        let transport = MemoryTransport::new([read_reply(0x1234)]);
+        let port_tx = spawn(&transport);
+
+        let op = read("op", 0x10);
+        let (tx, rx) = channel();
+        port_tx.send(OpMessage::OneShot(port_conf(), op.clone(), tx)).unwrap();
+
+        let response = rx.recv().unwrap().unwrap();
+        assert_eq!(response.value(), Some(0x1234 as f64));
+        assert_eq!(transport.written(), [op.to_modbus_bytes(&port_conf())]);
+    }
+
+    #[test]
+    fn missing_reply_is_an_invalid_response() {
+        let transport = MemoryTransport::default();
+        let port_tx = spawn(&transport);
+
+        let (tx, rx) = channel();
+        port_tx
+            .send(OpMessage::OneShot(port_conf(), read("op", 0), tx))
+            .unwrap();
+
+        let response = rx.recv().unwrap().unwrap();
+        assert!(response.frame_error().is_some());
+        assert_eq!(response.value(), None);
+    }
+
+    #[test]
+    fn failed_open_is_reported() {
+        let (port_tx, port_rx) = channel();
+        std::thread::spawn(move || {
+            serve_port_ops(port_rx, |_| {
+                Err(Error::new(ErrKind::FailedToOpenTargetPort))
+            })
+        });
+
+        let (tx, rx) = channel();
+        port_tx
+            .send(OpMessage::OneShot(port_conf(), read("op", 0), tx))
+            .unwrap();
+
+        assert!(rx.recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn continuous_polls_ops_in_order() {
+        let transport = MemoryTransport::new(
+            [1, 2, 3].into_iter().map(read_reply).collect::<Vec<_>>(),
+        );
+        let port_tx = spawn(&transport);
+
+        let ops = vec![vec![read("a", 0x10)], vec![read("b", 0x20)]];
+        let (tx, rx) = channel();
+        port_tx
+            .send(OpMessage::StartContinuous(
+                port_conf(),
+                ErrorPolicy::Continue,
+                ops.clone(),
+                tx,
+            ))
+            .unwrap();
+
+        let values = (0..3)
+            .map(|_| rx.recv().unwrap().unwrap().value())
+            .collect::<Vec<_>>();
+        port_tx.send(OpMessage::StopContinuous).unwrap();
+
+        assert_eq!(values, [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(
+            transport.written()[..3],
+            [
+                ops[0][0].to_modbus_bytes(&port_conf()),
+                ops[1][0].to_modbus_bytes(&port_conf()),
+                ops[0][0].to_modbus_bytes(&port_conf()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_policy_ends_the_poll_at_the_first_error() {
+        let transport =
+            MemoryTransport::new([read_reply(1), vec![], read_reply(3)]);
+        let port_tx = spawn(&transport);
+
+        let ops = vec![vec![read("a", 0x10)]];
+        let (tx, rx) = channel();
+        port_tx
+            .send(OpMessage::StartContinuous(
+                port_conf(),
+                ErrorPolicy::Stop,
+                ops,
+                tx,
+            ))
+            .unwrap();
+
+        let responses = rx.iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].value(), Some(1.0));
+        assert!(responses[1].frame_error().is_some());
+    }
+}
@@ -0,0 +1,203 @@
+use string_to_num::ParseNum;
+
+use crate::error::{ErrKind, Error};
+use crate::ops::{OpType, OpView};
+
+/// Parse a vendor register map CSV with the columns
+/// `name, address, type, scale, unit`, an optional header row is skipped.
+///
+/// `type` is `holding`, `input`, `write` or `coil` (or an op type name) and
+/// defaults to `holding`, `scale` multiplies read values and divides written
+/// values, and `unit` is shown after read values. Returns the ops of every
+/// valid row and an error naming the line of every invalid row. Fields may be
+/// quoted to hold commas, a quote inside a quoted field is written twice
+pub fn parse_register_map(csv: &str) -> (Vec<OpView>, Vec<Error>) {
+    let mut ops = vec![];
+    let mut errors = vec![];
+
+    for (idx, line) in csv.lines().enumerate() {
+        let line_num = idx + 1;
+        let fields = match split_fields(line) {
+            Ok(fields) => fields,
+            Err(message) => {
+                errors.push(Error::with_message(
+                    ErrKind::RegisterMapParseError,
+                    format!("line {}: {}", line_num, message),
+                ));
+                continue;
+            }
+        };
+        let fields = fields.iter().map(String::as_str).collect::<Vec<_>>();
+
+        if fields.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+        if line_num == 1
+            && fields.get(1).map_or(false, |f| f.eq_ignore_ascii_case("address"))
+        {
+            continue;
+        }
+
+        match parse_row(&fields) {
+            Ok(mut op) => {
+                op.validate();
+                ops.push(op);
+            }
+            Err(message) => errors.push(Error::with_message(
+                ErrKind::RegisterMapParseError,
+                format!("line {}: {}", line_num, message),
+            )),
+        }
+    }
+
+    (ops, errors)
+}
+
+/// Split a CSV line into its trimmed fields, unquoting quoted fields
+fn split_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unclosed quote".to_string());
+    }
+    fields.push(field.trim().to_string());
+
+    Ok(fields)
+}
+
+fn parse_row(fields: &[&str]) -> Result<OpView, String> {
+    let field = |idx: usize| fields.get(idx).copied().unwrap_or("");
+
+    let name = field(0);
+    if name.is_empty() {
+        return Err("missing name".to_string());
+    }
+
+    let addr = field(1);
+    if addr.parse_num::<u16>().is_err() {
+        return Err(format!("\"{}\" is not a valid register address", addr));
+    }
+
+    let op_type = match field(2).to_ascii_lowercase().as_str() {
+        "" | "holding" | "readsingle" => OpType::ReadSingle,
+        "input" | "readsinglero" => OpType::ReadSingleRO,
+        "write" | "writesingle" => OpType::WriteSingle,
+        "coil" | "writecoil" => OpType::WriteCoil,
+        other => return Err(format!("\"{}\" is not a register type", other)),
+    };
+
+    let scale = match field(3) {
+        "" => 1f64,
+        scale => match scale.parse_num::<f64>() {
+            Ok(scale) if scale != 0f64 => scale,
+            _ => return Err(format!("\"{}\" is not a valid scale", scale)),
+        },
+    };
+    let (read_eval, write_eval) = if scale == 1f64 {
+        ("val".to_string(), "val".to_string())
+    } else {
+        (format!("val * {}", scale), format!("val / {}", scale))
+    };
+
+    let mut op = OpView::new(
+        name.to_string(),
+        op_type,
+        addr.to_string(),
+        "".to_string(),
+        read_eval,
+        write_eval,
+    );
+    op.unit = field(4).to_string();
+
+    Ok(op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_are_unquoted() {
+        assert_eq!(
+            split_fields(r#" "Power, total" ,1,"say ""hi""",, "#).unwrap(),
+            ["Power, total", "1", "say \"hi\"", "", ""]
+        );
+        assert!(split_fields(r#"name,"1"#).is_err());
+    }
+
+    #[test]
+    fn good_rows_become_ops() {
+        let csv = "name,address,type,scale,unit\n\
+                   Voltage,0x10,input,0.1,V\n\
+                   \"Setpoint, high\",20,write\n\
+                   Status,30\n";
+        let (ops, errors) = parse_register_map(csv);
+
+        assert!(errors.is_empty());
+        assert_eq!(ops.len(), 3);
+        assert_eq!(ops[0].name, "Voltage");
+        assert_eq!(ops[0].unit, "V");
+        assert_eq!(ops[0].op_type, OpType::ReadSingleRO);
+        assert_eq!(ops[0].op_addr, "0x10");
+        assert_eq!(ops[0].read_eval, "val * 0.1");
+        assert_eq!(ops[0].write_eval, "val / 0.1");
+        assert_eq!(ops[1].name, "Setpoint, high");
+        assert_eq!(ops[1].op_type, OpType::WriteSingle);
+        assert_eq!(ops[2].op_type, OpType::ReadSingle);
+        assert_eq!(ops[2].read_eval, "val");
+        assert_eq!(ops[2].unit, "");
+    }
+
+    #[test]
+    fn bad_rows_are_reported_with_their_line() {
+        let csv = "Voltage,10\n\
+                   ,11\n\
+                   \n\
+                   Current,twelve\n\
+                   Power,13,analog\n\
+                   Energy,14,holding,0\n\
+                   \"Flow,15\n\
+                   Level,16\n";
+        let (ops, errors) = parse_register_map(csv);
+
+        assert_eq!(ops.len(), 2);
+        let expected = [
+            "line 2: missing name",
+            "line 4: \"twelve\" is not a valid register address",
+            "line 5: \"analog\" is not a register type",
+            "line 6: \"0\" is not a valid scale",
+            "line 7: unclosed quote",
+        ]
+        .map(|message| {
+            Error::with_message(
+                ErrKind::RegisterMapParseError,
+                message.to_string(),
+            )
+        });
+        assert_eq!(errors, expected);
+    }
+}
@@ -0,0 +1,1167 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use crate::error::{ErrKind, Error};
+use crate::message_sender::{DataType, DateTimeLayout, Operation, Request};
+use crate::modbus::{CrcAlgorithm, CrcConfig};
+
+/// A response frame with the operation that requested it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Response {
+    pub op: Operation,
+    bytes: Vec<u8>,
+    /// Time from sending the request to receiving the whole response
+    latency: Option<Duration>,
+    /// The CRC check was disabled for debugging, values are decoded even if
+    /// the CRC is wrong
+    skip_crc: bool,
+    /// Checksum the device appends to its frames
+    crc: CrcConfig,
+    /// `val` of the previous sample of the operation and the time since,
+    /// bound to `prev` and `dt` in the read expression
+    prev: Option<(f64, Duration)>,
+    /// Address of the device the request was sent to, the response must come
+    /// from it. Not checked if unknown
+    device_addr: Option<u8>,
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn make_msg(
+            f: &mut Formatter<'_>,
+            req: Request,
+            name: &str,
+            ret: &str,
+            bytes: &[u8],
+        ) -> std::fmt::Result {
+            match req.addr() {
+                Some(addr) => write!(
+                    f,
+                    "{:?}: {}(0x{:02X}) -> {}: ",
+                    req.variant_string(),
+                    name,
+                    addr,
+                    ret,
+                )?,
+                None => write!(
+                    f,
+                    "{:?}: {} -> {}: ",
+                    req.variant_string(),
+                    name,
+                    ret
+                )?,
+            }
+
+            let mut iter = bytes.iter();
+            write!(f, "{{ ")?;
+            if let Some(byte) = iter.next() {
+                write!(f, " {:02X}", byte)?;
+
+                for byte in iter {
+                    write!(f, " {:02X}", byte)?;
+                }
+            }
+            write!(f, " }}")?;
+
+            Ok(())
+        }
+
+        make_msg(
+            f,
+            self.op.req,
+            &self.op.name,
+            &self.display_value(),
+            &self.bytes,
+        )
+    }
+}
+
+impl Response {
+    /// The decoded value or why there is none, as shown by `Display`
+    pub fn display_value(&self) -> String {
+        if let Some(e) = self.frame_error() {
+            return match e.kind() {
+                ErrKind::NoResponse => "!NoResponse".to_string(),
+                ErrKind::TruncatedResponse => {
+                    format!("!TruncatedResponse ({})", e.message())
+                }
+                ErrKind::DeviceAddressMismatch => {
+                    format!("!WrongDevice ({})", e.message())
+                }
+                // the CRC status is shown next to the response instead
+                _ => "-".to_string(),
+            };
+        }
+
+        let unexpected = || {
+            format!(
+                "!UnexpectedResponse (expected {} bytes, got {})",
+                self.expected_len(),
+                self.bytes.len()
+            )
+        };
+
+        let (_addr, value) = match self.op.req {
+            Request::ReadSingle(addr) | Request::ReadSingleRO(addr) => {
+                if self.bytes.len() != self.expected_len() {
+                    (addr, unexpected())
+                } else if self.op.data_type == DataType::Ascii {
+                    (addr, format!("\"{}\"", self.ascii()))
+                } else if self.op.data_type == DataType::DateTime {
+                    (addr, self.date_time())
+                } else {
+                    match self.eval_read() {
+                        None if self.awaiting_prev() => (
+                            addr,
+                            "- (waiting for a previous sample)".to_string(),
+                        ),
+                        // only BCD registers can fail to decode
+                        None => (addr, "!InvalidBCD".to_string()),
+                        Some(val) => match self.op.label(val) {
+                            Some(label) => {
+                                let val = self.op.format_display(val);
+                                (addr, format!("{} ({})", val, label))
+                            }
+                            None => (addr, self.op.format_display(val)),
+                        },
+                    }
+                }
+            }
+            Request::WriteSingle(addr, original, _val) => {
+                if self.bytes.len() != self.expected_len() {
+                    (addr, unexpected())
+                } else if self.op.clamped {
+                    (addr, format!("{} (clamped)", original))
+                } else {
+                    (addr, original.to_string())
+                }
+            }
+            Request::WriteMultiple(addr, quantity) => {
+                if self.bytes.len() != self.expected_len() {
+                    (addr, unexpected())
+                } else {
+                    (addr, format!("{} registers written", quantity))
+                }
+            }
+            Request::WriteCoil(addr, _) => {
+                if self.bytes.len() != self.expected_len() {
+                    (addr, unexpected())
+                } else if self.coil_echo() {
+                    (addr, "On".to_string())
+                } else {
+                    (addr, "Off".to_string())
+                }
+            }
+            Request::Loopback(pattern) => {
+                let latency = self.latency.map_or_else(String::new, |latency| {
+                    format!(", {} ms", latency.as_millis())
+                });
+
+                if self.loopback_passed() {
+                    (0, format!("Pass (0x{:04X}{})", pattern, latency))
+                } else {
+                    (0, format!("Fail, expected 0x{:04X}{}", pattern, latency))
+                }
+            }
+            Request::ReadExceptionStatus => {
+                if self.bytes.len() != self.expected_len() {
+                    (0, unexpected())
+                } else {
+                    let status = self.bytes[2];
+                    let value = format!("0x{:02X} (0b{:08b})", status, status);
+                    match self.op.label(status as f64) {
+                        Some(label) => (0, format!("{} ({})", value, label)),
+                        None => (0, value),
+                    }
+                }
+            }
+            Request::ReadFileRecord(file, record, _) => {
+                if self.bytes.len() != self.expected_len() {
+                    (0, unexpected())
+                } else {
+                    let words = self
+                        .file_record()
+                        .iter()
+                        .map(|word| format!("{:04X}", word))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    (0, format!("file {} record {}: {}", file, record, words))
+                }
+            }
+            Request::ReadDeviceId(_) => {
+                if self.bytes.len() != self.expected_len() {
+                    (0, unexpected())
+                } else {
+                    let mut objects = self
+                        .device_id_objects()
+                        .into_iter()
+                        .map(|(id, value)| {
+                            format!("{}: {}", device_object_name(id), value)
+                        })
+                        .collect::<Vec<_>>();
+                    // the rest would take another request from the next id
+                    if self.bytes[5] == 0xFF {
+                        objects.push("more objects follow".to_string());
+                    }
+                    (0, objects.join(", "))
+                }
+            }
+            // the layout is vendor defined, so the reply is shown as sent
+            Request::Custom(_, addr, _) => {
+                let len = self.bytes.len() - self.crc.trailer_len();
+                let hex = self.bytes[..len]
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (addr.unwrap_or_default(), hex)
+            }
+            Request::CommEventCounter => {
+                if self.bytes.len() != self.expected_len() {
+                    (0, unexpected())
+                } else {
+                    let (status, count) = self.comm_event_counter();
+                    (
+                        0,
+                        format!(
+                            "{} events, status 0x{:04X} ({})",
+                            count,
+                            status,
+                            if status == 0xFFFF { "busy" } else { "ready" }
+                        ),
+                    )
+                }
+            }
+        };
+
+        if self.skip_crc {
+            format!("{} [CRC not checked]", value)
+        } else {
+            value
+        }
+    }
+
+    /// Just the op name and value, without request type, address or raw
+    /// bytes
+    pub fn compact(&self) -> String {
+        format!("{}: {}", self.op.name, self.display_value())
+    }
+
+    pub fn new(op: Operation, bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            latency: None,
+            skip_crc: false,
+            crc: CrcConfig::default(),
+            prev: None,
+            device_addr: op.device_addr,
+            op,
+        }
+    }
+
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self { latency: Some(latency), ..self }
+    }
+
+    pub fn with_skip_crc(self, skip_crc: bool) -> Self {
+        Self { skip_crc, ..self }
+    }
+
+    pub fn with_crc(self, crc: CrcConfig) -> Self {
+        Self { crc, ..self }
+    }
+
+    pub fn with_prev(self, prev: Option<(f64, Duration)>) -> Self {
+        Self { prev, ..self }
+    }
+
+    pub fn with_device_addr(self, device_addr: u8) -> Self {
+        Self { device_addr: Some(device_addr), ..self }
+    }
+
+    /// The read expression uses `prev` but there was no previous sample
+    pub fn awaiting_prev(&self) -> bool {
+        self.op.uses_prev && self.prev.is_none()
+    }
+
+    /// The response frame as received
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Time from sending the request to receiving the whole response
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Bytes the request and its response took on the bus
+    pub fn wire_len(&self) -> usize {
+        let dev = self.bytes.first().copied().unwrap_or_default();
+        self.crc.reencode(self.op.request_bytes(dev)).len() + self.bytes.len()
+    }
+
+    /// Coil state echoed by a Write Single Coil response, the length must
+    /// have been checked
+    fn coil_echo(&self) -> bool {
+        self.bytes[4] == 0xFF
+    }
+
+    /// Whether a loopback response echoed the request exactly
+    fn loopback_passed(&self) -> bool {
+        self.bytes == self.crc.reencode(self.op.req.to_bytes(self.bytes[0]))
+    }
+
+    /// Expected length of a successful read response
+    fn read_len(&self) -> usize {
+        5 + 2 * self.op.read_quantity() as usize
+    }
+
+    /// Length of a successful response to the op's request
+    pub fn expected_len(&self) -> usize {
+        // every length below includes a 2 byte CRC
+        let len = match self.op.req {
+            Request::ReadSingle(_) | Request::ReadSingleRO(_) => self.read_len(),
+            Request::ReadExceptionStatus => 5,
+            Request::WriteSingle(_, _, _)
+            | Request::CommEventCounter
+            | Request::Loopback(_)
+            | Request::WriteCoil(_, _)
+            | Request::WriteMultiple(_, _) => 8,
+            Request::ReadFileRecord(_, _, record_len) => {
+                7 + 2 * record_len as usize
+            }
+            Request::ReadDeviceId(_) => self.device_id_len(),
+            // nothing tells how long the reply should be, so whatever
+            // arrived is taken as complete
+            Request::Custom(_, _, _) => return self.bytes.len(),
+        };
+
+        len - 2 + self.crc.trailer_len()
+    }
+
+    /// Length of a Read Device Identification response as its object
+    /// headers declare it, with a 2 byte CRC. Headers that weren't received
+    /// count as empty objects
+    fn device_id_len(&self) -> usize {
+        // address, function, MEI type, read code, conformity level, more
+        // follows, next object id and the object count come first
+        let mut len = 8;
+        let objects = self.bytes.get(7).copied().unwrap_or(0);
+        for _ in 0..objects {
+            len += 2 + self.bytes.get(len + 1).copied().unwrap_or(0) as usize;
+        }
+
+        len + 2
+    }
+
+    /// Whether the CRC at the end of the frame matches its content, `None` if
+    /// the frame is too short to carry a CRC or the device sends none
+    pub fn crc_ok(&self) -> Option<bool> {
+        self.crc.check(&self.bytes)
+    }
+
+    /// Why the frame can't be decoded at all, `None` if it is long enough to
+    /// hold a CRC, the CRC matches or isn't checked and it came from the device
+    /// that was asked. A frame that fails its CRC and is shorter than expected
+    /// was most likely cut off rather than corrupted
+    pub fn frame_error(&self) -> Option<Error> {
+        let len = self.bytes.len();
+        let truncated = || {
+            Error::with_message(
+                ErrKind::TruncatedResponse,
+                format!("expected {} bytes, got {}", self.expected_len(), len),
+            )
+        };
+
+        if len == 0 {
+            return Some(Error::with_message(
+                ErrKind::NoResponse,
+                "No reply before the read timed out".to_string(),
+            ));
+        }
+        if len < 3 + self.crc.trailer_len() {
+            return Some(truncated());
+        }
+
+        if !(self.skip_crc
+            || self.crc.algorithm == CrcAlgorithm::None
+            || self.crc_ok() == Some(true))
+        {
+            if len < self.expected_len() {
+                return Some(truncated());
+            }
+            return Some(Error::with_message(
+                ErrKind::CrcCheckFailed,
+                "Response failed CRC check".to_string(),
+            ));
+        }
+
+        match self.device_addr {
+            Some(dev) if self.bytes[0] != dev => Some(Error::with_message(
+                ErrKind::DeviceAddressMismatch,
+                format!(
+                    "expected device 0x{:02X}, got 0x{:02X}",
+                    dev, self.bytes[0]
+                ),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Register `idx` of a read response, honoring the op's byte swap
+    /// setting, the length must have been checked
+    fn register(&self, idx: usize) -> u16 {
+        let (msb, lsb) = (self.bytes[3 + idx * 2], self.bytes[4 + idx * 2]);
+        if self.op.byte_swap {
+            ((lsb as u16) << 8) | msb as u16
+        } else {
+            ((msb as u16) << 8) | lsb as u16
+        }
+    }
+
+    /// Register `idx` decoded according to the op's data type, `None` if the
+    /// register isn't valid for the type
+    fn decode_register(&self, idx: usize) -> Option<u16> {
+        let reg = self.register(idx);
+        match self.op.data_type {
+            DataType::Bcd => bcd_to_u16(reg),
+            DataType::U16
+            | DataType::Ascii
+            | DataType::DateTime
+            | DataType::U64
+            | DataType::I64
+            | DataType::F64 => Some(reg),
+        }
+    }
+
+    /// The four registers of a 64-bit read combined into one word, the length
+    /// must have been checked
+    fn register_u64(&self) -> u64 {
+        assemble_u64(
+            [0, 1, 2, 3].map(|idx| self.register(idx)),
+            self.op.word_swap,
+        )
+    }
+
+    /// Evaluate a read response, the length must have been checked.
+    /// 64-bit values are evaluated as `f64`, so integers above 2^53 lose
+    /// precision, and they ignore the bit field
+    fn eval_read(&self) -> Option<f64> {
+        let (val, next) = self.read_vars()?;
+        let prev = match self.prev {
+            Some((prev, dt)) => (prev, dt.as_secs_f64()),
+            None if self.op.uses_prev => return None,
+            None => (0f64, 0f64),
+        };
+
+        let val = self.op.eval(self.bytes[0], val, next, prev);
+        Some(self.op.scale_value(val))
+    }
+
+    /// `val` and `next` of a read response, the length must have been
+    /// checked
+    fn read_vars(&self) -> Option<(f64, f64)> {
+        let val = match self.op.data_type {
+            DataType::U64 => self.register_u64() as f64,
+            DataType::I64 => self.register_u64() as i64 as f64,
+            DataType::F64 => f64::from_bits(self.register_u64()),
+            // shown as a timestamp, there is no single value to evaluate
+            DataType::DateTime => return None,
+            DataType::U16 | DataType::Ascii | DataType::Bcd => {
+                let next =
+                    if self.op.companion { self.decode_register(1)? } else { 0 };
+                // a signed field is sign extended after it was extracted
+                let val = self.op.extract_bits(self.decode_register(0)?);
+
+                return Some((self.op.sign_extend(val), next as f64));
+            }
+        };
+
+        Some((val, 0f64))
+    }
+
+    /// The register value a read expression gets as `val`, before it is
+    /// evaluated, for the `prev` of the next sample
+    pub fn sample(&self) -> Option<f64> {
+        match self.op.req {
+            Request::ReadSingle(_) | Request::ReadSingleRO(_)
+                if self.frame_error().is_none()
+                    && self.bytes.len() == self.expected_len()
+                    && !matches!(
+                        self.op.data_type,
+                        DataType::Ascii | DataType::DateTime
+                    ) =>
+            {
+                self.read_vars().map(|(val, _)| val)
+            }
+            _ => None,
+        }
+    }
+
+    /// Decode the raw frame field by field, one line per field
+    pub fn breakdown(&self) -> Vec<String> {
+        let bytes = &self.bytes;
+        if bytes.len() < 2 + self.crc.trailer_len() {
+            return vec![format!(
+                "Frame too short to decode: {} bytes",
+                bytes.len()
+            )];
+        }
+
+        let hex = |data: &[u8]| {
+            data.iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let (body, crc) = bytes.split_at(bytes.len() - self.crc.trailer_len());
+        let function = body[1];
+        let mut lines = vec![
+            format!("Device address: 0x{:02X}", body[0]),
+            format!("Function code: 0x{:02X}", function),
+        ];
+
+        let payload = &body[2..];
+        if function & 0x80 != 0 {
+            lines.push(format!("Exception code: {}", hex(payload)));
+        } else if self.op.req.is_read() && !payload.is_empty() {
+            lines.push(format!("Byte count: {}", payload[0]));
+            lines.push(format!("Data: {}", hex(&payload[1..])));
+        } else if self.op.req == Request::ReadExceptionStatus
+            && payload.len() == 1
+        {
+            lines.push(format!("Exception status: {}", hex(payload)));
+        } else if matches!(self.op.req, Request::Loopback(_))
+            && payload.len() == 4
+        {
+            lines.push(format!("Sub-function: {}", hex(&payload[..2])));
+            lines.push(format!("Echoed data: {}", hex(&payload[2..])));
+        } else if matches!(self.op.req, Request::ReadFileRecord(_, _, _))
+            && payload.len() >= 3
+        {
+            lines.push(format!("Response length: {}", payload[0]));
+            lines.push(format!("File response length: {}", payload[1]));
+            lines.push(format!("Reference type: {}", payload[2]));
+            lines.push(format!("Record data: {}", hex(&payload[3..])));
+        } else if matches!(self.op.req, Request::ReadDeviceId(_))
+            && payload.len() >= 6
+        {
+            lines.push(format!("MEI type: {}", hex(&payload[..1])));
+            lines.push(format!("Read device ID code: {}", payload[1]));
+            lines.push(format!("Conformity level: {}", hex(&payload[2..3])));
+            lines.push(format!("More follows: {}", hex(&payload[3..4])));
+            lines.push(format!("Next object ID: {}", hex(&payload[4..5])));
+            lines.push(format!("Number of objects: {}", payload[5]));
+            for (id, value) in device_id_objects(&payload[6..]) {
+                lines.push(format!(
+                    "Object 0x{:02X} ({}): {}",
+                    id,
+                    device_object_name(id),
+                    String::from_utf8_lossy(value)
+                ));
+            }
+        } else if self.op.req == Request::CommEventCounter && payload.len() == 4
+        {
+            lines.push(format!("Status word: {}", hex(&payload[..2])));
+            lines.push(format!("Event count: {}", hex(&payload[2..])));
+        } else if matches!(self.op.req, Request::WriteMultiple(_, _))
+            && payload.len() == 4
+        {
+            lines.push(format!("Starting address: {}", hex(&payload[..2])));
+            lines.push(format!("Quantity: {}", hex(&payload[2..])));
+        } else if payload.len() == 4 {
+            lines.push(format!("Register address: {}", hex(&payload[..2])));
+            lines.push(format!("Value: {}", hex(&payload[2..])));
+        } else {
+            lines.push(format!("Payload: {}", hex(payload)));
+        }
+
+        let expected = match self.crc.checksum(body) {
+            Some(expected) => expected,
+            None => return lines,
+        };
+        let msg_crc = self.crc.frame_crc(bytes);
+        lines.push(
+            if msg_crc == expected {
+                format!("CRC: {} (matched)", hex(crc))
+            } else {
+                format!(
+                    "CRC: {} (expected {:02X} {:02X})",
+                    hex(crc),
+                    expected as u8,
+                    (expected >> 8) as u8
+                )
+            },
+        );
+
+        lines
+    }
+
+    /// Registers of a Read File Record response, the length must have been
+    /// checked
+    fn file_record(&self) -> Vec<u16> {
+        self.bytes[5..self.bytes.len() - self.crc.trailer_len()]
+            .chunks(2)
+            .map(|word| ((word[0] as u16) << 8) | word[1] as u16)
+            .collect()
+    }
+
+    /// Object ids and values of a Read Device Identification response, the
+    /// length must have been checked
+    fn device_id_objects(&self) -> Vec<(u8, String)> {
+        let objects = &self.bytes[8..self.bytes.len() - self.crc.trailer_len()];
+        device_id_objects(objects)
+            .into_iter()
+            .map(|(id, value)| (id, String::from_utf8_lossy(value).into_owned()))
+            .collect()
+    }
+
+    /// Status word and event count of a Get Comm Event Counter response, the
+    /// length must have been checked
+    fn comm_event_counter(&self) -> (u16, u16) {
+        let word = |idx: usize| {
+            ((self.bytes[idx] as u16) << 8) | self.bytes[idx + 1] as u16
+        };
+
+        (word(2), word(4))
+    }
+
+    /// Interpret a read response as a string, padding NULs are dropped and
+    /// other non-printable characters are shown as `.`
+    fn ascii(&self) -> String {
+        let bytes = (0..self.op.read_quantity() as usize)
+            .flat_map(|idx| self.register(idx).to_be_bytes())
+            .collect::<Vec<_>>();
+
+        String::from_utf8_lossy(&bytes)
+            .trim_end_matches('\0')
+            .chars()
+            .map(|c| {
+                if c.is_control() || c == char::REPLACEMENT_CHARACTER {
+                    '.'
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    /// Interpret a read response as a timestamp in the op's layout, followed
+    /// by the raw registers so a wrong layout can be spotted
+    fn date_time(&self) -> String {
+        let regs = (0..self.op.read_quantity() as usize)
+            .map(|idx| self.register(idx))
+            .collect::<Vec<_>>();
+        let raw = regs
+            .iter()
+            .map(|reg| format!("{:04X}", reg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        match date_time_fields(self.op.date_time_layout, &regs) {
+            Some([year, month, day, hour, minute, second]) => format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} [{}]",
+                year, month, day, hour, minute, second, raw
+            ),
+            None => format!("!InvalidDateTime [{}]", raw),
+        }
+    }
+
+    /// The evaluated value carried by this response, `None` if the response
+    /// is malformed or failed its CRC check
+    pub fn value(&self) -> Option<f64> {
+        if self.frame_error().is_some() {
+            return None;
+        }
+
+        match self.op.req {
+            Request::ReadSingle(_) | Request::ReadSingleRO(_) => {
+                if self.bytes.len() != self.expected_len()
+                    || matches!(
+                        self.op.data_type,
+                        DataType::Ascii | DataType::DateTime
+                    )
+                {
+                    None
+                } else {
+                    self.eval_read()
+                }
+            }
+            Request::WriteSingle(_, original, _) => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(original)
+                }
+            }
+            // the number of registers written
+            Request::WriteMultiple(_, quantity) => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(quantity as f64)
+                }
+            }
+            // 1 if the device echoed the coil on, 0 if off
+            Request::WriteCoil(_, _) => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(if self.coil_echo() { 1f64 } else { 0f64 })
+                }
+            }
+            // 1 if the device echoed the pattern, 0 if not
+            Request::Loopback(_) => {
+                Some(if self.loopback_passed() { 1f64 } else { 0f64 })
+            }
+            Request::ReadExceptionStatus => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(self.bytes[2] as f64)
+                }
+            }
+            Request::CommEventCounter => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(self.comm_event_counter().1 as f64)
+                }
+            }
+            // the first register of the record, so rules can watch it
+            Request::ReadFileRecord(_, _, _) => {
+                if self.bytes.len() != self.expected_len() {
+                    None
+                } else {
+                    Some(self.file_record()[0] as f64)
+                }
+            }
+            // identification objects are strings
+            Request::ReadDeviceId(_) => None,
+            // the reply is only shown as raw bytes
+            Request::Custom(_, _, _) => None,
+        }
+    }
+}
+
+/// Split a device identification object list into object ids and values,
+/// stops at the first object that was cut off
+fn device_id_objects(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut objects = vec![];
+    while let [id, len, rest @ ..] = data {
+        let len = *len as usize;
+        if rest.len() < len {
+            break;
+        }
+        objects.push((*id, &rest[..len]));
+        data = &rest[len..];
+    }
+
+    objects
+}
+
+/// Name of a device identification object as the specification lists it
+fn device_object_name(id: u8) -> String {
+    match id {
+        0x00 => "VendorName".to_string(),
+        0x01 => "ProductCode".to_string(),
+        0x02 => "MajorMinorRevision".to_string(),
+        0x03 => "VendorUrl".to_string(),
+        0x04 => "ProductName".to_string(),
+        0x05 => "ModelName".to_string(),
+        0x06 => "UserApplicationName".to_string(),
+        id => format!("Object 0x{:02X}", id),
+    }
+}
+
+/// Year, month, day, hour, minute and second packed in `regs` by `layout`,
+/// two digit years are in this century. `None` if a BCD digit is invalid or a
+/// field is out of range
+fn date_time_fields(layout: DateTimeLayout, regs: &[u16]) -> Option<[u16; 6]> {
+    let bytes =
+        regs.iter().flat_map(|reg| reg.to_be_bytes()).collect::<Vec<_>>();
+    let bcd = |byte: u8| bcd_to_u16(byte as u16);
+
+    let fields = match layout {
+        DateTimeLayout::BcdYearFirst => {
+            let mut fields = [0; 6];
+            for (field, byte) in fields.iter_mut().zip(&bytes) {
+                *field = bcd(*byte)?;
+            }
+            fields[0] += 2000;
+            fields
+        }
+        DateTimeLayout::BcdSecondFirst => {
+            let mut fields = [0; 6];
+            for (field, byte) in fields.iter_mut().zip(bytes.iter().rev()) {
+                *field = bcd(*byte)?;
+            }
+            fields[0] += 2000;
+            fields
+        }
+        DateTimeLayout::Bytes => {
+            let mut fields = [0; 6];
+            for (field, byte) in fields.iter_mut().zip(&bytes) {
+                *field = *byte as u16;
+            }
+            fields[0] += 2000;
+            fields
+        }
+        DateTimeLayout::Words => regs.try_into().ok()?,
+    };
+
+    let [_, month, day, hour, minute, second] = fields;
+    if (1..=12).contains(&month)
+        && (1..=31).contains(&day)
+        && hour < 24
+        && minute < 60
+        && second < 60
+    {
+        Some(fields)
+    } else {
+        None
+    }
+}
+
+/// Combine four registers in the order they were received into a 64-bit word,
+/// the first register is the most significant unless `word_swap` is set
+fn assemble_u64(regs: [u16; 4], word_swap: bool) -> u64 {
+    let fold = |acc: u64, reg: &u16| (acc << 16) | *reg as u64;
+    if word_swap {
+        regs.iter().rev().fold(0, fold)
+    } else {
+        regs.iter().fold(0, fold)
+    }
+}
+
+/// Decode a packed BCD register, e.g. `0x1234` to `1234`, `None` if any
+/// nibble is not a decimal digit
+fn bcd_to_u16(reg: u16) -> Option<u16> {
+    (0..4).rev().try_fold(0u16, |acc, nibble| {
+        let digit = (reg >> (nibble * 4)) & 0xF;
+        if digit <= 9 {
+            Some(acc * 10 + digit)
+        } else {
+            None
+        }
+    })
+}
+
+/// Decode the response `bytes` received for `op`, fails if nothing arrived,
+/// the frame is truncated or it doesn't pass its CRC check
+pub fn decode_frame(op: Operation, bytes: Vec<u8>) -> Result<Response, Error> {
+    let response = Response::new(op, bytes);
+    match response.frame_error() {
+        Some(e) => Err(e),
+        None => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::message_sender::{
+        encode_registers, parse_eval, parse_u64_block, DeviceIdLevel, READ_VARS,
+    };
+    use crate::modbus::checksum;
+
+    fn read_op(
+        data_type: DataType,
+        byte_swap: bool,
+        word_swap: bool,
+    ) -> Operation {
+        let mut op =
+            Operation::new("energy".to_string(), Request::ReadSingle(0));
+        op.data_type = data_type;
+        op.byte_swap = byte_swap;
+        op.word_swap = word_swap;
+
+        op
+    }
+
+    fn read_response(op: Operation, data: &[u8]) -> Response {
+        let mut bytes = vec![0x01, 0x03, data.len() as u8];
+        bytes.extend_from_slice(data);
+        let crc = checksum(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        Response::new(op, bytes)
+    }
+
+    #[test]
+    fn assemble_most_significant_word_first() {
+        assert_eq!(
+            assemble_u64([0x0123, 0x4567, 0x89AB, 0xCDEF], false),
+            0x0123_4567_89AB_CDEF
+        );
+    }
+
+    #[test]
+    fn assemble_least_significant_word_first() {
+        assert_eq!(
+            assemble_u64([0x0123, 0x4567, 0x89AB, 0xCDEF], true),
+            0xCDEF_89AB_4567_0123
+        );
+    }
+
+    #[test]
+    fn rate_from_previous_sample() {
+        let mut op = read_op(DataType::U16, false, false);
+        op.read_eval =
+            Arc::new(parse_eval("(val - prev) / dt", READ_VARS).unwrap());
+        op.uses_prev = true;
+
+        let first = read_response(op.clone(), &[0x00, 0x64]);
+        assert!(first.awaiting_prev());
+        assert_eq!(first.value(), None);
+        assert_eq!(first.sample(), Some(100f64));
+
+        let second = read_response(op, &[0x00, 0x78])
+            .with_prev(Some((100f64, Duration::from_secs(2))));
+        assert_eq!(second.value(), Some(10f64));
+    }
+
+    #[test]
+    fn decode_u64_word_orders() {
+        let data = [0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03];
+
+        let response =
+            read_response(read_op(DataType::U64, false, false), &data);
+        assert_eq!(response.value(), Some(0x0000_0001_0002_0003u64 as f64));
+
+        let response = read_response(read_op(DataType::U64, false, true), &data);
+        assert_eq!(response.value(), Some(0x0003_0002_0001_0000u64 as f64));
+    }
+
+    #[test]
+    fn decode_u64_byte_swap_applies_per_register() {
+        let data = [0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00];
+
+        let response = read_response(read_op(DataType::U64, true, false), &data);
+        assert_eq!(response.value(), Some(0x0001_0002_0003_0004u64 as f64));
+
+        let response = read_response(read_op(DataType::U64, true, true), &data);
+        assert_eq!(response.value(), Some(0x0004_0003_0002_0001u64 as f64));
+    }
+
+    #[test]
+    fn decode_i64_and_f64() {
+        let data = (-2i64).to_be_bytes();
+        let response =
+            read_response(read_op(DataType::I64, false, false), &data);
+        assert_eq!(response.value(), Some(-2f64));
+
+        let data = 1234.5f64.to_be_bytes();
+        let response =
+            read_response(read_op(DataType::F64, false, false), &data);
+        assert_eq!(response.value(), Some(1234.5));
+    }
+
+    /// Decode what a Write Multiple of the op's type and swaps would send
+    fn round_trip(op: Operation, value: u64) -> Option<f64> {
+        let regs =
+            encode_registers(&[value], op.data_type, op.byte_swap, op.word_swap);
+        let data =
+            regs.iter().flat_map(|reg| reg.to_be_bytes()).collect::<Vec<_>>();
+
+        read_response(op, &data).value()
+    }
+
+    #[test]
+    fn write_multiple_round_trips() {
+        for (byte_swap, word_swap) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let op = |data_type| read_op(data_type, byte_swap, word_swap);
+
+            assert_eq!(round_trip(op(DataType::U16), 0x1234), Some(4660f64));
+            assert_eq!(
+                round_trip(op(DataType::U64), 0x0001_0002_0003_0004),
+                Some(0x0001_0002_0003_0004u64 as f64)
+            );
+            assert_eq!(
+                round_trip(op(DataType::I64), -1234i64 as u64),
+                Some(-1234f64)
+            );
+            assert_eq!(
+                round_trip(op(DataType::F64), (-21.75f64).to_bits()),
+                Some(-21.75)
+            );
+        }
+    }
+
+    #[test]
+    fn parse_u64_block_as_type() {
+        assert_eq!(
+            parse_u64_block("-2, 16", DataType::I64),
+            Ok(vec![-2i64 as u64, 16])
+        );
+        assert_eq!(
+            parse_u64_block("1.5", DataType::F64),
+            Ok(vec![1.5f64.to_bits()])
+        );
+        assert!(parse_u64_block("-2", DataType::U64).is_err());
+        assert!(parse_u64_block(&"1 ".repeat(31), DataType::U64).is_err());
+    }
+
+    #[test]
+    fn decode_64_bit_rejects_short_response() {
+        let data = [0x00, 0x01, 0x00, 0x02];
+        let response =
+            read_response(read_op(DataType::U64, false, false), &data);
+        assert_eq!(response.value(), None);
+    }
+
+    #[test]
+    fn decode_frame_checks_crc() {
+        let op = read_op(DataType::U16, false, false);
+        let response = read_response(op.clone(), &[0x12, 0x34]);
+        assert_eq!(
+            decode_frame(op.clone(), response.bytes.clone())
+                .ok()
+                .and_then(|resp| resp.value()),
+            Some(0x1234 as f64)
+        );
+
+        let mut bytes = response.bytes;
+        bytes[3] ^= 0xFF;
+        assert!(decode_frame(op, bytes).is_err());
+    }
+
+    #[test]
+    fn reply_from_another_device_is_rejected() {
+        let op = read_op(DataType::U16, false, false);
+        let response = read_response(op.clone(), &[0x12, 0x34]);
+        assert_eq!(response.clone().with_device_addr(1).value(), Some(4660.0));
+
+        let response = response.with_device_addr(2);
+        assert_eq!(
+            response.frame_error().map(|e| e.kind()),
+            Some(ErrKind::DeviceAddressMismatch)
+        );
+        assert_eq!(
+            response.display_value(),
+            "!WrongDevice (expected device 0x02, got 0x01)"
+        );
+        assert_eq!(response.value(), None);
+
+        // ops with a device address of their own are checked without the
+        // port's
+        let mut op = op;
+        op.device_addr = Some(3);
+        assert!(decode_frame(op, response.bytes).is_err());
+    }
+
+    #[test]
+    fn read_device_id_objects() {
+        let op = Operation::new(
+            "ident".to_string(),
+            Request::ReadDeviceId(DeviceIdLevel::Basic),
+        );
+        let mut bytes = vec![
+            0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x03, 0x00, 0x03, b'A',
+            b'C', b'M', 0x01, 0x02, b'X', b'1', 0x02, 0x03, b'1', b'.', b'0',
+        ];
+        bytes.extend_from_slice(&checksum(&bytes).to_le_bytes());
+
+        let response = Response::new(op, bytes);
+        assert_eq!(response.frame_error(), None);
+        assert_eq!(
+            response.display_value(),
+            "VendorName: ACM, ProductCode: X1, MajorMinorRevision: 1.0"
+        );
+        assert_eq!(response.value(), None);
+    }
+
+    #[test]
+    fn custom_reply_as_hex() {
+        let op = Operation::new(
+            "vendor".to_string(),
+            Request::Custom(0x41, None, None),
+        );
+        let mut bytes = vec![0x01, 0x41, 0x12, 0xAB];
+        bytes.extend_from_slice(&checksum(&bytes).to_le_bytes());
+
+        let response = Response::new(op, bytes);
+        assert_eq!(response.frame_error(), None);
+        assert_eq!(response.display_value(), "01 41 12 AB");
+        assert_eq!(response.value(), None);
+    }
+
+    #[test]
+    fn decode_date_time_layouts() {
+        assert_eq!(
+            date_time_fields(
+                DateTimeLayout::BcdYearFirst,
+                &[0x2410, 0x1712, 0x3005]
+            ),
+            Some([2024, 10, 17, 12, 30, 5])
+        );
+        assert_eq!(
+            date_time_fields(
+                DateTimeLayout::BcdSecondFirst,
+                &[0x0530, 0x1217, 0x1024]
+            ),
+            Some([2024, 10, 17, 12, 30, 5])
+        );
+        assert_eq!(
+            date_time_fields(DateTimeLayout::Bytes, &[0x180A, 0x110C, 0x1E05]),
+            Some([2024, 10, 17, 12, 30, 5])
+        );
+        assert_eq!(
+            date_time_fields(DateTimeLayout::Words, &[2024, 10, 17, 12, 30, 5]),
+            Some([2024, 10, 17, 12, 30, 5])
+        );
+
+        // not BCD, and month 13
+        assert_eq!(
+            date_time_fields(
+                DateTimeLayout::BcdYearFirst,
+                &[0x241A, 0x1712, 0x3005]
+            ),
+            None
+        );
+        assert_eq!(
+            date_time_fields(
+                DateTimeLayout::BcdYearFirst,
+                &[0x2413, 0x1712, 0x3005]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn date_time_with_raw_registers() {
+        let mut op = read_op(DataType::DateTime, false, false);
+        op.date_time_layout = DateTimeLayout::BcdYearFirst;
+
+        let response = read_response(op, &[0x24, 0x10, 0x17, 0x12, 0x30, 0x05]);
+        assert_eq!(
+            response.display_value(),
+            "2024-10-17 12:30:05 [2410 1712 3005]"
+        );
+        assert_eq!(response.value(), None);
+    }
+
+    #[test]
+    fn value_without_crc() {
+        let op = read_op(DataType::U16, false, false);
+        let response =
+            Response::new(op.clone(), vec![0x01, 0x03, 0x02, 0x12, 0x34])
+                .with_crc(CrcAlgorithm::None.into());
+        assert_eq!(response.crc_ok(), None);
+        assert_eq!(response.value(), Some(0x1234 as f64));
+
+        let bytes = CrcConfig::from(CrcAlgorithm::Ccitt)
+            .reencode(read_response(op.clone(), &[0x12, 0x34]).bytes().to_vec());
+        let response = Response::new(op, bytes);
+        assert!(response.value().is_none());
+        assert_eq!(
+            response.with_crc(CrcAlgorithm::Ccitt.into()).value(),
+            Some(0x1234 as f64)
+        );
+    }
+}
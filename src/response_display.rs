@@ -1,39 +1,124 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
+use std::time::{Duration, Instant, SystemTime};
 
 use iced::{
-    widget::{Column, Text},
-    Command, Element, Length,
+    theme,
+    widget::{
+        canvas::{self, Canvas, Cursor, Frame, Geometry},
+        Button, Column, Container, Row, Text, TextInput,
+    },
+    Alignment, Color, Command, Element, Length, Point, Rectangle, Size, Theme,
 };
 
+use string_to_num::ParseNum;
+
 use crate::error::Error;
-use crate::port_op::Response;
+use crate::response::Response;
+use crate::style::TextSize;
+
 
+/// Check mark or cross showing whether the response passed its CRC check,
+/// blank if the frame was too short to carry a CRC
+fn crc_icon<'a>(resp: &Response) -> Text<'a> {
+    match resp.crc_ok() {
+        Some(true) => Text::new("✓").style(Color::from_rgb(0.2, 0.7, 0.2)),
+        Some(false) => Text::new("✗").style(Color::from_rgb(0.8, 0.2, 0.2)),
+        None => Text::new(" "),
+    }
+    .width(Length::Units(16))
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ResponseViewMessage {
     AddResponse(Result<Response, Error>),
+    /// A response of a burst, with its position and the burst size
+    AddBurstResponse((usize, usize), Result<Response, Error>),
+    AddLog(String),
+    /// Expand or collapse the frame breakdown of an entry
+    ToggleBreakdown(usize),
+}
+
+/// A line in the response view, either a response or a plain log message
+#[derive(Debug, PartialEq, Clone)]
+enum ResponseEntry {
+    /// Numbered with its position in a burst if it was part of one
+    Response(Result<Response, Error>, Option<(usize, usize)>),
+    Log(String),
+}
+
+/// Prefix numbering a response of a burst, empty if it was sent on its own
+fn burst_prefix(burst: Option<(usize, usize)>) -> String {
+    burst
+        .map_or_else(String::new, |(idx, count)| format!("[{}/{}] ", idx, count))
 }
 
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct ResponseView {
-    responses: Vec<Result<Response, Error>>,
+    responses: Vec<ResponseEntry>,
+    /// Entry whose frame breakdown is shown
+    expanded: Option<usize>,
+    /// Show only op names and values
+    compact: bool,
+}
+
+/// Text of a response, only its name and value in compact mode
+fn response_text(resp: &Response, compact: bool) -> String {
+    if compact {
+        resp.compact()
+    } else {
+        resp.to_string()
+    }
 }
 
 /// This impl block is View logic and Update logic
 impl ResponseView {
+    pub fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+
     pub fn view(&self) -> Element<ResponseViewMessage> {
         let mut column =
             Column::new().height(Length::Shrink).width(Length::Fill);
 
-        for resp in &self.responses {
-            let text = match resp {
-                Ok(resp) => Text::new(resp.to_string()),
-                Err(err) => Text::new(err.to_string()),
-            }
-            .width(Length::Fill);
+        for (idx, entry) in self.responses.iter().enumerate() {
+            let (resp, burst) = match entry {
+                ResponseEntry::Response(Ok(resp), burst) => (resp, *burst),
+                ResponseEntry::Response(Err(err), burst) => {
+                    column = column.push(
+                        Text::new(format!("{}{}", burst_prefix(*burst), err))
+                            .width(Length::Fill),
+                    );
+                    continue;
+                }
+                ResponseEntry::Log(log) => {
+                    column =
+                        column.push(Text::new(log.clone()).width(Length::Fill));
+                    continue;
+                }
+            };
 
-            column = column.push(text);
+            // responses can be clicked to show their frame breakdown
+            column = column.push(
+                Button::new(Row::new().push(crc_icon(resp)).push(Text::new(
+                    format!(
+                        "{}{}",
+                        burst_prefix(burst),
+                        response_text(resp, self.compact)
+                    ),
+                )))
+                .width(Length::Fill)
+                .padding(0)
+                .style(theme::Button::Text)
+                .on_press(ResponseViewMessage::ToggleBreakdown(idx)),
+            );
+            if self.expanded == Some(idx) {
+                for line in resp.breakdown() {
+                    column = column.push(
+                        Text::new(format!("    {}", line)).size(TextSize::BODY),
+                    );
+                }
+            }
         }
 
         column.into()
@@ -45,52 +130,612 @@ impl ResponseView {
     ) -> Command<ResponseViewMessage> {
         match msg {
             ResponseViewMessage::AddResponse(response) => {
-                self.responses.push(response);
+                self.responses.push(ResponseEntry::Response(response, None));
+                Command::none()
+            }
+            ResponseViewMessage::AddBurstResponse(burst, response) => {
+                self.responses
+                    .push(ResponseEntry::Response(response, Some(burst)));
+                Command::none()
+            }
+            ResponseViewMessage::AddLog(log) => {
+                self.responses.push(ResponseEntry::Log(log));
+                Command::none()
+            }
+            ResponseViewMessage::ToggleBreakdown(idx) => {
+                self.expanded =
+                    if self.expanded == Some(idx) { None } else { Some(idx) };
                 Command::none()
             }
         }
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
 pub enum KeyedResponseViewMessage {
-    AddResponse(String, Result<Response, Error>),
+    /// Keyed by the device address override and the op name
+    AddResponse((Option<u8>, String), Result<Response, Error>),
     ClearResponses,
+    /// Show or hide the raw frame of a key
+    ToggleRaw((Option<u8>, String)),
+    /// Show or hide the value histogram of a key
+    ToggleHistogram((Option<u8>, String)),
+    SetHistogramBuckets(String),
+    SetHistogramWindow(String),
+    /// Milliseconds a changed value stays highlighted, 0 turns it off
+    SetHighlight(String),
+    /// Show only the value of a key, in large text
+    PopOut((Option<u8>, String)),
+    ClosePopOut,
+}
+
+/// Buckets of a histogram unless the user sets a count
+const DEFAULT_BUCKETS: usize = 10;
+/// Latest values kept per key for its histogram unless the user sets a window
+const DEFAULT_WINDOW: usize = 200;
+/// Most values a histogram window may hold
+const MAX_WINDOW: usize = 10_000;
+/// Height of a histogram chart in pixels
+const HISTOGRAM_HEIGHT: u16 = 80;
+/// Milliseconds a changed value is highlighted unless the user sets a time
+const DEFAULT_HIGHLIGHT_MS: u64 = 1000;
+/// Longest a changed value may stay highlighted, in milliseconds
+const MAX_HIGHLIGHT_MS: u64 = 60_000;
+
+/// Split the range of `samples` into `buckets` equally wide buckets, the
+/// lower bound and number of samples of every bucket
+fn histogram(samples: &VecDeque<f64>, buckets: usize) -> Vec<(f64, usize)> {
+    if samples.is_empty() || buckets == 0 {
+        return vec![];
+    }
+
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (max - min) / buckets as f64;
+
+    let mut counts = vec![0; buckets];
+    for &sample in samples {
+        // the maximum belongs to the last bucket, a constant to the first
+        let idx = if width > 0.0 {
+            (((sample - min) / width) as usize).min(buckets - 1)
+        } else {
+            0
+        };
+        counts[idx] += 1;
+    }
+
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, count)| (min + width * idx as f64, count))
+        .collect()
+}
+
+/// Chart of the bucket counts of a histogram, one bar per bucket scaled to
+/// the fullest one
+#[derive(Debug)]
+struct HistogramChart {
+    counts: Vec<usize>,
+}
+
+impl<Message> canvas::Program<Message> for HistogramChart {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+
+        let most = self.counts.iter().copied().max().unwrap_or(0);
+        if most > 0 {
+            let width = frame.width() / self.counts.len() as f32;
+            for (idx, &count) in self.counts.iter().enumerate() {
+                let height = frame.height() * count as f32 / most as f32;
+                // a pixel of space on both sides keeps the bars apart
+                frame.fill_rectangle(
+                    Point::new(
+                        width * idx as f32 + 1.0,
+                        frame.height() - height,
+                    ),
+                    Size::new((width - 2.0).max(1.0), height),
+                    Color::from_rgb(0.2, 0.5, 0.9),
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+/// Latest response of a key in the continuous view and stats over every
+/// response received for it since the view was cleared
+#[derive(Debug, Clone)]
+pub struct KeyedEntry {
+    pub response: Result<Response, Error>,
+    pub updated: SystemTime,
+    /// Responses received
+    pub count: u64,
+    /// Responses without a valid value
+    pub failures: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// The latest values, oldest first, at most a histogram window of them
+    pub samples: VecDeque<f64>,
+    /// Show the raw bytes of the latest response
+    pub show_raw: bool,
+    /// Show the distribution of the latest values
+    pub show_histogram: bool,
+    /// The latest value is past one of the op's alarm limits
+    pub alarm: bool,
+    /// `val` of the latest sample before its expression and when it arrived
+    pub last_sample: Option<(f64, Instant)>,
+    /// When the shown value last differed from the one before it
+    pub changed: Option<Instant>,
+}
+
+/// Whether a response counts as failed, a response whose expression waits
+/// for a previous sample didn't fail
+fn failed(response: &Result<Response, Error>) -> bool {
+    match response {
+        Ok(resp) => resp.value().is_none() && !resp.awaiting_prev(),
+        Err(_) => true,
+    }
+}
+
+/// The register value of a response and when it arrived
+fn sample(response: &Result<Response, Error>) -> Option<(f64, Instant)> {
+    let sample = response.as_ref().ok().and_then(Response::sample)?;
+    Some((sample, Instant::now()))
+}
+
+/// The value a response is shown with, errors have none
+fn shown_value(response: &Result<Response, Error>) -> Option<String> {
+    response.as_ref().ok().map(Response::display_value)
+}
+
+/// Whether the value of a response is past one of its op's alarm limits
+fn in_alarm(response: &Result<Response, Error>) -> bool {
+    response.as_ref().ok().map_or(false, |resp| {
+        resp.value().and_then(|val| resp.op.alarm(val)).is_some()
+    })
+}
+
+impl KeyedEntry {
+    fn new(response: Result<Response, Error>) -> Self {
+        let value = response.as_ref().ok().and_then(Response::value);
+
+        Self {
+            updated: SystemTime::now(),
+            count: 1,
+            failures: failed(&response) as u64,
+            min: value,
+            max: value,
+            samples: value.into_iter().collect(),
+            show_raw: false,
+            show_histogram: false,
+            alarm: in_alarm(&response),
+            last_sample: sample(&response),
+            changed: None,
+            response,
+        }
+    }
+
+    fn record(&mut self, response: Result<Response, Error>, window: usize) {
+        self.count += 1;
+        match response.as_ref().ok().and_then(Response::value) {
+            Some(value) => {
+                self.min = Some(self.min.map_or(value, |min| min.min(value)));
+                self.max = Some(self.max.map_or(value, |max| max.max(value)));
+                self.samples.push_back(value);
+                self.trim_samples(window);
+            }
+            None if failed(&response) => self.failures += 1,
+            None => {}
+        }
+
+        self.alarm = in_alarm(&response);
+        if shown_value(&response) != shown_value(&self.response) {
+            self.changed = Some(Instant::now());
+        }
+        // a failed read keeps the sample before it
+        if let Some(sample) = sample(&response) {
+            self.last_sample = Some(sample);
+        }
+        self.response = response;
+        self.updated = SystemTime::now();
+    }
+
+    /// Drop the oldest values until at most `window` are left
+    fn trim_samples(&mut self, window: usize) {
+        while self.samples.len() > window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Bar chart of the latest values, labelled with their range and the
+    /// count of the fullest bucket
+    fn histogram_view<'a>(
+        &self,
+        buckets: usize,
+    ) -> Element<'a, KeyedResponseViewMessage> {
+        let counts = histogram(&self.samples, buckets)
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect::<Vec<_>>();
+        let most = counts.iter().copied().max().unwrap_or(0);
+        let min = self.samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        let labels = if self.samples.is_empty() {
+            Row::new().push(Text::new("No values yet").size(TextSize::SMALL))
+        } else {
+            Row::new()
+                .push(
+                    Text::new(format!("{:.3}", min))
+                        .size(TextSize::SMALL)
+                        .width(Length::Fill),
+                )
+                .push(
+                    Text::new(format!("fullest bucket: {}", most))
+                        .size(TextSize::SMALL)
+                        .width(Length::Fill),
+                )
+                .push(Text::new(format!("{:.3}", max)).size(TextSize::SMALL))
+        };
+
+        Column::new()
+            .padding([0, 0, 4, 32])
+            .push(
+                Canvas::new(HistogramChart { counts })
+                    .width(Length::Fill)
+                    .height(Length::Units(HISTOGRAM_HEIGHT)),
+            )
+            .push(labels)
+            .into()
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct KeyedResponseView {
-    quarries: HashMap<String, Result<Response, Error>>,
+    /// Sorted so responses from the same device are listed together
+    quarries: BTreeMap<(Option<u8>, String), KeyedEntry>,
+    /// Show only op names and values
+    compact: bool,
+    /// Buckets every histogram is split into
+    histogram_buckets: usize,
+    /// Latest values a histogram is drawn from
+    histogram_window: usize,
+    buckets_input: String,
+    window_input: String,
+    /// How long a changed value stands out. The view is only redrawn as
+    /// responses and metrics arrive, so it may stay a little longer
+    highlight: Duration,
+    highlight_input: String,
+    /// Key whose value is shown alone in place of the whole window
+    popped_out: Option<(Option<u8>, String)>,
+}
+
+impl Default for KeyedResponseView {
+    fn default() -> Self {
+        Self {
+            quarries: BTreeMap::new(),
+            compact: false,
+            histogram_buckets: DEFAULT_BUCKETS,
+            histogram_window: DEFAULT_WINDOW,
+            buckets_input: DEFAULT_BUCKETS.to_string(),
+            window_input: DEFAULT_WINDOW.to_string(),
+            highlight: Duration::from_millis(DEFAULT_HIGHLIGHT_MS),
+            highlight_input: DEFAULT_HIGHLIGHT_MS.to_string(),
+            popped_out: None,
+        }
+    }
 }
 
 impl KeyedResponseView {
+    pub fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+
+    /// Whether the latest value of `key` is past an alarm limit
+    pub fn in_alarm(&self, key: &(Option<u8>, String)) -> bool {
+        self.quarries.get(key).map_or(false, |entry| entry.alarm)
+    }
+
+    /// `val` of the latest sample of `key` and the time since, the `prev`
+    /// and `dt` of its next response
+    pub fn prev(&self, key: &(Option<u8>, String)) -> Option<(f64, Duration)> {
+        let (sample, at) = self.quarries.get(key)?.last_sample?;
+        Some((sample, at.elapsed()))
+    }
+
+    /// Every key with its latest response, in display order
+    pub fn entries(
+        &self,
+    ) -> impl Iterator<Item = (&(Option<u8>, String), &KeyedEntry)> {
+        self.quarries.iter()
+    }
+
     pub fn update(
         &mut self,
         msg: KeyedResponseViewMessage,
     ) -> Command<KeyedResponseViewMessage> {
         use KeyedResponseViewMessage::*;
         match msg {
-            AddResponse(key, response) => {
-                self.quarries.insert(key, response);
-            }
+            AddResponse(key, response) => match self.quarries.get_mut(&key) {
+                Some(entry) => entry.record(response, self.histogram_window),
+                None => {
+                    self.quarries.insert(key, KeyedEntry::new(response));
+                }
+            },
             ClearResponses => {
                 self.quarries.clear();
             }
+            ToggleRaw(key) => {
+                if let Some(entry) = self.quarries.get_mut(&key) {
+                    entry.show_raw = !entry.show_raw;
+                }
+            }
+            ToggleHistogram(key) => {
+                if let Some(entry) = self.quarries.get_mut(&key) {
+                    entry.show_histogram = !entry.show_histogram;
+                }
+            }
+            // an invalid count keeps the last valid one
+            SetHistogramBuckets(input) => {
+                if let Ok(buckets @ 1..=100) = input.parse_num::<usize>() {
+                    self.histogram_buckets = buckets;
+                }
+                self.buckets_input = input;
+            }
+            SetHistogramWindow(input) => {
+                if let Ok(window @ 1..=MAX_WINDOW) = input.parse_num::<usize>() {
+                    self.histogram_window = window;
+                    for entry in self.quarries.values_mut() {
+                        entry.trim_samples(window);
+                    }
+                }
+                self.window_input = input;
+            }
+            SetHighlight(input) => {
+                if let Ok(ms @ 0..=MAX_HIGHLIGHT_MS) = input.parse_num::<u64>() {
+                    self.highlight = Duration::from_millis(ms);
+                }
+                self.highlight_input = input;
+            }
+            PopOut(key) => self.popped_out = Some(key),
+            ClosePopOut => self.popped_out = None,
         }
 
         Command::none()
     }
 
     pub fn view(&self) -> Element<KeyedResponseViewMessage> {
-        let mut column =
-            Column::new().height(Length::Shrink).width(Length::Fill);
+        let mut column = Column::new()
+            .height(Length::Shrink)
+            .width(Length::Fill)
+            .push(self.highlight_settings());
+        if self.quarries.values().any(|entry| entry.show_histogram) {
+            column = column.push(self.histogram_settings());
+        }
+
+        let mut last_device = None;
+        for ((device_addr, key), entry) in self.quarries.iter() {
+            // a header before the first response of each gateway device
+            if *device_addr != last_device {
+                if let Some(addr) = device_addr {
+                    column = column.push(Text::new(format!("Device {}", addr)));
+                }
+                last_device = *device_addr;
+            }
 
-        for (key, resp) in self.quarries.iter() {
-            column = match resp {
-                Ok(resp) => column.push(Text::new(resp.to_string())),
+            let entry_key = (*device_addr, key.clone());
+            let highlighted =
+                entry.changed.map_or(false, |at| at.elapsed() < self.highlight);
+            column = match &entry.response {
+                // responses can be clicked to show their raw frame
+                Ok(resp) => column.push(
+                    Row::new()
+                        .align_items(Alignment::Center)
+                        .push(
+                            Button::new(Row::new().push(crc_icon(resp)).push(
+                                // values past an alarm limit stand out
+                                if entry.alarm {
+                                    Text::new(format!(
+                                        "! {}",
+                                        response_text(resp, self.compact)
+                                    ))
+                                    .style(Color::from_rgb(0.8, 0.2, 0.2))
+                                } else if highlighted {
+                                    // draws the eye to values that just
+                                    // changed
+                                    Text::new(response_text(resp, self.compact))
+                                        .style(Color::from_rgb(0.2, 0.5, 0.9))
+                                } else {
+                                    Text::new(response_text(resp, self.compact))
+                                },
+                            ))
+                            .width(Length::Fill)
+                            .padding(0)
+                            .style(theme::Button::Text)
+                            .on_press(
+                                KeyedResponseViewMessage::ToggleRaw(
+                                    entry_key.clone(),
+                                ),
+                            ),
+                        )
+                        .push(
+                            Button::new(
+                                Text::new("Histogram").size(TextSize::SMALL),
+                            )
+                            .padding([0, 4])
+                            .style(theme::Button::Text)
+                            .on_press(
+                                KeyedResponseViewMessage::ToggleHistogram(
+                                    entry_key.clone(),
+                                ),
+                            ),
+                        )
+                        .push(
+                            Button::new(
+                                Text::new("Pop Out").size(TextSize::SMALL),
+                            )
+                            .padding([0, 4])
+                            .style(theme::Button::Text)
+                            .on_press(
+                                KeyedResponseViewMessage::PopOut(entry_key),
+                            ),
+                        ),
+                ),
                 Err(err) => column.push(Text::new(format!("{}: {}", key, err))),
+            };
+            if let (true, Ok(resp)) = (entry.show_raw, &entry.response) {
+                let hex = resp
+                    .bytes()
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                column = column.push(
+                    Text::new(format!("    {}", hex)).size(TextSize::BODY),
+                );
+            }
+            if entry.show_histogram {
+                column =
+                    column.push(entry.histogram_view(self.histogram_buckets));
             }
         }
 
         column.into()
     }
+
+    pub fn is_popped_out(&self) -> bool {
+        self.popped_out.is_some()
+    }
+
+    /// The popped out key's name and latest value in large text, for
+    /// glancing at from the device, `None` if no key is popped out
+    pub fn pop_out_view(&self) -> Option<Element<KeyedResponseViewMessage>> {
+        let (device_addr, name) = self.popped_out.as_ref()?;
+        let entry = self.quarries.get(&(*device_addr, name.clone()));
+
+        let title = match device_addr {
+            Some(addr) => format!("{} @ Device {}", name, addr),
+            None => name.clone(),
+        };
+        // the key is kept if the responses are cleared, its next response
+        // shows again
+        let value = match entry.map(|entry| &entry.response) {
+            Some(Ok(resp)) => Text::new(resp.display_value()),
+            Some(Err(e)) => Text::new(e.to_string()),
+            None => Text::new("-"),
+        }
+        .size(TextSize::DISPLAY);
+        let value = if entry.map_or(false, |entry| entry.alarm) {
+            value.style(Color::from_rgb(0.8, 0.2, 0.2))
+        } else {
+            value
+        };
+
+        Some(
+            Container::new(
+                Column::new()
+                    .spacing(8)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(title).size(TextSize::HEADING))
+                    .push(value)
+                    .push(
+                        Button::new("Back")
+                            .on_press(KeyedResponseViewMessage::ClosePopOut),
+                    ),
+            )
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .center_x()
+            .center_y()
+            .into(),
+        )
+    }
+
+    /// Input for how long changed values are highlighted
+    fn highlight_settings(&self) -> Element<KeyedResponseViewMessage> {
+        Row::new()
+            .spacing(4)
+            .align_items(Alignment::Center)
+            .push(Text::new("Highlight changes for").size(TextSize::SMALL))
+            .push(
+                TextInput::new(
+                    "ms",
+                    &self.highlight_input,
+                    KeyedResponseViewMessage::SetHighlight,
+                )
+                .width(Length::Units(64))
+                .padding([0, 2]),
+            )
+            .push(Text::new("ms").size(TextSize::SMALL))
+            .into()
+    }
+
+    /// Bucket count and window inputs shared by every histogram, shown once
+    /// above the responses while any histogram is open
+    fn histogram_settings(&self) -> Element<KeyedResponseViewMessage> {
+        Row::new()
+            .spacing(4)
+            .align_items(Alignment::Center)
+            .push(Text::new("Buckets").size(TextSize::SMALL))
+            .push(
+                TextInput::new(
+                    "Buckets",
+                    &self.buckets_input,
+                    KeyedResponseViewMessage::SetHistogramBuckets,
+                )
+                .width(Length::Units(64))
+                .padding([0, 2]),
+            )
+            .push(Text::new("Window").size(TextSize::SMALL))
+            .push(
+                TextInput::new(
+                    "Samples",
+                    &self.window_input,
+                    KeyedResponseViewMessage::SetHistogramWindow,
+                )
+                .width(Length::Units(80))
+                .padding([0, 2]),
+            )
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_bucketed_by_range() {
+        let samples = VecDeque::from([0.0, 1.0, 2.5, 4.9, 5.0, 9.9, 10.0]);
+        assert_eq!(
+            histogram(&samples, 2),
+            [(0.0, 4), (5.0, 3)],
+            "the maximum belongs to the last bucket"
+        );
+        assert_eq!(
+            histogram(&samples, 4),
+            [(0.0, 2), (2.5, 2), (5.0, 1), (7.5, 2)]
+        );
+    }
+
+    #[test]
+    fn constant_samples_fill_the_first_bucket() {
+        let samples = VecDeque::from([3.0, 3.0, 3.0]);
+        assert_eq!(histogram(&samples, 3), [(3.0, 3), (3.0, 0), (3.0, 0)]);
+    }
+
+    #[test]
+    fn nothing_to_bucket() {
+        assert!(histogram(&VecDeque::new(), 10).is_empty());
+        assert!(histogram(&VecDeque::from([1.0]), 0).is_empty());
+    }
 }
@@ -0,0 +1,189 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Comparison used by a rule to test a decoded value against its threshold
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum Comparison {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    pub fn compare(&self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterEqual => lhs >= rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessEqual => lhs <= rhs,
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Comparison::Greater => ">",
+                Comparison::GreaterEqual => ">=",
+                Comparison::Less => "<",
+                Comparison::LessEqual => "<=",
+                Comparison::Equal => "==",
+                Comparison::NotEqual => "!=",
+            }
+        )
+    }
+}
+
+/// When the decoded value of `source_op` satisfies `comparison` against
+/// `threshold`, the operation named `target_op` is sent once
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub source_op: String,
+    /// Device `source_op` is polled on in gateway mode, `None` for an op
+    /// without a device address of its own
+    #[serde(default)]
+    pub source_device: Option<u8>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub target_op: String,
+
+    /// Whether the condition held on the last evaluation, rules only fire on
+    /// the transition from not holding to holding
+    #[serde(skip)]
+    active: bool,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.source_device {
+            Some(addr) => write!(f, "if {} @ Device {}", self.source_op, addr)?,
+            None => write!(f, "if {}", self.source_op)?,
+        }
+        write!(
+            f,
+            " {} {} then {}",
+            self.comparison, self.threshold, self.target_op
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RuleList {
+    rules: Vec<Rule>,
+}
+
+impl RuleList {
+    /// Feed the decoded value of the operation keyed `source`, by its device
+    /// address override and name, to every rule watching it, returns the
+    /// rules that fired
+    pub fn evaluate(
+        &mut self,
+        source: &(Option<u8>, String),
+        value: f64,
+    ) -> Vec<Rule> {
+        let mut fired = vec![];
+
+        for rule in self
+            .rules
+            .iter_mut()
+            .filter(|r| r.source_device == source.0 && r.source_op == source.1)
+        {
+            let holds = rule.comparison.compare(value, rule.threshold);
+            if holds && !rule.active {
+                fired.push(rule.clone());
+            }
+            rule.active = holds;
+        }
+
+        fired
+    }
+
+    /// Re-arm all rules, used when a new continuous quarry starts
+    pub fn reset(&mut self) {
+        for rule in self.rules.iter_mut() {
+            rule.active = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(comparison: Comparison, threshold: f64) -> RuleList {
+        RuleList {
+            rules: vec![Rule {
+                source_op: "temp".to_string(),
+                source_device: None,
+                comparison,
+                threshold,
+                target_op: "fan".to_string(),
+                active: false,
+            }],
+        }
+    }
+
+    fn fired(rules: &mut RuleList, source: &str, values: &[f64]) -> Vec<usize> {
+        let source = (None, source.to_string());
+        values
+            .iter()
+            .map(|value| rules.evaluate(&source, *value).len())
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_on_crossing() {
+        let mut rules = rules(Comparison::Greater, 30f64);
+        assert_eq!(
+            fired(&mut rules, "temp", &[25., 31., 35., 40.]),
+            [0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn re_arms_after_leaving() {
+        let mut rules = rules(Comparison::GreaterEqual, 30f64);
+        assert_eq!(
+            fired(&mut rules, "temp", &[30., 29., 30., 30., 10., 50.]),
+            [1, 0, 1, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn reset_re_arms() {
+        let mut rules = rules(Comparison::Less, 0f64);
+        assert_eq!(fired(&mut rules, "temp", &[-1.]), [1]);
+        rules.reset();
+        assert_eq!(fired(&mut rules, "temp", &[-1., -2.]), [1, 0]);
+    }
+
+    #[test]
+    fn other_ops_are_ignored() {
+        let mut rules = rules(Comparison::Equal, 1f64);
+        assert_eq!(fired(&mut rules, "pressure", &[1., 1.]), [0, 0]);
+        assert_eq!(fired(&mut rules, "temp", &[1.]), [1]);
+    }
+
+    #[test]
+    fn same_op_on_other_devices_is_ignored() {
+        let mut rules = rules(Comparison::Greater, 30f64);
+        rules.rules[0].source_device = Some(2);
+
+        let other = (Some(3), "temp".to_string());
+        assert!(rules.evaluate(&other, 40f64).is_empty());
+        assert!(rules.evaluate(&(None, "temp".to_string()), 40f64).is_empty());
+
+        let fired = rules.evaluate(&(Some(2), "temp".to_string()), 40f64);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].to_string(), "if temp @ Device 2 > 30 then fan");
+    }
+}
@@ -0,0 +1,497 @@
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use iced::{
+    widget::{Button, Column, Row, Scrollable, Text},
+    Alignment, Command, Element, Length,
+};
+
+use crate::error::{ErrKind, Error};
+use crate::modbus::RequestFrame;
+use crate::port_op::{ErrorPolicy, OpMessage, PortOption, RunEnd};
+use crate::transport::{PortConfig, Transport};
+
+/// Lowest and highest assignable Modbus device address
+const SCAN_ADDRESSES: std::ops::RangeInclusive<u8> = 1..=247;
+
+/// Standard baud rates tried by baud detection, most common first
+pub const BAUD_RATES: &[u32] =
+    &[9600, 19200, 38400, 57600, 115200, 4800, 2400, 1200];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanEvent {
+    /// An address was probed
+    Probed(u8),
+    /// A device at the address answered after the given time
+    Found(u8, Duration),
+    /// A baud rate was tried without a valid answer
+    BaudTried(u32),
+    /// The device answered at the baud rate
+    BaudFound(u32),
+    /// Probing an address or baud rate failed and the scan went on, with
+    /// what failed
+    Failed(String),
+    /// Every address or baud rate was probed or the scan was canceled
+    Finished,
+}
+
+/// Handle messages that arrived while scanning, returns how the scan ends if
+/// it should stop
+fn scan_canceled(rx: &Receiver<OpMessage>) -> Option<RunEnd> {
+    while let Ok(op_msg) = rx.try_recv() {
+        // don't care if the sends fail
+        match op_msg {
+            OpMessage::StopScan | OpMessage::Disconnect => {
+                return Some(RunEnd::Done)
+            }
+            OpMessage::Shutdown => return Some(RunEnd::Shutdown),
+            OpMessage::OneShot(_, _, resp_tx)
+            | OpMessage::StartContinuous(_, _, _, resp_tx) => {
+                let _ = resp_tx.send(Err(Error::with_message(
+                    ErrKind::PortBusyScanning,
+                    "Port is busy scanning the bus".to_string(),
+                )));
+            }
+            OpMessage::Scan(_, _, scan_tx)
+            | OpMessage::DetectBaud(_, _, scan_tx) => {
+                let _ = scan_tx.send(Err(Error::with_message(
+                    ErrKind::PortBusyScanning,
+                    "A bus scan is already running".to_string(),
+                )));
+            }
+            OpMessage::Monitor(_, monitor_tx) => {
+                let _ = monitor_tx.send(Err(Error::with_message(
+                    ErrKind::PortBusyScanning,
+                    "Port is busy scanning the bus".to_string(),
+                )));
+            }
+            OpMessage::TestOpen(_, open_tx) => {
+                let _ = open_tx.send(Err(Error::with_message(
+                    ErrKind::PortBusyScanning,
+                    "Port is busy scanning the bus".to_string(),
+                )));
+            }
+            OpMessage::StopContinuous
+            | OpMessage::PauseContinuous(_)
+            | OpMessage::StopMonitor => {}
+        }
+    }
+
+    None
+}
+
+/// Send a single holding register read to `device_addr` with the checksum of
+/// `port_conf`, any answer from that address with a valid checksum counts,
+/// exceptions included. With `skip_crc` the checksum isn't checked. Returns
+/// the time it took to answer
+fn probe(
+    transport: &mut dyn Transport,
+    port_conf: &PortConfig,
+    device_addr: u8,
+) -> std::io::Result<Option<Duration>> {
+    let frame = RequestFrame { device_addr, function: 0x03, addr: 0, value: 1 };
+
+    let start = Instant::now();
+    transport.write_frame(&port_conf.crc.reencode(frame.to_bytes().to_vec()))?;
+    let response = transport.read_frame().unwrap_or_default();
+    let latency = start.elapsed();
+
+    // the shortest answer is an exception, address, function and code
+    let answered = response.len() >= 3 + port_conf.crc.trailer_len()
+        && response[0] == device_addr
+        && (port_conf.skip_crc || port_conf.crc.check(&response) != Some(false));
+
+    Ok(answered.then_some(latency))
+}
+
+fn write_failed(e: std::io::Error) -> Error {
+    Error::with_message(
+        ErrKind::PortWriteFailed,
+        format!("Failed to write msg to port due to: {}", e),
+    )
+}
+
+/// Report a failed probe, returns true if the scan should stop. With
+/// `ErrorPolicy::Stop` the scan ends on the error without finishing
+fn probe_failed(
+    policy: ErrorPolicy,
+    target: String,
+    error: Error,
+    tx: &Sender<Result<ScanEvent, Error>>,
+) -> bool {
+    // don't care if the sends fail
+    match policy {
+        ErrorPolicy::Stop => {
+            let _ = tx.send(Err(error));
+            true
+        }
+        ErrorPolicy::Continue => tx
+            .send(Ok(ScanEvent::Failed(format!(
+                "{}: {}",
+                target,
+                error.message()
+            ))))
+            .is_err(),
+    }
+}
+
+/// Probe every device address, runs on port_op_thread, `StopScan` cancels it
+pub fn scan_bus(
+    transport: &mut dyn Transport,
+    port_conf: &PortConfig,
+    policy: ErrorPolicy,
+    rx: &Receiver<OpMessage>,
+    tx: &Sender<Result<ScanEvent, Error>>,
+) -> RunEnd {
+    for device_addr in SCAN_ADDRESSES {
+        if let Some(end) = scan_canceled(rx) {
+            let _ = tx.send(Ok(ScanEvent::Finished));
+            return end;
+        }
+
+        let event = match probe(transport, port_conf, device_addr) {
+            Ok(Some(latency)) => ScanEvent::Found(device_addr, latency),
+            Ok(None) => ScanEvent::Probed(device_addr),
+            Err(e) => {
+                let target = format!("address {}", device_addr);
+                if probe_failed(policy, target, write_failed(e), tx) {
+                    return RunEnd::Done;
+                }
+                continue;
+            }
+        };
+        if tx.send(Ok(event)).is_err() {
+            return RunEnd::Done;
+        }
+    }
+
+    let _ = tx.send(Ok(ScanEvent::Finished));
+    RunEnd::Done
+}
+
+/// Probe the configured device at every rate in `BAUD_RATES` until one gives a
+/// valid answer, each rate on a port opened by `open`. Runs on
+/// port_op_thread, `StopScan` cancels it
+pub fn detect_baud(
+    port_conf: &PortConfig,
+    policy: ErrorPolicy,
+    rx: &Receiver<OpMessage>,
+    tx: &Sender<Result<ScanEvent, Error>>,
+    mut open: impl FnMut(&PortConfig) -> Result<Box<dyn Transport>, Error>,
+) -> RunEnd {
+    for &baud in BAUD_RATES {
+        if let Some(end) = scan_canceled(rx) {
+            let _ = tx.send(Ok(ScanEvent::Finished));
+            return end;
+        }
+
+        let port_conf = PortConfig { baud, ..port_conf.clone() };
+        let mut transport = match open(&port_conf) {
+            Ok(transport) => transport,
+            Err(e) => {
+                if probe_failed(policy, format!("{} baud", baud), e, tx) {
+                    return RunEnd::Done;
+                }
+                continue;
+            }
+        };
+
+        let device_addr = port_conf.device_addr;
+        let event = match probe(transport.as_mut(), &port_conf, device_addr) {
+            Ok(Some(_)) => ScanEvent::BaudFound(baud),
+            Ok(None) => ScanEvent::BaudTried(baud),
+            Err(e) => {
+                let target = format!("{} baud", baud);
+                if probe_failed(policy, target, write_failed(e), tx) {
+                    return RunEnd::Done;
+                }
+                continue;
+            }
+        };
+        let found = matches!(event, ScanEvent::BaudFound(_));
+        if tx.send(Ok(event)).is_err() || found {
+            break;
+        }
+    }
+
+    let _ = tx.send(Ok(ScanEvent::Finished));
+    RunEnd::Done
+}
+
+pub async fn scan_start(
+    port_option: PortOption,
+    policy: ErrorPolicy,
+    port_op_tx: Sender<OpMessage>,
+    sender: Sender<Result<ScanEvent, Error>>,
+) -> Result<(), Error> {
+    let port_conf: PortConfig = port_option.try_into()?;
+
+    if port_op_tx.send(OpMessage::Scan(port_conf, policy, sender)).is_err() {
+        Err(Error::new(ErrKind::PortOpThreadNotPresent))
+    } else {
+        Ok(())
+    }
+}
+
+/// Detect the baud rate of the configured device, the selected baud is
+/// ignored
+pub async fn detect_baud_start(
+    port_option: PortOption,
+    policy: ErrorPolicy,
+    port_op_tx: Sender<OpMessage>,
+    sender: Sender<Result<ScanEvent, Error>>,
+) -> Result<(), Error> {
+    let port_conf: PortConfig =
+        PortOption { baud: BAUD_RATES[0].to_string(), ..port_option }
+            .try_into()?;
+
+    if port_op_tx.send(OpMessage::DetectBaud(port_conf, policy, sender)).is_err()
+    {
+        Err(Error::new(ErrKind::PortOpThreadNotPresent))
+    } else {
+        Ok(())
+    }
+}
+
+pub async fn scan_get_results(
+    rx: Arc<Mutex<Receiver<Result<ScanEvent, Error>>>>,
+) -> Result<Vec<Result<ScanEvent, Error>>, Error> {
+    // Locking really shouldn't fail, crash the process if that happens
+    let rx = rx.lock().unwrap();
+    let event = if let Ok(event) = rx.recv() {
+        event
+    } else {
+        return Err(Error::with_message(
+            ErrKind::PortOpThreadNotPresent,
+            "port op thread not present".to_string(),
+        ));
+    };
+
+    let mut result = vec![event];
+
+    while let Ok(event) = rx.try_recv() {
+        result.push(event);
+    }
+
+    Ok(result)
+}
+
+pub async fn scan_stop(tx: Sender<OpMessage>) {
+    let _ = tx.send(OpMessage::StopScan);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanViewMessage {
+    AddEvent(ScanEvent),
+    Clear,
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanView {
+    found: Vec<(u8, Duration)>,
+    last_probed: Option<u8>,
+    last_baud: Option<u32>,
+    found_baud: Option<u32>,
+    /// Probes that failed while the scan went on
+    failures: Vec<String>,
+    finished: bool,
+    visible: bool,
+}
+
+impl ScanView {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn update(&mut self, msg: ScanViewMessage) -> Command<ScanViewMessage> {
+        match msg {
+            ScanViewMessage::AddEvent(event) => match event {
+                ScanEvent::Probed(addr) => self.last_probed = Some(addr),
+                ScanEvent::Found(addr, latency) => {
+                    self.last_probed = Some(addr);
+                    self.found.push((addr, latency));
+                }
+                ScanEvent::BaudTried(baud) => self.last_baud = Some(baud),
+                ScanEvent::BaudFound(baud) => {
+                    self.last_baud = Some(baud);
+                    self.found_baud = Some(baud);
+                }
+                ScanEvent::Failed(failure) => self.failures.push(failure),
+                ScanEvent::Finished => self.finished = true,
+            },
+            ScanViewMessage::Clear => {
+                *self = Self { visible: true, ..Self::default() };
+            }
+            ScanViewMessage::Close => {
+                self.visible = false;
+            }
+        }
+
+        Command::none()
+    }
+
+    pub fn view(&self) -> Element<ScanViewMessage> {
+        let failed = match self.failures.len() {
+            0 => String::new(),
+            failures => format!(", {} failed", failures),
+        };
+        let status = if let Some(baud) = self.found_baud {
+            format!("Device answered at {} baud", baud)
+        } else if let Some(baud) = self.last_baud {
+            if self.finished {
+                format!("No baud rate got a valid answer{}", failed)
+            } else {
+                format!("Trying {} baud{}", baud, failed)
+            }
+        } else {
+            match (self.finished, self.last_probed) {
+                (true, _) => {
+                    format!(
+                        "Scan finished, {} found{}",
+                        self.found.len(),
+                        failed
+                    )
+                }
+                (false, Some(addr)) => format!(
+                    "Scanning {}/{}, {} found{}",
+                    addr,
+                    SCAN_ADDRESSES.end(),
+                    self.found.len(),
+                    failed
+                ),
+                (false, None) => "Scan starting".to_string(),
+            }
+        };
+
+        let mut column =
+            Column::new().width(Length::Fill).height(Length::Shrink).push(
+                Row::new()
+                    .padding(5)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(status).width(Length::Fill))
+                    .push(Button::new("Close").on_press(ScanViewMessage::Close)),
+            );
+
+        for (addr, latency) in &self.found {
+            column = column.push(Text::new(format!(
+                "Device {} (0x{:02X}): {} ms",
+                addr,
+                addr,
+                latency.as_millis()
+            )));
+        }
+        for failure in &self.failures {
+            column = column.push(Text::new(format!("Failed {}", failure)));
+        }
+
+        Scrollable::new(column).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use crate::modbus::{CrcAlgorithm, CrcByteOrder, CrcConfig};
+    use crate::transport::MemoryTransport;
+
+    /// Exception reply of `device_addr` to the probe
+    fn reply(device_addr: u8, crc: CrcConfig) -> Vec<u8> {
+        let mut reply = vec![device_addr, 0x83, 0x02];
+        crc.append(&mut reply);
+        reply
+    }
+
+    /// Addresses found by scanning over `transport`
+    fn found(
+        transport: &mut MemoryTransport,
+        port_conf: &PortConfig,
+    ) -> Vec<u8> {
+        let (_op_tx, op_rx) = channel();
+        let (tx, rx) = channel();
+        scan_bus(transport, port_conf, ErrorPolicy::Stop, &op_rx, &tx);
+
+        let events = rx.try_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(events.last(), Some(&ScanEvent::Finished));
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                ScanEvent::Found(addr, _) => Some(addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_answering_devices() {
+        let crc = CrcConfig::default();
+        let mut transport =
+            MemoryTransport::new([vec![], reply(2, crc), reply(2, crc)]);
+
+        assert_eq!(found(&mut transport, &PortConfig::default()), [2]);
+        assert_eq!(transport.written().len(), 247);
+    }
+
+    #[test]
+    fn bad_crc_only_counts_with_skip_crc() {
+        let mut bad = reply(1, CrcConfig::default());
+        bad[3] ^= 0xFF;
+
+        let port_conf = PortConfig::default();
+        let mut transport = MemoryTransport::new([bad.clone()]);
+        assert!(found(&mut transport, &port_conf).is_empty());
+
+        let port_conf = PortConfig { skip_crc: true, ..port_conf };
+        let mut transport = MemoryTransport::new([bad]);
+        assert_eq!(found(&mut transport, &port_conf), [1]);
+    }
+
+    #[test]
+    fn probes_with_the_port_crc() {
+        let crc = CrcConfig {
+            algorithm: CrcAlgorithm::Ccitt,
+            byte_order: CrcByteOrder::HighFirst,
+        };
+        let port_conf = PortConfig { crc, ..PortConfig::default() };
+
+        let mut transport = MemoryTransport::new([
+            reply(1, crc),
+            reply(2, CrcConfig::default()),
+        ]);
+        assert_eq!(found(&mut transport, &port_conf), [1]);
+
+        let frame =
+            RequestFrame { device_addr: 1, function: 0x03, addr: 0, value: 1 };
+        assert_eq!(
+            transport.written()[0],
+            crc.reencode(frame.to_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn detects_the_answering_baud() {
+        let port_conf = PortConfig { device_addr: 1, ..PortConfig::default() };
+        let (_op_tx, op_rx) = channel();
+        let (tx, rx) = channel();
+        detect_baud(&port_conf, ErrorPolicy::Stop, &op_rx, &tx, |port_conf| {
+            let replies = match port_conf.baud {
+                19200 => vec![reply(1, port_conf.crc)],
+                _ => vec![],
+            };
+            Ok(Box::new(MemoryTransport::new(replies)))
+        });
+
+        let events = rx.try_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                ScanEvent::BaudTried(9600),
+                ScanEvent::BaudFound(19200),
+                ScanEvent::Finished
+            ]
+        );
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use iced::{
+    alignment::{Horizontal, Vertical},
+    widget::{Button, Column, Row, Scrollable, Text, TextInput},
+    Alignment, Command, Element, Length,
+};
+
+use serde::{Deserialize, Serialize};
+use string_to_num::ParseNum;
+
+use crate::error::{ErrKind, Error};
+use crate::message_sender::MAX_READ_QUANTITY;
+use crate::modbus::{checksum, RequestFrame};
+use crate::style::TextSize;
+use crate::transport::{SerialTransport, Transport};
+
+/// A simulated Modbus RTU slave serving a single register map for function
+/// codes 0x03, 0x04 and 0x06
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Slave {
+    device_addr: u8,
+    registers: HashMap<u16, u16>,
+}
+
+impl Slave {
+    pub fn new(device_addr: u8, registers: HashMap<u16, u16>) -> Self {
+        Self { device_addr, registers }
+    }
+
+    /// Build the response frame for a request frame, `None` if the request
+    /// is not addressed to this slave or is malformed, in which case a real
+    /// slave stays silent too
+    pub fn respond(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let request = RequestFrame::from_bytes(frame).ok()?;
+        if request.device_addr != self.device_addr {
+            return None;
+        }
+
+        let (func, addr, val) = (request.function, request.addr, request.value);
+
+        let mut response = match func {
+            0x03 | 0x04 => {
+                if val == 0 || val > MAX_READ_QUANTITY {
+                    exception(self.device_addr, func, 0x03)
+                } else {
+                    let values = (0..val)
+                        .map(|offset| {
+                            addr.checked_add(offset)
+                                .and_then(|addr| self.registers.get(&addr))
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    match values {
+                        Some(values) => {
+                            let mut bytes =
+                                vec![self.device_addr, func, (val * 2) as u8];
+                            for value in values {
+                                bytes.push((value >> 8) as u8);
+                                bytes.push(*value as u8);
+                            }
+                            bytes
+                        }
+                        None => exception(self.device_addr, func, 0x02),
+                    }
+                }
+            }
+            0x06 => {
+                self.registers.insert(addr, val);
+                frame[..6].to_vec()
+            }
+            _ => exception(self.device_addr, func, 0x01),
+        };
+
+        let crc = checksum(&response);
+        response.push(crc as u8);
+        response.push((crc >> 8) as u8);
+
+        Some(response)
+    }
+
+    pub fn register(&self, addr: u16) -> Option<u16> {
+        self.registers.get(&addr).copied()
+    }
+
+    /// Take over the device address and register set of `table`, registers
+    /// already served keep their current value, which may have been written
+    /// by the tester, unless their address is `edited`
+    fn merge(&mut self, table: Slave, edited: Option<u16>) {
+        self.device_addr = table.device_addr;
+        self.registers.retain(|addr, _| table.registers.contains_key(addr));
+        for (addr, val) in table.registers {
+            if edited == Some(addr) || !self.registers.contains_key(&addr) {
+                self.registers.insert(addr, val);
+            }
+        }
+    }
+}
+
+/// Exception response without CRC
+fn exception(device_addr: u8, func: u8, code: u8) -> Vec<u8> {
+    vec![device_addr, func | 0x80, code]
+}
+
+/// Serve requests arriving on `transport` until a message is received on, or
+/// the sender of, `stop_rx` is dropped
+fn simulator_thread(
+    mut transport: impl Transport,
+    slave: Arc<Mutex<Slave>>,
+    stop_rx: Receiver<()>,
+) {
+    loop {
+        match stop_rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            _ => return,
+        }
+
+        let request = match transport.read_frame() {
+            Ok(request) => request,
+            Err(_) => {
+                // reading the master side of a pty fails while no one has the
+                // slave side open, back off instead of spinning
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+        };
+
+        if request.is_empty() {
+            continue;
+        }
+
+        // Locking really shouldn't fail, crash the thread if that happens
+        let response = slave.lock().unwrap().respond(&request);
+        if let Some(response) = response {
+            // don't care if write failed, the tester will time out
+            let _ = transport.write_frame(&response);
+        }
+    }
+}
+
+/// A running simulator, dropping it stops the simulator thread
+#[derive(Debug)]
+struct SimulatorHandle {
+    port_name: String,
+    slave: Arc<Mutex<Slave>>,
+    _stop_tx: Sender<()>,
+}
+
+#[cfg(unix)]
+fn start_simulator(slave: Slave) -> Result<SimulatorHandle, Error> {
+    use serialport::SerialPort;
+
+    let (mut master, slave_port) = serialport::TTYPort::pair().map_err(|e| {
+        Error::with_message(
+            ErrKind::FailedToStartSimulator,
+            format!("Failed to create virtual port pair due to: {}", e),
+        )
+    })?;
+
+    let port_name = match slave_port.name() {
+        Some(name) => name,
+        None => {
+            return Err(Error::with_message(
+                ErrKind::FailedToStartSimulator,
+                "Virtual port has no name".to_string(),
+            ))
+        }
+    };
+    // the tester opens the slave side by name, release our handle to it
+    drop(slave_port);
+
+    // don't care if this fails, the default timeout still works
+    let _ = master.set_timeout(Duration::from_millis(10));
+
+    let transport = SerialTransport::new(Box::new(master));
+
+    let slave = Arc::new(Mutex::new(slave));
+    let (stop_tx, stop_rx) = channel();
+    {
+        let slave = slave.clone();
+        std::thread::spawn(move || simulator_thread(transport, slave, stop_rx));
+    }
+
+    Ok(SimulatorHandle { port_name, slave, _stop_tx: stop_tx })
+}
+
+#[cfg(not(unix))]
+fn start_simulator(_slave: Slave) -> Result<SimulatorHandle, Error> {
+    Err(Error::with_message(
+        ErrKind::FailedToStartSimulator,
+        "The simulator needs a virtual port pair, which is only available on \
+        unix, use a null-modem emulator such as com0com instead"
+            .to_string(),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+struct RegisterView {
+    addr: String,
+    val: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulatorMessage {
+    Toggle,
+    SetDeviceAddress(String),
+    AddRegister,
+    RemoveRegister(usize),
+    SetRegisterAddr(usize, String),
+    SetRegisterValue(usize, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SimulatorView {
+    device_addr: String,
+    registers: Vec<RegisterView>,
+
+    #[serde(skip)]
+    running: Option<SimulatorHandle>,
+}
+
+impl SimulatorView {
+    /// Name of the port the tester should open to talk to the simulator
+    pub fn port_name(&self) -> Option<&String> {
+        self.running.as_ref().map(|handle| &handle.port_name)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Build a slave from the register table, rows that don't parse are
+    /// skipped
+    fn make_slave(&self) -> Result<Slave, Error> {
+        let device_addr = match self.device_addr.parse_num::<u8>() {
+            Ok(addr) => addr,
+            Err(_) => {
+                return Err(Error::with_message(
+                    ErrKind::FailedToStartSimulator,
+                    format!(
+                        "\"{}\" is not a valid device address",
+                        self.device_addr
+                    ),
+                ))
+            }
+        };
+
+        let registers = self
+            .registers
+            .iter()
+            .filter_map(|reg| {
+                Some((
+                    reg.addr.parse_num::<u16>().ok()?,
+                    reg.val.parse_num().ok()?,
+                ))
+            })
+            .collect();
+
+        Ok(Slave::new(device_addr, registers))
+    }
+
+    /// Push the register table to the running slave, only the value of the
+    /// register in row `edited` overrides what the slave currently holds
+    fn sync(&self, edited: Option<usize>) {
+        if let (Some(handle), Ok(table)) = (&self.running, self.make_slave()) {
+            let edited =
+                edited.and_then(|idx| self.registers[idx].addr.parse_num().ok());
+            handle.slave.lock().unwrap().merge(table, edited);
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        message: SimulatorMessage,
+    ) -> Result<Command<SimulatorMessage>, Error> {
+        match message {
+            SimulatorMessage::Toggle => {
+                if self.running.take().is_none() {
+                    self.running = Some(start_simulator(self.make_slave()?)?);
+                }
+            }
+            SimulatorMessage::SetDeviceAddress(addr) => {
+                self.device_addr = addr;
+                self.sync(None);
+            }
+            SimulatorMessage::AddRegister => {
+                self.registers.push(RegisterView::default());
+            }
+            SimulatorMessage::RemoveRegister(idx) => {
+                self.registers.remove(idx);
+                self.sync(None);
+            }
+            SimulatorMessage::SetRegisterAddr(idx, addr) => {
+                self.registers[idx].addr = addr;
+                self.sync(Some(idx));
+            }
+            SimulatorMessage::SetRegisterValue(idx, val) => {
+                self.registers[idx].val = val;
+                self.sync(Some(idx));
+            }
+        }
+
+        Ok(Command::none())
+    }
+
+    pub fn view(&self) -> Element<SimulatorMessage> {
+        let mut column =
+            Column::new().width(Length::Fill).height(Length::Shrink).push(
+                Row::new()
+                    .padding(5)
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new(match self.port_name() {
+                            Some(name) => format!("Simulator on {}", name),
+                            None => "Simulator stopped".to_string(),
+                        })
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        TextInput::new(
+                            "Slave Address",
+                            &self.device_addr,
+                            SimulatorMessage::SetDeviceAddress,
+                        )
+                        .width(Length::Units(120))
+                        .padding([0, 2]),
+                    ),
+            );
+
+        for (idx, reg) in self.registers.iter().enumerate() {
+            let current = self
+                .running
+                .as_ref()
+                .zip(reg.addr.parse_num::<u16>().ok())
+                .and_then(|(handle, addr)| {
+                    handle.slave.lock().unwrap().register(addr)
+                })
+                .map_or_else(|| "-".to_string(), |val| val.to_string());
+
+            column = column.push(
+                Row::new()
+                    .padding(5)
+                    .align_items(Alignment::Center)
+                    .push(
+                        Button::new(
+                            Text::new("-")
+                                .vertical_alignment(Vertical::Center)
+                                .horizontal_alignment(Horizontal::Center)
+                                .size(TextSize::HEADING),
+                        )
+                        .on_press(SimulatorMessage::RemoveRegister(idx)),
+                    )
+                    .push(
+                        TextInput::new("Address", &reg.addr, move |addr| {
+                            SimulatorMessage::SetRegisterAddr(idx, addr)
+                        })
+                        .width(Length::FillPortion(30))
+                        .padding([0, 2]),
+                    )
+                    .push(
+                        TextInput::new("Value", &reg.val, move |val| {
+                            SimulatorMessage::SetRegisterValue(idx, val)
+                        })
+                        .width(Length::FillPortion(30))
+                        .padding([0, 2]),
+                    )
+                    .push(Text::new(current).width(Length::FillPortion(20))),
+            );
+        }
+
+        column = column.push(
+            Row::new()
+                .push(
+                    Button::new(
+                        Text::new("+")
+                            .vertical_alignment(Vertical::Center)
+                            .horizontal_alignment(Horizontal::Center),
+                    )
+                    .width(Length::Fill)
+                    .on_press(SimulatorMessage::AddRegister),
+                )
+                .padding(5),
+        );
+
+        Scrollable::new(column).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_sender::{Operation, Request};
+    use crate::port_op::{serve_port_ops, OpMessage};
+    use crate::transport::{MemoryTransport, PortConfig};
+
+    fn slave() -> Slave {
+        Slave::new(1, HashMap::from([(0x10, 0x1234), (0x11, 0xABCD)]))
+    }
+
+    fn request(device_addr: u8, function: u8, addr: u16, value: u16) -> [u8; 8] {
+        RequestFrame { device_addr, function, addr, value }.to_bytes()
+    }
+
+    /// Response frame without its CRC, after checking the CRC
+    fn body(response: Vec<u8>) -> Vec<u8> {
+        let len = response.len();
+        let crc = checksum(&response[..len - 2]);
+        assert_eq!(response[len - 2..], [crc as u8, (crc >> 8) as u8]);
+        response[..len - 2].to_vec()
+    }
+
+    #[test]
+    fn reads_holding_and_input_registers() {
+        let mut slave = slave();
+
+        for func in [0x03, 0x04] {
+            let response = slave.respond(&request(1, func, 0x10, 2)).unwrap();
+            assert_eq!(body(response), [1, func, 4, 0x12, 0x34, 0xAB, 0xCD]);
+        }
+    }
+
+    #[test]
+    fn written_register_reads_back() {
+        let mut slave = slave();
+
+        let frame = request(1, 0x06, 0x20, 0x0102);
+        assert_eq!(slave.respond(&frame).unwrap(), frame);
+
+        let response = slave.respond(&request(1, 0x03, 0x20, 1)).unwrap();
+        assert_eq!(body(response), [1, 0x03, 2, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn other_device_addr_is_ignored() {
+        assert_eq!(slave().respond(&request(2, 0x03, 0x10, 1)), None);
+    }
+
+    #[test]
+    fn exceptions() {
+        let mut slave = slave();
+
+        let cases = [
+            // unsupported function
+            (request(1, 0x10, 0x10, 1), [1, 0x90, 0x01]),
+            // register past the end of the map
+            (request(1, 0x03, 0x11, 2), [1, 0x83, 0x02]),
+            // quantity out of range
+            (request(1, 0x04, 0x10, 0), [1, 0x84, 0x03]),
+            (request(1, 0x03, 0x10, MAX_READ_QUANTITY + 1), [1, 0x83, 0x03]),
+        ];
+        for (request, expected) in cases {
+            assert_eq!(body(slave.respond(&request).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn merge_keeps_written_values() {
+        let mut running = slave();
+        running.respond(&request(1, 0x06, 0x10, 7)).unwrap();
+
+        let table = Slave::new(
+            3,
+            HashMap::from([(0x10, 0x1234), (0x11, 0xABCD), (0x12, 5)]),
+        );
+        running.merge(table.clone(), None);
+        assert_eq!(running.device_addr, 3);
+        assert_eq!(running.register(0x10), Some(7));
+        assert_eq!(running.register(0x12), Some(5));
+
+        running.merge(table, Some(0x10));
+        assert_eq!(running.register(0x10), Some(0x1234));
+
+        running.merge(Slave::new(3, HashMap::from([(0x12, 5)])), None);
+        assert_eq!(running.register(0x10), None);
+    }
+
+    /// Answers every written frame with the reply of a shared slave
+    struct SlaveTransport {
+        slave: Arc<Mutex<Slave>>,
+        link: MemoryTransport,
+    }
+
+    impl Transport for SlaveTransport {
+        fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+            self.link.write_frame(frame)?;
+            if let Some(reply) = self.slave.lock().unwrap().respond(frame) {
+                self.link.push_reply(reply);
+            }
+            Ok(())
+        }
+
+        fn read_frame(&mut self) -> std::io::Result<Vec<u8>> {
+            self.link.read_frame()
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+            self.link.set_timeout(timeout)
+        }
+    }
+
+    #[test]
+    fn port_op_round_trip() {
+        let slave = Arc::new(Mutex::new(slave()));
+        let (port_tx, port_rx) = channel();
+        {
+            let slave = slave.clone();
+            std::thread::spawn(move || {
+                serve_port_ops(port_rx, |_| {
+                    Ok(Box::new(SlaveTransport {
+                        slave: slave.clone(),
+                        link: MemoryTransport::default(),
+                    }))
+                })
+            });
+        }
+
+        let port_conf = PortConfig { device_addr: 1, ..PortConfig::default() };
+        let send = |op: Operation| {
+            let (tx, rx) = channel();
+            port_tx.send(OpMessage::OneShot(port_conf.clone(), op, tx)).unwrap();
+            rx.recv().unwrap().unwrap()
+        };
+
+        let write = Operation::new(
+            "write".to_string(),
+            Request::WriteSingle(0x20, 258.0, 0x0102),
+        );
+        assert_eq!(send(write).value(), Some(258.0));
+        assert_eq!(slave.lock().unwrap().register(0x20), Some(0x0102));
+
+        let read = Operation::new("read".to_string(), Request::ReadSingle(0x20));
+        assert_eq!(send(read).value(), Some(258.0));
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::modbus::{CrcAlgorithm, CrcByteOrder};
+use crate::response_display::KeyedResponseView;
+use crate::transport::PortConfig;
+
+/// Port config the continuous values were polled with
+#[derive(Debug, Serialize)]
+struct PortSnapshot {
+    port_name: String,
+    baud: u32,
+    data_bits: u8,
+    parity: &'static str,
+    stop_bits: u8,
+    device_addr: u8,
+    nine_bit: bool,
+    t35_framing: bool,
+    auto_timeout: bool,
+    read_delay_ms: u128,
+    skip_crc: bool,
+    crc_algorithm: CrcAlgorithm,
+    crc_byte_order: CrcByteOrder,
+}
+
+impl From<&PortConfig> for PortSnapshot {
+    fn from(port_conf: &PortConfig) -> Self {
+        // destructured so a new port setting can't be left out
+        let PortConfig {
+            port_name,
+            baud,
+            stop_bits,
+            parity,
+            data_bits,
+            device_addr,
+            nine_bit,
+            t35_framing,
+            auto_timeout,
+            read_delay,
+            skip_crc,
+            crc,
+        } = port_conf;
+
+        Self {
+            port_name: port_name.clone(),
+            baud: *baud,
+            data_bits: match data_bits {
+                serialport::DataBits::Five => 5,
+                serialport::DataBits::Six => 6,
+                serialport::DataBits::Seven => 7,
+                serialport::DataBits::Eight => 8,
+            },
+            parity: match parity {
+                serialport::Parity::None => "none",
+                serialport::Parity::Odd => "odd",
+                serialport::Parity::Even => "even",
+            },
+            stop_bits: match stop_bits {
+                serialport::StopBits::One => 1,
+                serialport::StopBits::Two => 2,
+            },
+            device_addr: *device_addr,
+            nine_bit: *nine_bit,
+            t35_framing: *t35_framing,
+            auto_timeout: *auto_timeout,
+            read_delay_ms: read_delay.as_millis(),
+            skip_crc: *skip_crc,
+            crc_algorithm: crc.algorithm,
+            crc_byte_order: crc.byte_order,
+        }
+    }
+}
+
+/// State of one key of the continuous view
+#[derive(Debug, Serialize)]
+struct EntrySnapshot {
+    name: String,
+    /// Set for gateway devices
+    device_addr: Option<u8>,
+    value: Option<f64>,
+    /// Whether the response passed its CRC check, `None` if there was no
+    /// frame to check
+    crc_ok: Option<bool>,
+    /// The response or error as shown in the view
+    display: String,
+    error: Option<String>,
+    updated_unix_ms: u128,
+    count: u64,
+    failures: u64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+/// The continuous view at a point in time, written as JSON for scripts
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    taken_unix_ms: u128,
+    /// `None` if nothing was polled yet
+    port: Option<PortSnapshot>,
+    entries: Vec<EntrySnapshot>,
+}
+
+fn unix_ms(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis())
+}
+
+impl Snapshot {
+    /// Snapshot of `view`, whose values were polled with `port_conf`
+    pub fn new(
+        view: &KeyedResponseView,
+        port_conf: Option<&PortConfig>,
+    ) -> Self {
+        let port = port_conf.map(PortSnapshot::from);
+
+        let entries = view
+            .entries()
+            .map(|((device_addr, name), entry)| {
+                let (value, crc_ok, display, error) = match &entry.response {
+                    Ok(resp) => {
+                        (resp.value(), resp.crc_ok(), resp.to_string(), None)
+                    }
+                    Err(err) => {
+                        (None, None, err.to_string(), Some(err.to_string()))
+                    }
+                };
+
+                EntrySnapshot {
+                    name: name.clone(),
+                    device_addr: *device_addr,
+                    value,
+                    crc_ok,
+                    display,
+                    error,
+                    updated_unix_ms: unix_ms(entry.updated),
+                    count: entry.count,
+                    failures: entry.failures,
+                    min: entry.min,
+                    max: entry.max,
+                }
+            })
+            .collect();
+
+        Self { taken_unix_ms: unix_ms(SystemTime::now()), port, entries }
+    }
+
+    /// Write the snapshot as pretty printed JSON to `path`
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::CrcConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn every_port_setting_is_recorded() {
+        let port_conf = PortConfig {
+            port_name: "COM3".to_string(),
+            baud: 19200,
+            stop_bits: serialport::StopBits::Two,
+            parity: serialport::Parity::Even,
+            data_bits: serialport::DataBits::Seven,
+            device_addr: 5,
+            nine_bit: true,
+            t35_framing: true,
+            auto_timeout: true,
+            read_delay: Duration::from_millis(20),
+            skip_crc: true,
+            crc: CrcConfig {
+                algorithm: CrcAlgorithm::Ccitt,
+                byte_order: CrcByteOrder::HighFirst,
+            },
+        };
+        let snapshot =
+            Snapshot::new(&KeyedResponseView::default(), Some(&port_conf));
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(
+            json["port"],
+            serde_json::json!({
+                "port_name": "COM3",
+                "baud": 19200,
+                "data_bits": 7,
+                "parity": "even",
+                "stop_bits": 2,
+                "device_addr": 5,
+                "nine_bit": true,
+                "t35_framing": true,
+                "auto_timeout": true,
+                "read_delay_ms": 20,
+                "skip_crc": true,
+                "crc_algorithm": "Ccitt",
+                "crc_byte_order": "HighFirst",
+            })
+        );
+    }
+
+    #[test]
+    fn no_port_before_polling() {
+        let snapshot = Snapshot::new(&KeyedResponseView::default(), None);
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert!(json["port"].is_null());
+    }
+}
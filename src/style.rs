@@ -0,0 +1,53 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Text sizes shared by every view, before the UI scale is applied
+pub struct TextSize;
+
+impl TextSize {
+    /// Hints, previews and secondary lines
+    pub const SMALL: u16 = 14;
+    /// Decoded frames and details under a response
+    pub const BODY: u16 = 16;
+    /// Op names and titles
+    pub const HEADING: u16 = 20;
+    /// A popped out value, readable from across the room
+    pub const DISPLAY: u16 = 96;
+}
+
+/// Scale of the whole UI in percent, text and widgets alike
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UiScale(u16);
+
+pub const UI_SCALES: &[UiScale] = &[
+    UiScale(75),
+    UiScale(90),
+    UiScale(100),
+    UiScale(110),
+    UiScale(125),
+    UiScale(150),
+    UiScale(175),
+    UiScale(200),
+];
+
+impl UiScale {
+    /// Factor iced scales the UI by, a hand edited layout can't make the UI
+    /// unusably small or large
+    pub fn factor(self) -> f64 {
+        self.0.clamp(50, 300) as f64 / 100f64
+    }
+}
+
+impl Default for UiScale {
+    fn default() -> Self {
+        UiScale(100)
+    }
+}
+
+impl Display for UiScale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
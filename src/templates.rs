@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use crate::layout::LayoutFile;
+use crate::ops::{DataType, DeviceIdLevel, OpType, OpView};
+
+/// Named ops for a device or a common register, inserted into an op list in
+/// one go
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpTemplate {
+    pub name: String,
+    pub ops: Vec<OpView>,
+}
+
+fn op(name: &str, op_type: OpType, addr: &str) -> OpView {
+    OpView::new(
+        name.to_string(),
+        op_type,
+        addr.to_string(),
+        String::new(),
+        "val".to_string(),
+        "val".to_string(),
+    )
+}
+
+/// A signed register holding tenths of its unit
+fn tenths(name: &str, op_type: OpType, addr: &str, unit: &str) -> OpView {
+    let mut op = op(name, op_type, addr);
+    op.signed_bits = "16".to_string();
+    op.scale = "0.1".to_string();
+    op.precision = "1".to_string();
+    op.unit = unit.to_string();
+
+    op
+}
+
+/// Templates shipped with the tester, for common devices and registers
+fn builtin_templates() -> Vec<OpTemplate> {
+    let mut humidity = tenths("Humidity", OpType::ReadSingleRO, "2", "%RH");
+    humidity.signed_bits = String::new();
+
+    let mut energy = op("Energy", OpType::ReadSingle, "0");
+    energy.data_type = DataType::U64;
+    energy.scale = "0.001".to_string();
+    energy.precision = "3".to_string();
+    energy.unit = "kWh".to_string();
+
+    let mut device_id = op("Device ID", OpType::ReadDeviceId, "");
+    device_id.device_id_level = DeviceIdLevel::Basic;
+
+    vec![
+        OpTemplate {
+            name: "Temperature/Humidity Sensor (XY-MD02)".to_string(),
+            ops: vec![
+                tenths("Temperature", OpType::ReadSingleRO, "1", "°C"),
+                humidity,
+            ],
+        },
+        OpTemplate {
+            name: "Temperature Register (0.1 °C)".to_string(),
+            ops: vec![tenths("Temperature", OpType::ReadSingle, "0", "°C")],
+        },
+        OpTemplate {
+            name: "Energy Counter (U64 Wh)".to_string(),
+            ops: vec![energy],
+        },
+        OpTemplate {
+            name: "Relay Output".to_string(),
+            ops: vec![op("Relay", OpType::WriteCoil, "0")],
+        },
+        OpTemplate {
+            name: "Device Identification".to_string(),
+            ops: vec![device_id],
+        },
+    ]
+}
+
+/// The built-in templates and the user's, which are kept in a file next to
+/// the layout
+#[derive(Debug, Default)]
+pub struct TemplateLibrary {
+    builtin: Vec<OpTemplate>,
+    user: Vec<OpTemplate>,
+    file: LayoutFile,
+}
+
+impl TemplateLibrary {
+    /// Load the user's templates from `file`, there are none if it doesn't
+    /// exist yet. A file that doesn't load is backed up right away, as the
+    /// next save would overwrite it, the returned log says so
+    pub fn load(file: LayoutFile) -> (Self, Option<String>) {
+        let (user, log) = match file.load::<Vec<OpTemplate>>() {
+            Ok(user) => (user.unwrap_or_default(), None),
+            Err(e) => {
+                let log = match file.backup() {
+                    Ok(backup) => format!(
+                        "Failed to load templates from {}: {}. The file was \
+                        backed up to {}",
+                        file.path, e, backup
+                    ),
+                    Err(backup_err) => format!(
+                        "Failed to load templates from {}: {}. Backing the \
+                        file up failed too: {}",
+                        file.path, e, backup_err
+                    ),
+                };
+                (vec![], Some(log))
+            }
+        };
+
+        (Self { builtin: builtin_templates(), user, file }, log)
+    }
+
+    /// Names of every template, the built-in ones first
+    pub fn names(&self) -> Vec<String> {
+        self.builtin
+            .iter()
+            .chain(&self.user)
+            .map(|template| template.name.clone())
+            .collect()
+    }
+
+    /// Ops of the template called `name`, empty if there is none
+    pub fn ops(&self, name: &str) -> Vec<OpView> {
+        self.builtin
+            .iter()
+            .chain(&self.user)
+            .find(|template| template.name == name)
+            .map_or_else(Vec::new, |template| template.ops.clone())
+    }
+
+    /// Save `ops` as the user template `name`, replacing a user template of
+    /// the same name. Returns the file the templates were written to
+    pub fn save(&mut self, name: &str, ops: &[OpView]) -> Result<&str, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("enter a name for the template".to_string());
+        }
+        if ops.is_empty() {
+            return Err("the list has no operations".to_string());
+        }
+        if self.builtin.iter().any(|template| template.name == name) {
+            return Err(format!("\"{}\" is a built-in template", name));
+        }
+
+        let template = OpTemplate { name: name.to_string(), ops: ops.to_vec() };
+        match self.user.iter_mut().find(|template| template.name == name) {
+            Some(saved) => *saved = template,
+            None => self.user.push(template),
+        }
+
+        self.file.save(&self.user)?;
+        Ok(&self.file.path)
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind as IoErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::{ErrKind, Error};
+use crate::modbus::CrcConfig;
+
+/// Read timeout used unless an operation overrides it
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Longest frame `read_frame` collects, well above the 256 byte RTU limit so
+/// only a flooded bus is cut short
+pub const MAX_FRAME_LEN: usize = 4096;
+
+/// Silence that ends an RTU frame, 3.5 character times of 11 bits. Above
+/// 19200 baud the spec fixes it at 1.75 ms instead
+pub fn frame_gap(baud: u32) -> Duration {
+    if baud > 19200 || baud == 0 {
+        Duration::from_micros(1750)
+    } else {
+        Duration::from_secs_f64(3.5 * 11.0 / baud as f64)
+    }
+}
+
+/// Read timeout for a request of `request_len` bytes answered with
+/// `reply_len` bytes at `baud`: the time both take on the wire at 11 bits per
+/// character plus `DEFAULT_TIMEOUT` for the device to answer. Slow bauds get
+/// proportionally longer timeouts
+pub fn wire_timeout(
+    baud: u32,
+    request_len: usize,
+    reply_len: usize,
+) -> Duration {
+    if baud == 0 {
+        return DEFAULT_TIMEOUT;
+    }
+
+    let bits = 11 * (request_len + reply_len);
+    DEFAULT_TIMEOUT + Duration::from_secs_f64(bits as f64 / baud as f64)
+}
+
+/// Whether an IO error means the port itself is gone, e.g. a USB adapter was
+/// unplugged, rather than a single write or read failing
+pub fn is_port_gone(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        IoErrorKind::NotFound
+            | IoErrorKind::NotConnected
+            | IoErrorKind::BrokenPipe
+            | IoErrorKind::PermissionDenied
+    )
+}
+
+/// What the user can do about a port that failed to open
+fn open_error_hint(e: &serialport::Error) -> &'static str {
+    match e.kind() {
+        serialport::ErrorKind::NoDevice => {
+            "the port is missing or used by another program, check the cable \
+            and close other serial tools"
+        }
+        serialport::ErrorKind::InvalidInput => {
+            "the port doesn't support these settings, check the baud, parity \
+            and stop bits"
+        }
+        serialport::ErrorKind::Io(IoErrorKind::NotFound) => {
+            "the port was not found, check the cable and refresh the port list"
+        }
+        serialport::ErrorKind::Io(IoErrorKind::PermissionDenied) => {
+            "access was denied, the port may be open in another program or \
+            the user lacks permission (e.g. not in the dialout group)"
+        }
+        serialport::ErrorKind::Io(_) | serialport::ErrorKind::Unknown => {
+            "check the port is connected and not in use"
+        }
+    }
+}
+
+/// Settings of an open serial port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortConfig {
+    pub port_name: String,
+    pub baud: u32,
+    pub stop_bits: serialport::StopBits,
+    pub parity: serialport::Parity,
+    pub data_bits: serialport::DataBits,
+    pub device_addr: u8,
+    /// Send address bytes with the 9th bit set
+    pub nine_bit: bool,
+    /// End frames after a t3.5 gap instead of the read timeout
+    pub t35_framing: bool,
+    /// Derive the read timeout of each request from the baud and frame
+    /// lengths, see `wire_timeout`. Timeouts set on an op still apply
+    pub auto_timeout: bool,
+    /// Wait between writing a request and reading its response, for slow
+    /// devices
+    pub read_delay: Duration,
+    /// Decode responses that fail their CRC check
+    pub skip_crc: bool,
+    /// Checksum appended to requests and checked on responses
+    pub crc: CrcConfig,
+}
+
+impl Default for PortConfig {
+    fn default() -> Self {
+        Self {
+            port_name: String::new(),
+            baud: 0,
+            stop_bits: serialport::StopBits::One,
+            parity: serialport::Parity::None,
+            data_bits: serialport::DataBits::Eight,
+            device_addr: 0,
+            nine_bit: false,
+            t35_framing: false,
+            auto_timeout: false,
+            read_delay: Duration::ZERO,
+            skip_crc: false,
+            crc: CrcConfig::default(),
+        }
+    }
+}
+
+impl PortConfig {
+    /// Names of the settings that differ between `self` and `other`
+    pub fn differences(&self, other: &PortConfig) -> Vec<&'static str> {
+        [
+            (self.port_name != other.port_name, "port"),
+            (self.baud != other.baud, "baud"),
+            (
+                self.data_bits != other.data_bits
+                    || self.parity != other.parity
+                    || self.stop_bits != other.stop_bits,
+                "framing",
+            ),
+            (self.device_addr != other.device_addr, "device address"),
+            (self.nine_bit != other.nine_bit, "9-bit address"),
+            (self.t35_framing != other.t35_framing, "t3.5 framing"),
+            (self.auto_timeout != other.auto_timeout, "auto timeout"),
+            (self.read_delay != other.read_delay, "read delay"),
+            (self.skip_crc != other.skip_crc, "skip CRC"),
+            (self.crc != other.crc, "CRC"),
+        ]
+        .into_iter()
+        .filter_map(|(differs, name)| differs.then_some(name))
+        .collect()
+    }
+}
+
+/// Port, baud, framing and device address, e.g. `COM3 9600 8N1 @ 0x01`
+impl Display for PortConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let data_bits = match self.data_bits {
+            serialport::DataBits::Five => 5,
+            serialport::DataBits::Six => 6,
+            serialport::DataBits::Seven => 7,
+            serialport::DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            serialport::Parity::None => 'N',
+            serialport::Parity::Odd => 'O',
+            serialport::Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            serialport::StopBits::One => 1,
+            serialport::StopBits::Two => 2,
+        };
+
+        write!(
+            f,
+            "{} {} {}{}{} @ 0x{:02X}",
+            self.port_name,
+            self.baud,
+            data_bits,
+            parity,
+            stop_bits,
+            self.device_addr
+        )
+    }
+}
+
+impl PortConfig {
+    pub fn new(
+        port_name: String,
+        baud: u32,
+        stop_bits: serialport::StopBits,
+        parity: serialport::Parity,
+        device_addr: u8,
+    ) -> Self {
+        PortConfig {
+            port_name,
+            baud,
+            stop_bits,
+            parity,
+            data_bits: serialport::DataBits::Eight,
+            device_addr,
+            nine_bit: false,
+            t35_framing: false,
+            auto_timeout: false,
+            read_delay: Duration::ZERO,
+            skip_crc: false,
+            crc: CrcConfig::default(),
+        }
+    }
+}
+
+/// A link that carries whole Modbus frames between the tester and a device
+pub trait Transport {
+    /// Write a complete frame
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()>;
+
+    /// Read a complete frame, an empty frame means nothing arrived before the
+    /// transport timed out
+    fn read_frame(&mut self) -> std::io::Result<Vec<u8>>;
+
+    /// Change how long `read_frame` waits for a frame
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()>;
+}
+
+/// Parity setting that makes the parity bit sent with `byte` equal `bit`,
+/// which emulates mark (`true`) and space parity with the odd and even
+/// settings every platform supports
+fn parity_for_bit(byte: u8, bit: bool) -> serialport::Parity {
+    // even parity sets the bit when the data has an odd number of ones
+    if (byte.count_ones() % 2 == 1) == bit {
+        serialport::Parity::Even
+    } else {
+        serialport::Parity::Odd
+    }
+}
+
+/// Modbus RTU over a serial port, frames are delimited by read timeout or by
+/// the t3.5 gap if `frame_gap` is set
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+    /// Parity the port was opened with, restored after a 9-bit write
+    parity: serialport::Parity,
+    /// Send the address byte with the 9th bit set, see `write_nine_bit`
+    nine_bit: bool,
+    /// How long to wait for the first byte of a frame
+    timeout: Duration,
+    /// Silence after which a started frame is complete
+    frame_gap: Option<Duration>,
+}
+
+impl SerialTransport {
+    pub fn new(port: Box<dyn serialport::SerialPort>) -> Self {
+        let parity = port.parity().unwrap_or(serialport::Parity::None);
+        let timeout = port.timeout();
+        Self { port, parity, nine_bit: false, timeout, frame_gap: None }
+    }
+
+    pub fn open(port_conf: &PortConfig) -> Result<Self, Error> {
+        match serialport::new(port_conf.port_name.clone(), port_conf.baud)
+            .parity(port_conf.parity)
+            .data_bits(port_conf.data_bits)
+            .stop_bits(port_conf.stop_bits)
+            .timeout(DEFAULT_TIMEOUT)
+            .open()
+        {
+            Ok(port) => Ok(Self {
+                nine_bit: port_conf.nine_bit,
+                frame_gap: port_conf
+                    .t35_framing
+                    .then(|| frame_gap(port_conf.baud)),
+                ..Self::new(port)
+            }),
+            Err(e) => Err(Error::with_message(
+                ErrKind::FailedToOpenTargetPort,
+                format!(
+                    "Failed to open port \"{}\": {}, {}",
+                    port_conf.port_name,
+                    e.description,
+                    open_error_hint(&e)
+                ),
+            )),
+        }
+    }
+
+    /// Write a frame with 9-bit addressing, the address byte goes out with
+    /// the 9th (parity) bit set and every other byte with it cleared. The
+    /// parity is switched per byte, and each byte has to leave the port
+    /// before the next switch, so the frame is written one byte at a time.
+    ///
+    /// Limitations: the gaps this leaves between bytes depend on the driver,
+    /// USB adapters that buffer writes may stretch them past the RTU inter
+    /// character limit. Replies are read with the configured parity, which
+    /// has to be odd or even for the 9th bit of received bytes to be framed
+    /// correctly, and the OS must not drop bytes with parity errors
+    fn write_nine_bit(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        for (idx, &byte) in frame.iter().enumerate() {
+            self.port.set_parity(parity_for_bit(byte, idx == 0))?;
+            self.port.write_all(&[byte])?;
+            // waits until the byte was sent, not only queued
+            self.port.flush()?;
+        }
+
+        Ok(self.port.set_parity(self.parity)?)
+    }
+
+    /// Read until the port times out or `max_len` bytes arrived, so a device
+    /// that never stops talking can't grow the frame without bound. Bytes
+    /// read before an error are still returned, the caller checks CRC.
+    ///
+    /// With t3.5 framing the timeout only applies to the first byte, after
+    /// it the frame ends at the first gap of `frame_gap`. Port timeouts have
+    /// millisecond resolution, so the gap is rounded up to whole milliseconds
+    pub fn read_frame_max(
+        &mut self,
+        max_len: usize,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut frame = Vec::new();
+        let mut byte = [0u8];
+
+        while frame.len() < max_len {
+            match self.port.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => {
+                    frame.push(byte[0]);
+                    if frame.len() == 1 {
+                        if let Some(gap) = self.frame_gap {
+                            let millis = gap.as_micros().div_ceil(1000);
+                            self.port.set_timeout(Duration::from_millis(
+                                millis as u64,
+                            ))?;
+                        }
+                    }
+                }
+                Err(e) if e.kind() == IoErrorKind::TimedOut => break,
+                Err(e) if frame.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        if self.frame_gap.is_some() && !frame.is_empty() {
+            self.port.set_timeout(self.timeout)?;
+        }
+
+        Ok(frame)
+    }
+}
+
+impl Transport for SerialTransport {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        if self.nine_bit {
+            self.write_nine_bit(frame)
+        } else {
+            self.port.write_all(frame)
+        }
+    }
+
+    fn read_frame(&mut self) -> std::io::Result<Vec<u8>> {
+        self.read_frame_max(MAX_FRAME_LEN)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.timeout = timeout;
+        Ok(self.port.set_timeout(timeout)?)
+    }
+}
+
+/// Frames kept in memory instead of sent over a port, for tests.
+/// `read_frame` hands out the queued replies in order, and an empty frame
+/// once they run out. Clones share the replies and the log of written frames
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    replies: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    written: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MemoryTransport {
+    pub fn new(replies: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        Self {
+            replies: Arc::new(Mutex::new(replies.into_iter().collect())),
+            ..Self::default()
+        }
+    }
+
+    /// Queue a reply after the ones already queued
+    pub fn push_reply(&self, reply: Vec<u8>) {
+        self.replies.lock().unwrap().push_back(reply);
+    }
+
+    /// Every frame written so far, oldest first
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.written.lock().unwrap().clone()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.written.lock().unwrap().push(frame.to_vec());
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> std::io::Result<Vec<u8>> {
+        Ok(self.replies.lock().unwrap().pop_front().unwrap_or_default())
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> std::io::Result<()> {
+        Ok(())
+    }
+}
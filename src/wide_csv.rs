@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::response::Response;
+
+/// Key of a continuous op, the device address is set for gateway devices
+type Key = (Option<u8>, String);
+
+/// Log of the continuous responses with one row per poll cycle and one column
+/// per op, for graphing several values together. A cycle ends when an op
+/// responds a second time, values that failed to decode are left blank
+#[derive(Debug)]
+pub struct WideCsv {
+    writer: BufWriter<File>,
+    /// Learned from the first cycle, ops that only respond later aren't logged
+    columns: Option<Vec<Key>>,
+    /// Ops of the first cycle in the order they responded
+    first_cycle: Vec<Key>,
+    /// Values of the cycle in progress and when its first value arrived
+    row: HashMap<Key, String>,
+    row_started: Option<SystemTime>,
+}
+
+/// Quote a cell that holds a separator, a quote or a line break
+fn cell(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis())
+}
+
+impl WideCsv {
+    /// Start a new log at `path`, replacing any file already there
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            columns: None,
+            first_cycle: vec![],
+            row: HashMap::new(),
+            row_started: None,
+        })
+    }
+
+    /// Add a response to the cycle in progress, writing out the cycle first
+    /// if the op already responded in it
+    pub fn record(&mut self, response: &Response) -> Result<(), String> {
+        let key = (response.op.device_addr, response.op.name.clone());
+        if self.row.contains_key(&key) {
+            self.write_row()?;
+        }
+
+        // unrounded, the precision of the op only applies to the display
+        let value = match response.value() {
+            Some(value) if response.frame_error().is_none() => value.to_string(),
+            _ => String::new(),
+        };
+
+        if self.columns.is_none() {
+            self.first_cycle.push(key.clone());
+        }
+        self.row_started.get_or_insert_with(SystemTime::now);
+        self.row.insert(key, value);
+
+        Ok(())
+    }
+
+    /// Write the cycle in progress, and the header after the first cycle
+    fn write_row(&mut self) -> Result<(), String> {
+        let started = match self.row_started.take() {
+            Some(started) => started,
+            None => return Ok(()),
+        };
+
+        if self.columns.is_none() {
+            let header = std::iter::once("unix_ms".to_string())
+                .chain(self.first_cycle.iter().map(|(device_addr, name)| {
+                    match device_addr {
+                        Some(addr) => cell(&format!("{}@{}", name, addr)),
+                        None => cell(name),
+                    }
+                }))
+                .collect::<Vec<_>>()
+                .join(",");
+            writeln!(self.writer, "{}", header).map_err(|e| e.to_string())?;
+            self.columns = Some(std::mem::take(&mut self.first_cycle));
+        }
+
+        // unwrap because the columns were just set if they weren't before
+        let row = std::iter::once(unix_ms(started).to_string())
+            .chain(self.columns.as_ref().unwrap().iter().map(|key| {
+                self.row.get(key).map_or_else(String::new, |value| cell(value))
+            }))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.row.clear();
+
+        writeln!(self.writer, "{}", row).map_err(|e| e.to_string())?;
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+
+    /// Write the last, possibly partial, cycle
+    pub fn finish(mut self) -> Result<(), String> {
+        self.write_row()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::message_sender::{parse_eval, Operation, Request, READ_VARS};
+    use crate::modbus::checksum;
+
+    /// Response of device 1 to a single register read of `raw`
+    fn response(op: &Operation, raw: u16) -> Response {
+        let mut bytes = vec![1, 0x03, 2, (raw >> 8) as u8, raw as u8];
+        let crc = checksum(&bytes);
+        bytes.extend([crc as u8, (crc >> 8) as u8]);
+        Response::new(op.clone(), bytes)
+    }
+
+    #[test]
+    fn values_are_logged_unrounded() {
+        let path = std::env::temp_dir().join("modbus-tester-wide-csv.csv");
+        let op = Operation {
+            read_eval: Arc::new(parse_eval("val / 2", READ_VARS).unwrap()),
+            precision: Some(0),
+            ..Operation::new("op".to_string(), Request::ReadSingle(0))
+        };
+
+        let mut csv = WideCsv::create(path.to_str().unwrap()).unwrap();
+        csv.record(&response(&op, 5)).unwrap();
+        csv.record(&response(&op, 7)).unwrap();
+        csv.finish().unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let values = log
+            .lines()
+            .map(|line| line.split_once(',').unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["op", "2.5", "3.5"]);
+    }
+}